@@ -0,0 +1,185 @@
+/* C API for CA2 archives. Generated by cbindgen from capi/src/lib.rs, do not edit. */
+
+#ifndef CA2_H
+#define CA2_H
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * The kind of a stored array
+ */
+typedef enum Ca2Kind {
+  CA2_KIND_COVERING = 0,
+  CA2_KIND_SEQUENCE = 1,
+} Ca2Kind;
+
+/**
+ * A set of archives and the arrays stored in them
+ */
+typedef struct Ca2Archive Ca2Archive;
+
+/**
+ * Decodes the best array for a request and translates its rows
+ */
+typedef struct Ca2Reader Ca2Reader;
+
+/**
+ * The parameters and values of a request, with C strings for the names and values
+ */
+typedef struct Ca2Request Ca2Request;
+
+/**
+ * Metadata of a stored array
+ */
+typedef struct Ca2EntryInfo {
+  /**
+   * Number of rows
+   */
+  uint64_t n;
+  /**
+   * Strength
+   */
+  uint8_t t;
+  enum Ca2Kind kind;
+  /**
+   * Number of parameters (or events of a sequence covering array)
+   */
+  size_t k;
+} Ca2EntryInfo;
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Returns the message of the last error that happened in this thread, or NULL if there was none.
+ * The string stays valid until the next failing call in this thread.
+ */
+const char *ca2_last_error(void);
+
+/**
+ * Opens archives. `path` can be an archive, a directory that is searched recursively or a glob pattern.
+ * Only the indices of the archives are read.
+ */
+struct Ca2Archive *ca2_archive_open(const char *path);
+
+/**
+ * Frees archives opened with ca2_archive_open
+ */
+void ca2_archive_close(struct Ca2Archive *archive);
+
+/**
+ * Number of arrays stored in the archives
+ */
+size_t ca2_archive_len(const struct Ca2Archive *archive);
+
+/**
+ * Writes the metadata of array `index` to `info`. If `vs` is not NULL, the first `vs_len` numbers of values
+ * (sorted descending) are written to it. Returns 0, or -1 on failure.
+ */
+int ca2_archive_entry_info(const struct Ca2Archive *archive,
+                           size_t index,
+                           struct Ca2EntryInfo *info,
+                           uint16_t *vs,
+                           size_t vs_len);
+
+/**
+ * Finds the arrays that are compatible with a covering array of strength t for parameters with vs values.
+ * Writes up to `out_len` indices to `out`, smallest arrays first; ca2_reader_open_entry decodes them.
+ * Returns the number of compatible arrays (which may be larger than out_len), or -1 on failure.
+ */
+ptrdiff_t ca2_archive_find_compatible(const struct Ca2Archive *archive,
+                                      uint8_t t,
+                                      const uint16_t *vs,
+                                      size_t vs_len,
+                                      size_t *out,
+                                      size_t out_len);
+
+/**
+ * Parses an input model (ACTS, CTWedge or an event list) into a request for strength t
+ */
+struct Ca2Request *ca2_request_parse(const char *model, uint8_t t);
+
+/**
+ * Creates a request for a covering array of strength t for parameters with vs values.
+ * Parameter i is named p{i+1}, and its values are the numbers 0 to vs[i]-1.
+ */
+struct Ca2Request *ca2_request_new(uint8_t t, const uint16_t *vs, size_t vs_len);
+
+/**
+ * Frees a request. Readers keep their own copy of the request, so it may be freed before them.
+ */
+void ca2_request_free(struct Ca2Request *request);
+
+/**
+ * Number of parameters (or sequence positions) of a request, i.e. the length of a translated row
+ */
+size_t ca2_request_len(const struct Ca2Request *request);
+
+/**
+ * Name of parameter i, or NULL if it does not exist
+ */
+const char *ca2_request_parameter_name(const struct Ca2Request *request, size_t i);
+
+/**
+ * Number of values of parameter i, or 0 if it does not exist
+ */
+size_t ca2_request_parameter_size(const struct Ca2Request *request, size_t i);
+
+/**
+ * Value `value` of parameter i, or NULL if it does not exist
+ */
+const char *ca2_request_parameter_value(const struct Ca2Request *request, size_t i, size_t value);
+
+/**
+ * Selects the smallest array compatible with the request, just like dca, and prepares decoding it.
+ * Returns NULL if there is no compatible array (ca2_last_error() is set accordingly).
+ */
+struct Ca2Reader *ca2_reader_open(const struct Ca2Archive *archive,
+                                  const struct Ca2Request *request);
+
+/**
+ * Prepares decoding array `index` of the archives (e.g. one found by ca2_archive_find_compatible) for the request.
+ * Returns NULL if the index is out of range or the array is not compatible with the request.
+ */
+struct Ca2Reader *ca2_reader_open_entry(const struct Ca2Archive *archive,
+                                        const struct Ca2Request *request,
+                                        size_t index);
+
+/**
+ * Frees a reader opened with ca2_reader_open or ca2_reader_open_entry
+ */
+void ca2_reader_close(struct Ca2Reader *reader);
+
+/**
+ * Number of rows of the selected array
+ */
+uint64_t ca2_reader_rows(const struct Ca2Reader *reader);
+
+/**
+ * Reads the next row as value indices of the requested parameters: values[i] is the index of the value
+ * of parameter i (see ca2_request_parameter_value). Rows of sequence covering arrays contain event indices.
+ * Returns the number of values in the row, which is ca2_request_len() (at most values_len are written),
+ * 0 after the last row, or -1 on failure.
+ */
+ptrdiff_t ca2_reader_next_row(struct Ca2Reader *reader,
+                              uint16_t *values,
+                              size_t values_len);
+
+/**
+ * Like ca2_reader_next_row, but writes the values as strings.
+ * The strings belong to the reader and stay valid until it is closed.
+ */
+ptrdiff_t ca2_reader_next_row_strings(struct Ca2Reader *reader,
+                                      const char **values,
+                                      size_t values_len);
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* CA2_H */
@@ -0,0 +1,572 @@
+// C API for reading CA2 archives from other languages.
+//
+// All objects are opaque and created and destroyed by the functions below. Functions that return
+// pointers return NULL on failure, functions that return integers return a negative value on failure.
+// In both cases, ca2_last_error() describes what went wrong. Panics never unwind into C: they are caught
+// and reported like errors (unless the library is built with panic = "abort").
+// The header include/ca2.h is generated from this file with cbindgen, which copies the /// comments.
+//
+// Safety: all functions take pointers from C. Objects must have been created by this library and not
+// been freed yet, and arrays must be valid for the given number of elements. NULL is always rejected.
+#![allow(clippy::missing_safety_doc)]
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString, c_char, c_int};
+use std::fs::File;
+use std::io::Read;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::ptr;
+use ca2::{CASpec, CAKind, CompressedCA, RequestedCA, Value, Ca2Error, Ca2Result
+          , find_archives, extract_ca_specs, generate_reorder_map, open_ca, parse_model};
+
+// Number of rows that are decoded at once
+const DECODE_BATCH_ROWS : usize = 4096;
+
+thread_local! {
+    static LAST_ERROR : RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|e| *e.borrow_mut() = Some(message));
+}
+
+// Stores the error for ca2_last_error() and returns `failed`
+fn fail<T>(e: Ca2Error, failed: T) -> T {
+    set_last_error(e.to_string());
+    failed
+}
+
+// Runs the body of an exported function and returns `failed` if it fails or panics
+fn guard<T, F: FnOnce() -> Ca2Result<T>>(failed: T, f: F) -> T {
+    match catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(e)) => fail(e, failed),
+        Err(panic) => {
+            let message = panic.downcast_ref::<&str>().copied()
+                .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("unknown panic");
+            set_last_error(format!("Internal error: {}", message));
+            failed
+        }
+    }
+}
+
+fn invalid(message: &str) -> Ca2Error {
+    Ca2Error::InvalidArgument(String::from(message))
+}
+
+// Reads a string argument
+unsafe fn string_arg<'a>(s: *const c_char, name: &str) -> Ca2Result<&'a str> {
+    if s.is_null() {
+        return Err(invalid(&format!("{} must not be NULL", name)));
+    }
+    CStr::from_ptr(s).to_str().map_err(|_| invalid(&format!("{} is not valid UTF-8", name)))
+}
+
+// Reads an array argument, which may be NULL if it is empty
+unsafe fn slice_arg<'a, T>(p: *const T, len: usize, name: &str) -> Ca2Result<&'a [T]> {
+    match (p.is_null(), len) {
+        (_, 0) => Ok(&[]),
+        (true, _) => Err(invalid(&format!("{} must not be NULL", name))),
+        (false, _) => Ok(std::slice::from_raw_parts(p, len))
+    }
+}
+
+unsafe fn slice_arg_mut<'a, T>(p: *mut T, len: usize, name: &str) -> Ca2Result<&'a mut [T]> {
+    match (p.is_null(), len) {
+        (_, 0) => Ok(&mut []),
+        (true, _) => Err(invalid(&format!("{} must not be NULL", name))),
+        (false, _) => Ok(std::slice::from_raw_parts_mut(p, len))
+    }
+}
+
+/// The kind of a stored array
+#[repr(C)]
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Ca2Kind {
+    Covering = 0,
+    Sequence = 1
+}
+
+/// Metadata of a stored array
+#[repr(C)]
+pub struct Ca2EntryInfo {
+    /// Number of rows
+    pub n: u64,
+    /// Strength
+    pub t: u8,
+    pub kind: Ca2Kind,
+    /// Number of parameters (or events of a sequence covering array)
+    pub k: usize
+}
+
+/// A set of archives and the arrays stored in them
+pub struct Ca2Archive {
+    files: Vec<PathBuf>,
+    // The stored arrays as (file, offset, specification), in the order of the archives
+    entries: Vec<(usize, u64, CASpec)>
+}
+
+/// The parameters and values of a request, with C strings for the names and values
+#[derive(Clone)]
+pub struct Ca2Request {
+    requested_ca: RequestedCA,
+    names: Vec<CString>,
+    values: Vec<Vec<CString>>
+}
+
+/// Decodes the best array for a request and translates its rows
+pub struct Ca2Reader {
+    // A copy of the request, so that the reader does not depend on it
+    request: Ca2Request,
+    compressed_ca: CompressedCA<Box<dyn Read>>,
+    reorder_map: Vec<usize>,
+    // Rows of the current batch, and the next row in it
+    buf: Vec<Value>,
+    rows: usize,
+    row: usize,
+    // The current row, translated to value indices of the requested parameters
+    translated: Vec<usize>
+}
+
+/// Returns the message of the last error that happened in this thread, or NULL if there was none.
+/// The string stays valid until the next failing call in this thread.
+#[no_mangle]
+pub extern "C" fn ca2_last_error() -> *const c_char {
+    catch_unwind(|| LAST_ERROR.with(|e| e.borrow().as_ref().map_or(ptr::null(), |e| e.as_ptr())))
+        .unwrap_or(ptr::null())
+}
+
+/// Opens archives. `path` can be an archive, a directory that is searched recursively or a glob pattern.
+/// Only the indices of the archives are read.
+#[no_mangle]
+pub unsafe extern "C" fn ca2_archive_open(path: *const c_char) -> *mut Ca2Archive {
+    guard(ptr::null_mut(), || open_archive(path).map(|archive| Box::into_raw(Box::new(archive))))
+}
+
+unsafe fn open_archive(path: *const c_char) -> Ca2Result<Ca2Archive> {
+    let files = find_archives(&[PathBuf::from(string_arg(path, "path")?)])?;
+    let mut entries = Vec::new();
+    for (i, file) in files.iter().enumerate() {
+        entries.extend(extract_ca_specs(file)?
+            .into_iter()
+            .filter(|(_, ca_spec)| ca_spec.kind != CAKind::Unused)
+            .map(|(offset, ca_spec)| (i, offset, ca_spec)));
+    }
+    Ok(Ca2Archive { files, entries })
+}
+
+/// Frees archives opened with ca2_archive_open
+#[no_mangle]
+pub unsafe extern "C" fn ca2_archive_close(archive: *mut Ca2Archive) {
+    guard((), || {
+        if !archive.is_null() {
+            drop(Box::from_raw(archive));
+        }
+        Ok(())
+    })
+}
+
+/// Number of arrays stored in the archives
+#[no_mangle]
+pub unsafe extern "C" fn ca2_archive_len(archive: *const Ca2Archive) -> usize {
+    guard(0, || Ok(archive.as_ref().map_or(0, |archive| archive.entries.len())))
+}
+
+/// Writes the metadata of array `index` to `info`. If `vs` is not NULL, the first `vs_len` numbers of values
+/// (sorted descending) are written to it. Returns 0, or -1 on failure.
+#[no_mangle]
+pub unsafe extern "C" fn ca2_archive_entry_info(archive: *const Ca2Archive, index: usize, info: *mut Ca2EntryInfo
+                                                , vs: *mut u16, vs_len: usize) -> c_int {
+    guard(-1, || {
+        let archive = archive.as_ref().ok_or_else(|| invalid("archive must not be NULL"))?;
+        let (_, _, ca_spec) = archive.entries.get(index).ok_or_else(|| invalid("index is out of range"))?;
+        if let Some(info) = info.as_mut() {
+            *info = Ca2EntryInfo {
+                n: ca_spec.n,
+                t: ca_spec.t,
+                kind: if ca_spec.kind == CAKind::Sequence { Ca2Kind::Sequence } else { Ca2Kind::Covering },
+                k: ca_spec.vs.len()
+            };
+        }
+        if !vs.is_null() {
+            let vs = slice_arg_mut(vs, vs_len, "vs")?;
+            let len = vs.len().min(ca_spec.vs.len());
+            vs[..len].copy_from_slice(&ca_spec.vs[..len]);
+        }
+        Ok(0)
+    })
+}
+
+/// Finds the arrays that are compatible with a covering array of strength t for parameters with vs values.
+/// Writes up to `out_len` indices to `out`, smallest arrays first; ca2_reader_open_entry decodes them.
+/// Returns the number of compatible arrays (which may be larger than out_len), or -1 on failure.
+#[no_mangle]
+pub unsafe extern "C" fn ca2_archive_find_compatible(archive: *const Ca2Archive, t: u8, vs: *const u16, vs_len: usize
+                                                     , out: *mut usize, out_len: usize) -> isize {
+    guard(-1, || {
+        let archive = archive.as_ref().ok_or_else(|| invalid("archive must not be NULL"))?;
+        let requested_ca = RequestedCA::from_sizes(t, slice_arg(vs, vs_len, "vs")?)?;
+        let out = slice_arg_mut(out, out_len, "out")?;
+
+        let compatible = archive.compatible(&requested_ca.ca_spec);
+        for (o, i) in out.iter_mut().zip(compatible.iter()) {
+            *o = *i;
+        }
+        Ok(compatible.len() as isize)
+    })
+}
+
+impl Ca2Archive {
+    // Indices of the arrays compatible with `requested`, smallest arrays first (and in the order of the archives)
+    fn compatible(&self, requested: &CASpec) -> Vec<usize> {
+        let mut compatible : Vec<usize> = (0..self.entries.len())
+            .filter(|i| requested.is_compatible(&self.entries[*i].2))
+            .collect();
+        compatible.sort_by_key(|i| self.entries[*i].2.n);
+        compatible
+    }
+}
+
+/// Parses an input model (ACTS, CTWedge or an event list) into a request for strength t
+#[no_mangle]
+pub unsafe extern "C" fn ca2_request_parse(model: *const c_char, t: u8) -> *mut Ca2Request {
+    guard(ptr::null_mut(), || {
+        let request = new_request(parse_model(string_arg(model, "model")?, t)?)?;
+        Ok(Box::into_raw(Box::new(request)))
+    })
+}
+
+/// Creates a request for a covering array of strength t for parameters with vs values.
+/// Parameter i is named p{i+1}, and its values are the numbers 0 to vs[i]-1.
+#[no_mangle]
+pub unsafe extern "C" fn ca2_request_new(t: u8, vs: *const u16, vs_len: usize) -> *mut Ca2Request {
+    guard(ptr::null_mut(), || {
+        let request = new_request(RequestedCA::from_sizes(t, slice_arg(vs, vs_len, "vs")?)?)?;
+        Ok(Box::into_raw(Box::new(request)))
+    })
+}
+
+fn new_request(requested_ca: RequestedCA) -> Ca2Result<Ca2Request> {
+    let c_string = |s: &String| CString::new(s.as_str())
+        .map_err(|_| Ca2Error::Parse(format!("{} contains a NUL character", s)));
+    let names = requested_ca.parameter_names.iter().map(c_string).collect::<Ca2Result<_>>()?;
+    let values = requested_ca.parameter_values.iter()
+        .map(|values| values.iter().map(c_string).collect())
+        .collect::<Ca2Result<_>>()?;
+    Ok(Ca2Request { requested_ca, names, values })
+}
+
+/// Frees a request. Readers keep their own copy of the request, so it may be freed before them.
+#[no_mangle]
+pub unsafe extern "C" fn ca2_request_free(request: *mut Ca2Request) {
+    guard((), || {
+        if !request.is_null() {
+            drop(Box::from_raw(request));
+        }
+        Ok(())
+    })
+}
+
+/// Number of parameters (or sequence positions) of a request, i.e. the length of a translated row
+#[no_mangle]
+pub unsafe extern "C" fn ca2_request_len(request: *const Ca2Request) -> usize {
+    guard(0, || Ok(request.as_ref().map_or(0, |request| request.names.len())))
+}
+
+/// Name of parameter i, or NULL if it does not exist
+#[no_mangle]
+pub unsafe extern "C" fn ca2_request_parameter_name(request: *const Ca2Request, i: usize) -> *const c_char {
+    guard(ptr::null(), || Ok(request.as_ref()
+        .and_then(|request| request.names.get(i))
+        .map_or(ptr::null(), |name| name.as_ptr())))
+}
+
+/// Number of values of parameter i, or 0 if it does not exist
+#[no_mangle]
+pub unsafe extern "C" fn ca2_request_parameter_size(request: *const Ca2Request, i: usize) -> usize {
+    guard(0, || Ok(request.as_ref()
+        .and_then(|request| request.values.get(i))
+        .map_or(0, |values| values.len())))
+}
+
+/// Value `value` of parameter i, or NULL if it does not exist
+#[no_mangle]
+pub unsafe extern "C" fn ca2_request_parameter_value(request: *const Ca2Request, i: usize, value: usize) -> *const c_char {
+    guard(ptr::null(), || Ok(request.as_ref()
+        .and_then(|request| request.values.get(i))
+        .and_then(|values| values.get(value))
+        .map_or(ptr::null(), |value| value.as_ptr())))
+}
+
+/// Selects the smallest array compatible with the request, just like dca, and prepares decoding it.
+/// Returns NULL if there is no compatible array (ca2_last_error() is set accordingly).
+#[no_mangle]
+pub unsafe extern "C" fn ca2_reader_open(archive: *const Ca2Archive, request: *const Ca2Request) -> *mut Ca2Reader {
+    guard(ptr::null_mut(), || {
+        let archive = archive.as_ref().ok_or_else(|| invalid("archive must not be NULL"))?;
+        let request = request.as_ref().ok_or_else(|| invalid("request must not be NULL"))?;
+        let index = *archive.compatible(&request.requested_ca.ca_spec).first()
+            .ok_or_else(|| invalid("No compatible CA found in archives"))?;
+        open_reader(archive, request, index).map(|reader| Box::into_raw(Box::new(reader)))
+    })
+}
+
+/// Prepares decoding array `index` of the archives (e.g. one found by ca2_archive_find_compatible) for the request.
+/// Returns NULL if the index is out of range or the array is not compatible with the request.
+#[no_mangle]
+pub unsafe extern "C" fn ca2_reader_open_entry(archive: *const Ca2Archive, request: *const Ca2Request
+                                               , index: usize) -> *mut Ca2Reader {
+    guard(ptr::null_mut(), || {
+        let archive = archive.as_ref().ok_or_else(|| invalid("archive must not be NULL"))?;
+        let request = request.as_ref().ok_or_else(|| invalid("request must not be NULL"))?;
+        let (_, _, ca_spec) = archive.entries.get(index).ok_or_else(|| invalid("index is out of range"))?;
+        if !request.requested_ca.ca_spec.is_compatible(ca_spec) {
+            return Err(invalid("The array is not compatible with the request"));
+        }
+        open_reader(archive, request, index).map(|reader| Box::into_raw(Box::new(reader)))
+    })
+}
+
+fn open_reader(archive: &Ca2Archive, request: &Ca2Request, index: usize) -> Ca2Result<Ca2Reader> {
+    let (file, offset, ca_spec) = &archive.entries[index];
+    let reorder_map = generate_reorder_map(&request.requested_ca.parameter_sizes, ca_spec)?;
+
+    let compressed_ca = open_ca(File::open(&archive.files[*file])?, *offset, ca_spec)?;
+    let buf = vec![0; DECODE_BATCH_ROWS * compressed_ca.row_len().max(1)];
+
+    Ok(Ca2Reader {
+        request: request.clone(),
+        compressed_ca,
+        reorder_map,
+        buf,
+        rows: 0,
+        row: 0,
+        translated: Vec::with_capacity(request.requested_ca.parameter_sizes.len())
+    })
+}
+
+/// Frees a reader opened with ca2_reader_open or ca2_reader_open_entry
+#[no_mangle]
+pub unsafe extern "C" fn ca2_reader_close(reader: *mut Ca2Reader) {
+    guard((), || {
+        if !reader.is_null() {
+            drop(Box::from_raw(reader));
+        }
+        Ok(())
+    })
+}
+
+/// Number of rows of the selected array
+#[no_mangle]
+pub unsafe extern "C" fn ca2_reader_rows(reader: *const Ca2Reader) -> u64 {
+    guard(0, || Ok(reader.as_ref().map_or(0, |reader| {
+        reader.compressed_ca.rows_remaining() + (reader.rows - reader.row) as u64
+    })))
+}
+
+impl Ca2Reader {
+    // Decodes and translates the next row. Returns false after the last row.
+    fn advance(&mut self) -> Ca2Result<bool> {
+        if self.row == self.rows {
+            self.rows = self.compressed_ca.read_rows(&mut self.buf)?;
+            self.row = 0;
+            if self.rows == 0 {
+                return Ok(false);
+            }
+        }
+        let row_len = self.compressed_ca.row_len();
+        let row = &self.buf[self.row * row_len..(self.row + 1) * row_len];
+        self.row += 1;
+
+        self.translated.clear();
+        self.translated.extend(self.request.requested_ca.translate_indices(row, &self.reorder_map).map(|(_, v)| v));
+        Ok(true)
+    }
+}
+
+/// Reads the next row as value indices of the requested parameters: values[i] is the index of the value
+/// of parameter i (see ca2_request_parameter_value). Rows of sequence covering arrays contain event indices.
+/// Returns the number of values in the row, which is ca2_request_len() (at most values_len are written),
+/// 0 after the last row, or -1 on failure.
+#[no_mangle]
+pub unsafe extern "C" fn ca2_reader_next_row(reader: *mut Ca2Reader, values: *mut u16, values_len: usize) -> isize {
+    guard(-1, || {
+        let reader = reader.as_mut().ok_or_else(|| invalid("reader must not be NULL"))?;
+        let values = slice_arg_mut(values, values_len, "values")?;
+        if !reader.advance()? {
+            return Ok(0);
+        }
+        for (o, v) in values.iter_mut().zip(reader.translated.iter()) {
+            *o = *v as u16;
+        }
+        Ok(reader.translated.len() as isize)
+    })
+}
+
+/// Like ca2_reader_next_row, but writes the values as strings.
+/// The strings belong to the reader and stay valid until it is closed.
+#[no_mangle]
+pub unsafe extern "C" fn ca2_reader_next_row_strings(reader: *mut Ca2Reader, values: *mut *const c_char
+                                                     , values_len: usize) -> isize {
+    guard(-1, || {
+        let reader = reader.as_mut().ok_or_else(|| invalid("reader must not be NULL"))?;
+        let values = slice_arg_mut(values, values_len, "values")?;
+        if !reader.advance()? {
+            return Ok(0);
+        }
+        for (i, (o, v)) in values.iter_mut().zip(reader.translated.iter()).enumerate() {
+            *o = reader.request.values[i][*v].as_ptr();
+        }
+        Ok(reader.translated.len() as isize)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A single CA(6; 2, 3^1 2^2)
+    const FIXTURE : &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/cas.ca2");
+    const MODEL : &str = "[System]\nName: fixture\n\n[Parameter]\np1 (enum): a,b,c\np2 (boolean): true,false\np3 (enum): x,y\n";
+
+    fn last_error() -> String {
+        let e = ca2_last_error();
+        assert!(!e.is_null());
+        unsafe { CStr::from_ptr(e) }.to_str().unwrap().to_string()
+    }
+
+    fn c_string(s: &str) -> CString {
+        CString::new(s).unwrap()
+    }
+
+    // The rows of the fixture as stored, decoded with the library
+    fn stored_rows() -> Vec<Vec<Value>> {
+        let (offset, ca_spec) = extract_ca_specs(std::path::Path::new(FIXTURE)).unwrap().remove(0);
+        open_ca(File::open(FIXTURE).unwrap(), offset, &ca_spec).unwrap().collect::<Ca2Result<_>>().unwrap()
+    }
+
+    #[test]
+    fn archives_are_opened_and_searched() {
+        unsafe {
+            let archive = ca2_archive_open(c_string(FIXTURE).as_ptr());
+            assert!(!archive.is_null());
+            assert_eq!(ca2_archive_len(archive), 1);
+
+            let mut info = Ca2EntryInfo { n: 0, t: 0, kind: Ca2Kind::Sequence, k: 0 };
+            let mut vs = [0u16; 4];
+            assert_eq!(ca2_archive_entry_info(archive, 0, &mut info, vs.as_mut_ptr(), vs.len()), 0);
+            assert_eq!((info.n, info.t, info.k), (6, 2, 3));
+            assert!(info.kind == Ca2Kind::Covering);
+            assert_eq!(vs, [3, 2, 2, 0]);
+
+            let mut out = [usize::MAX; 2];
+            assert_eq!(ca2_archive_find_compatible(archive, 2, [2u16, 3].as_ptr(), 2, out.as_mut_ptr(), out.len()), 1);
+            assert_eq!(out, [0, usize::MAX]);
+            // Counting works without an output array
+            assert_eq!(ca2_archive_find_compatible(archive, 1, [2u16].as_ptr(), 1, ptr::null_mut(), 0), 1);
+            assert_eq!(ca2_archive_find_compatible(archive, 3, [2u16, 2, 2].as_ptr(), 3, out.as_mut_ptr(), out.len()), 0);
+            assert_eq!(ca2_archive_find_compatible(archive, 2, [3u16, 3].as_ptr(), 2, out.as_mut_ptr(), out.len()), 0);
+            ca2_archive_close(archive);
+        }
+    }
+
+    #[test]
+    fn readers_return_every_row() {
+        let stored = stored_rows();
+        unsafe {
+            let archive = ca2_archive_open(c_string(FIXTURE).as_ptr());
+            let request = ca2_request_parse(c_string(MODEL).as_ptr(), 2);
+            assert!(!request.is_null());
+            assert_eq!(ca2_request_len(request), 3);
+            assert_eq!(CStr::from_ptr(ca2_request_parameter_name(request, 1)).to_str().unwrap(), "p2");
+            assert_eq!(ca2_request_parameter_size(request, 0), 3);
+            assert_eq!(CStr::from_ptr(ca2_request_parameter_value(request, 2, 1)).to_str().unwrap(), "y");
+
+            // The parameters have as many values as the stored columns, so the rows are the stored ones
+            let reader = ca2_reader_open(archive, request);
+            assert!(!reader.is_null());
+            assert_eq!(ca2_reader_rows(reader), 6);
+            let mut rows = Vec::new();
+            let mut row = [0u16; 3];
+            while ca2_reader_next_row(reader, row.as_mut_ptr(), row.len()) == 3 {
+                rows.push(row.to_vec());
+            }
+            assert_eq!(rows, stored);
+            assert_eq!(ca2_reader_next_row(reader, row.as_mut_ptr(), row.len()), 0);
+            assert_eq!(ca2_reader_rows(reader), 0);
+            ca2_reader_close(reader);
+
+            // Readers keep a copy of the request
+            let reader = ca2_reader_open_entry(archive, request, 0);
+            ca2_request_free(request);
+            let values = [["a", "b", "c"], ["true", "false", ""], ["x", "y", ""]];
+            let mut strings = [ptr::null(); 3];
+            for stored_row in stored.iter() {
+                assert_eq!(ca2_reader_next_row_strings(reader, strings.as_mut_ptr(), strings.len()), 3);
+                let row : Vec<&str> = strings.iter().map(|s| CStr::from_ptr(*s).to_str().unwrap()).collect();
+                let expected : Vec<&str> = stored_row.iter().enumerate().map(|(i, v)| values[i][*v as usize]).collect();
+                assert_eq!(row, expected);
+            }
+            assert_eq!(ca2_reader_next_row_strings(reader, strings.as_mut_ptr(), strings.len()), 0);
+            ca2_reader_close(reader);
+            ca2_archive_close(archive);
+        }
+    }
+
+    #[test]
+    fn errors_are_reported() {
+        unsafe {
+            assert!(ca2_archive_open(ptr::null()).is_null());
+            assert_eq!(last_error(), "Invalid argument: path must not be NULL");
+            assert!(ca2_archive_open(c_string("/nonexistent/archive.ca2").as_ptr()).is_null());
+            assert!(!last_error().is_empty());
+
+            let archive = ca2_archive_open(c_string(FIXTURE).as_ptr());
+            assert_eq!(ca2_archive_entry_info(archive, 1, ptr::null_mut(), ptr::null_mut(), 0), -1);
+            assert_eq!(last_error(), "Invalid argument: index is out of range");
+            assert_eq!(ca2_archive_entry_info(ptr::null(), 0, ptr::null_mut(), ptr::null_mut(), 0), -1);
+            assert_eq!(last_error(), "Invalid argument: archive must not be NULL");
+            assert_eq!(ca2_archive_find_compatible(archive, 2, ptr::null(), 2, ptr::null_mut(), 0), -1);
+            assert_eq!(last_error(), "Invalid argument: vs must not be NULL");
+            assert_eq!(ca2_archive_len(ptr::null()), 0);
+
+            let request = ca2_request_new(2, [3u16, 3].as_ptr(), 2);
+            assert!(ca2_reader_open(archive, request).is_null());
+            assert_eq!(last_error(), "Invalid argument: No compatible CA found in archives");
+            assert!(ca2_reader_open_entry(archive, request, 5).is_null());
+            assert_eq!(last_error(), "Invalid argument: index is out of range");
+            assert!(ca2_reader_open_entry(archive, request, 0).is_null());
+            assert_eq!(last_error(), "Invalid argument: The array is not compatible with the request");
+            assert!(ca2_reader_open(archive, ptr::null()).is_null());
+            assert_eq!(last_error(), "Invalid argument: request must not be NULL");
+            assert!(ca2_request_parameter_name(request, 2).is_null());
+            assert!(ca2_request_parameter_value(request, 0, 3).is_null());
+            assert_eq!(ca2_request_parameter_size(request, 2), 0);
+            ca2_request_free(request);
+
+            assert!(ca2_request_parse(c_string("not a model").as_ptr(), 2).is_null());
+            assert!(!last_error().is_empty());
+            assert!(ca2_request_new(2, [0u16].as_ptr(), 1).is_null());
+            assert_eq!(last_error(), "Invalid argument: Every parameter needs at least one value");
+
+            assert_eq!(ca2_reader_next_row(ptr::null_mut(), ptr::null_mut(), 0), -1);
+            assert_eq!(last_error(), "Invalid argument: reader must not be NULL");
+            assert_eq!(ca2_reader_next_row_strings(ptr::null_mut(), ptr::null_mut(), 0), -1);
+            let request = ca2_request_new(2, [2u16, 2].as_ptr(), 2);
+            let reader = ca2_reader_open(archive, request);
+            assert_eq!(ca2_reader_next_row(reader, ptr::null_mut(), 2), -1);
+            assert_eq!(last_error(), "Invalid argument: values must not be NULL");
+            ca2_reader_close(reader);
+            ca2_request_free(request);
+            ca2_archive_close(archive);
+
+            // Freeing NULL does nothing
+            ca2_archive_close(ptr::null_mut());
+            ca2_request_free(ptr::null_mut());
+            ca2_reader_close(ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,651 @@
+use std::fs::File;
+use std::io::{Write, Read, BufReader, BufRead, BufWriter, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use bzip2::bufread::BzDecoder;
+use crate::{CASpec, CAKind, CA2Version, CompressedCA, MAGIC_BYTES_CA2_PRE, MAGIC_BYTES_CA2, Ca2Error, Ca2Result
+            , generate_bit_sizes};
+
+// The index of a CA2 archive: The position of its metadata block
+// and the (offset, CA specification) pairs it contains
+pub struct ArchiveIndex {
+    // Position of MAGIC_BYTES_CA2_PRE, i.e. the end of the compressed CA data
+    pub metadata_offset: u64,
+    pub entries: Vec<(u64, CASpec)>,
+    // Offsets of data left behind by replaced or removed entries
+    pub unused: Vec<u64>
+}
+
+impl ArchiveIndex {
+    // The number of bytes from `offset` to the next stored data (or the metadata). Archives store
+    // their CAs back to back, so for an entry this is the size of its compressed data.
+    pub fn data_size(&self, offset: u64) -> u64 {
+        self.entries.iter()
+            .map(|(o, _)| *o)
+            .chain(self.unused.iter().copied())
+            .filter(|o| *o > offset)
+            .min()
+            .unwrap_or(self.metadata_offset)
+            .saturating_sub(offset)
+    }
+}
+
+// Upper limit for the size of an archive index. Each entry only takes a few bytes per parameter,
+// so real archives stay far below this, and corrupted ones cannot make us allocate arbitrary amounts.
+pub const MAX_INDEX_SIZE : u64 = 64 * 1024 * 1024;
+
+// Environment variable listing default archive locations, separated like PATH
+pub const ARCHIVE_PATH_ENV_VAR : &str = "CA2_PATH";
+
+// The existing default archive locations from CA2_PATH
+pub fn default_archive_locations() -> Vec<PathBuf> {
+    std::env::var_os(ARCHIVE_PATH_ENV_VAR)
+        .map(|paths| std::env::split_paths(&paths).filter(|p| p.exists()).collect())
+        .unwrap_or_default()
+}
+
+// Expands archive locations into archive files.
+// Files are used as they are, glob patterns are expanded, and directories are searched
+// recursively for .ca2 files and self-extracting executables that contain an archive index.
+pub fn find_archives(locations: &[PathBuf]) -> Ca2Result<Vec<PathBuf>> {
+    let mut out = Vec::new();
+    for location in locations.iter() {
+        if location.is_file() {
+            out.push(location.clone());
+        } else if location.is_dir() {
+            search_directory(location, &mut out)?;
+        } else {
+            // Maybe a glob pattern that was not expanded by the shell
+            let matches : Vec<PathBuf> = location.to_str()
+                .and_then(|pattern| glob::glob(pattern).ok())
+                .map(|paths| paths.filter_map(Result::ok).collect())
+                .unwrap_or_default();
+            if matches.is_empty() {
+                return Err(Ca2Error::Io(std::io::Error::new(
+                    std::io::ErrorKind::NotFound,
+                    format!("CA archive {} does not exist", location.display())
+                )));
+            }
+            for path in matches {
+                if path.is_dir() {
+                    search_directory(&path, &mut out)?;
+                } else {
+                    out.push(path);
+                }
+            }
+        }
+    }
+    Ok(out)
+}
+
+// Recursively collects archives in a directory. Symbolic links to directories are not followed.
+fn search_directory(dir: &Path, out: &mut Vec<PathBuf>) -> Ca2Result<()> {
+    let mut dir_entries = std::fs::read_dir(dir)?.collect::<std::io::Result<Vec<_>>>()?;
+    dir_entries.sort_by_key(|e| e.file_name());
+    for dir_entry in dir_entries {
+        let path = dir_entry.path();
+        let file_type = dir_entry.file_type()?;
+        if file_type.is_dir() {
+            search_directory(&path, out)?;
+        } else if path.is_file() && (
+            path.extension().is_some_and(|ext| ext == "ca2") ||
+                (is_executable(&path) && contains_index(&path))
+        ) {
+            out.push(path);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    path.metadata().is_ok_and(|m| m.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("exe"))
+}
+
+// Whether a file contains a valid archive index, e.g. a self-extracting archive
+pub fn contains_index(path: &Path) -> bool {
+    File::open(path).is_ok_and(|mut f| read_index(&mut f).is_ok())
+}
+
+// Extract all the CA specifications in a file
+pub fn extract_ca_specs(input_file: &Path) -> Ca2Result<Vec<(u64, CASpec)>> {
+    read_archive_index(input_file).map(|index| index.entries)
+}
+
+// Reads the index of an archive file. Format errors name the archive. An archive left behind by an
+// interrupted edit is only restored by the next edit (or `recover_archive`), so its errors point that out.
+pub fn read_archive_index(archive: &Path) -> Ca2Result<ArchiveIndex> {
+    let mut f = File::open(archive)?;
+    read_index(&mut f).map_err(|e| {
+        if journal_path(archive).is_file() {
+            return Ca2Error::Format(format!(
+                "{}: {} (an edit of the archive was interrupted, `ca2 pack --recover -o {}` undoes it)",
+                archive.display(), e, archive.display()
+            ));
+        }
+        match e {
+            Ca2Error::Format(msg) => Ca2Error::Format(format!("{}: {}", archive.display(), msg)),
+            e => e
+        }
+    })
+}
+
+// Finds the smallest CA compatible with the request by reading the metadata of all archives.
+// Returns the archive, the offset of the CA and its specification.
+pub fn find_best_ca(archive_files: &[PathBuf], requested: &CASpec) -> Ca2Result<Option<(PathBuf, u64, CASpec)>> {
+    let mut best : Option<(&PathBuf, u64, CASpec)> = None;
+    for archive_file in archive_files.iter() {
+        for (offset, ca_spec) in extract_ca_specs(archive_file)? {
+            if requested.is_compatible(&ca_spec) && best.as_ref().is_none_or(|best| ca_spec.n < best.2.n) {
+                best = Some((archive_file, offset, ca_spec));
+            }
+        }
+    }
+    Ok(best.map(|(archive_file, offset, ca_spec)| (archive_file.clone(), offset, ca_spec)))
+}
+
+// Reads the index of an archive
+pub fn read_index<R: Read+Seek>(f: &mut R) -> Ca2Result<ArchiveIndex> {
+    let mut entries = Vec::new();
+
+    // Get the file size, we don't want to search before this
+    // It's slightly incorrect to use i64 here, it's supposed to be u64.
+    let f_size : i64 = f.seek(SeekFrom::End(0))?.try_into()
+        .map_err(|_| Ca2Error::Format(String::from("Archive too large")))?;
+    // Note that we are now at the *end* of the file.
+
+    let mut reader = BufReader::new(f);
+    let metadata_start = find_metadata(&mut reader, f_size)?;
+
+    // Read all CA specs into a buffer
+    let mut ca_metadata = Vec::new();
+    (&mut reader).take(MAX_INDEX_SIZE + 1).read_to_end(&mut ca_metadata)?;
+    if ca_metadata.len() as u64 > MAX_INDEX_SIZE {
+        return Err(Ca2Error::Format(format!("Index larger than {} bytes", MAX_INDEX_SIZE)));
+    }
+    let metadata_offset = metadata_start - (MAGIC_BYTES_CA2.len() as u64 + 1);
+
+    // Parse CA specs, one by one. They are always preceded by a u64 offset.
+    let mut unused = Vec::new();
+    let mut buf_offset = 0;
+    while buf_offset < ca_metadata.len() {
+        let cca_offset = ca_metadata.get(buf_offset..buf_offset+8)
+            .map(|o| u64::from_be_bytes(o.try_into().unwrap()))
+            .ok_or_else(|| Ca2Error::Format(String::from("Truncated CA offset")))?;
+        let (ca_spec, ca_spec_len) = CASpec::unserialize(&ca_metadata[buf_offset+8..])?;
+        // Jump over terminator
+        buf_offset += ca_spec_len + (u64::BITS as usize/8);
+        validate_entry(cca_offset, &ca_spec, metadata_offset)?;
+        if ca_spec.kind == CAKind::Unused {
+            unused.push(cca_offset);
+        } else {
+            entries.push((cca_offset, ca_spec));
+        }
+    }
+
+    Ok(ArchiveIndex {
+        metadata_offset,
+        entries,
+        unused
+    })
+}
+
+// Checks an index entry against the archive it was read from.
+// The data of every entry has to lie before the index at `data_end`. Basic data has a fixed size,
+// so its number of rows can be checked as well. For compressed data, this is left to the decoder,
+// which fails once the stream ends before all rows have been read.
+fn validate_entry(offset: u64, ca_spec: &CASpec, data_end: u64) -> Ca2Result<()> {
+    if ca_spec.kind == CAKind::Unused {
+        return match offset <= data_end {
+            true => Ok(()),
+            false => Err(Ca2Error::Format(format!("Unused space at offset {} lies outside of the archive", offset)))
+        };
+    }
+    if offset >= data_end {
+        return Err(Ca2Error::Format(format!("CA data at offset {} lies outside of the archive", offset)));
+    }
+    if ca_spec.n > 0 && ca_spec.vs.is_empty() {
+        return Err(Ca2Error::Format(format!("CA at offset {} has rows, but no parameters", offset)));
+    }
+    if ca_spec.kind == CAKind::Sequence && ca_spec.vs.iter().any(|v| *v as usize != ca_spec.vs.len()) {
+        return Err(Ca2Error::Format(format!("Sequence CA at offset {} has inconsistent numbers of events", offset)));
+    }
+    if ca_spec.version == CA2Version::Basic
+        && ca_spec.basic_data_length().is_none_or(|len| len > data_end - offset) {
+        return Err(Ca2Error::Format(format!("CA at offset {} has more rows than the archive contains", offset)));
+    }
+    Ok(())
+}
+
+// Writes the metadata block of an archive, i.e. the magic bytes followed by all entries
+pub fn write_index<W: Write>(writer: &mut W, entries: &[(u64, CASpec)]) -> Ca2Result<()> {
+    writer.write_all(&[MAGIC_BYTES_CA2_PRE])?;
+    writer.write_all(MAGIC_BYTES_CA2)?;
+
+    for (offset, ca_spec) in entries.iter() {
+        // First the offset...
+        writer.write_all(&offset.to_be_bytes())?;
+        // Then the CA specification
+        writer.write_all(&ca_spec.serialize())?;
+    }
+    Ok(())
+}
+
+// Attempts to find the metadata block in an archive by searching for the magic bytes.
+// This search is performed from the end of the file.
+pub fn find_metadata<R: Read+Seek>(reader: &mut BufReader<R>, file_size: i64) -> Ca2Result<u64> {
+    // We search backwards for the last occurrence of the magic bytes, one BufReader buffer at a time
+    let magic_len = MAGIC_BYTES_CA2.len() + 1;
+    let window = reader.capacity().max(magic_len) as u64;
+    let file_size = file_size as u64;
+    let mut buf = Vec::new();
+    let mut end = file_size;
+    while end > 0 {
+        let start = end.saturating_sub(window);
+        // The magic bytes might start in this window and end in the following one
+        let read_end = (end + magic_len as u64 - 1).min(file_size);
+        reader.seek(SeekFrom::Start(start))?;
+        buf.resize((read_end - start) as usize, 0);
+        reader.read_exact(&mut buf)?;
+
+        let found = buf.windows(magic_len)
+            .rposition(|w| w[0] == MAGIC_BYTES_CA2_PRE && w[1..] == MAGIC_BYTES_CA2[..]);
+        if let Some(i) = found {
+            // Position the reader right after the magic bytes
+            let metadata_start = start + (i + magic_len) as u64;
+            reader.seek(SeekFrom::Start(metadata_start))?;
+            return Ok(metadata_start);
+        }
+        end = start;
+    }
+    // Reached the beginning of the file, we fail
+    Err(Ca2Error::Format(String::from("No CA metadata found.")))
+}
+
+// Maps an archive into memory, so that decoding does not need to copy its contents.
+// The archive must not be modified while it is mapped.
+#[cfg(feature = "mmap")]
+pub fn map_archive(path: &Path) -> Ca2Result<std::io::Cursor<memmap2::Mmap>> {
+    let f = File::open(path)?;
+    // SAFETY: The map is read-only, and archives are not modified while they are decoded
+    let mmap = unsafe { memmap2::Mmap::map(&f)? };
+    Ok(std::io::Cursor::new(mmap))
+}
+
+// Wraps the reader for the raw data of a CA in the matching decoder
+pub fn setup_decoder<R: 'static + Read>(ca_spec: &CASpec, reader: BufReader<R>) -> Box<dyn Read> {
+    match ca_spec.version {
+        CA2Version::Basic => Box::new(reader),
+        CA2Version::Bzip2 | CA2Version::Extended => Box::new(BzDecoder::new(reader))
+    }
+}
+
+// Prepares decoding the CA stored at `offset` of an archive. Rows of SCAs are checked to be permutations.
+pub fn open_ca<R: 'static + Read + Seek>(archive: R, offset: u64, ca_spec: &CASpec) -> Ca2Result<CompressedCA<Box<dyn Read>>> {
+    let mut reader = BufReader::new(archive);
+    reader.seek(SeekFrom::Start(offset))?;
+    let decoder = setup_decoder(ca_spec, reader);
+    let mut compressed_ca = CompressedCA::new(decoder, generate_bit_sizes(&ca_spec.vs), ca_spec.n, ca_spec.version);
+    if ca_spec.kind == CAKind::Sequence {
+        compressed_ca.require_permutations();
+    }
+    Ok(compressed_ca)
+}
+
+// Determines how many bytes the raw data of a CA stored at `offset` occupies.
+// The index does not record this, so for Bzip2 data we need to decode the stream.
+pub fn stored_length<R: Read+Seek>(reader: &mut R, offset: u64, ca_spec: &CASpec) -> Ca2Result<u64> {
+    match ca_spec.version {
+        CA2Version::Basic => ca_spec.basic_data_length()
+            .ok_or_else(|| Ca2Error::Format(String::from("CA data too large"))),
+        CA2Version::Bzip2 | CA2Version::Extended => {
+            reader.seek(SeekFrom::Start(offset))?;
+            let mut counter = CountingReader { inner: BufReader::new(reader), count: 0 };
+            std::io::copy(&mut BzDecoder::new(&mut counter), &mut std::io::sink())?;
+            Ok(counter.count)
+        }
+    }
+}
+
+// A buffered reader that counts the bytes consumed from it
+struct CountingReader<R> {
+    inner: R,
+    count: u64
+}
+
+impl<R: BufRead> Read for CountingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.count += n as u64;
+        Ok(n)
+    }
+}
+
+impl<R: BufRead> BufRead for CountingReader<R> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.inner.fill_buf()
+    }
+
+    fn consume(&mut self, amt: usize) {
+        self.count += amt as u64;
+        self.inner.consume(amt);
+    }
+}
+
+// Writes the metadata block of an edited archive. Entries keep the order they are given in, so that editing
+// an archive does not change the indices of the other entries. Unused space is recorded after the entries
+// so that it can be reclaimed by compaction later on.
+pub fn write_edited_index<W: Write>(writer: &mut W, entries: &[(u64, CASpec)], unused: &[u64]) -> Ca2Result<()> {
+    write_index(writer, entries)?;
+    for offset in unused.iter() {
+        writer.write_all(&offset.to_be_bytes())?;
+        writer.write_all(&CASpec::new_unused().serialize())?;
+    }
+    Ok(())
+}
+
+// Edits an archive in place: `write` gets a writer positioned at the metadata block of the archive,
+// writes the new CA data and metadata block there, and the archive is cut off after them.
+// Only the old metadata block is saved first, in a journal next to the archive, so that the edit costs
+// as much I/O as the new data. If the edit fails, the archive is restored from the journal;
+// if the process dies during the edit, `recover_archive` restores it on the next edit.
+pub fn edit_archive_tail<T, F>(archive: &Path, metadata_offset: u64, write: F) -> Ca2Result<T>
+where F: FnOnce(&mut BufWriter<&mut File>) -> Ca2Result<T> {
+    let mut f = std::fs::OpenOptions::new().read(true).write(true).open(archive)?;
+    let old_len = f.metadata()?.len();
+    let mut tail = Vec::new();
+    f.seek(SeekFrom::Start(metadata_offset))?;
+    (&mut f).take(old_len.saturating_sub(metadata_offset)).read_to_end(&mut tail)?;
+    write_journal(archive, old_len, metadata_offset, &tail)?;
+
+    let result = (|| {
+        f.seek(SeekFrom::Start(metadata_offset))?;
+        let mut writer = BufWriter::new(&mut f);
+        let value = write(&mut writer)?;
+        writer.flush()?;
+        drop(writer);
+        let end = f.stream_position()?;
+        f.set_len(end)?;
+        f.sync_all()?;
+        Ok(value)
+    })();
+    match result {
+        Ok(value) => {
+            std::fs::remove_file(journal_path(archive))?;
+            Ok(value)
+        },
+        Err(e) => {
+            drop(f);
+            match recover_archive(archive) {
+                Ok(_) => Err(e),
+                Err(recovery) => Err(with_note(e, format!("undoing the edit failed as well: {}", recovery)))
+            }
+        }
+    }
+}
+
+// Magic bytes of a journal. They are followed by the old length of the archive, the offset and length of
+// the saved metadata block, and the block itself.
+const MAGIC_BYTES_JOURNAL : &[u8; 16] = b"CA2_EDIT_JOURNAL";
+
+// Saves the metadata block of an archive before it is edited. The journal is written to a temporary file
+// that only replaces the journal once it is complete, so `recover_archive` never sees a partial one.
+fn write_journal(archive: &Path, old_len: u64, metadata_offset: u64, tail: &[u8]) -> Ca2Result<()> {
+    let journal = journal_path(archive);
+    let tmp_path = journal_tmp_path(archive);
+    let result = File::create(&tmp_path).map_err(Ca2Error::from).and_then(|mut f_journal| {
+        f_journal.write_all(MAGIC_BYTES_JOURNAL)?;
+        f_journal.write_all(&old_len.to_be_bytes())?;
+        f_journal.write_all(&metadata_offset.to_be_bytes())?;
+        f_journal.write_all(&(tail.len() as u64).to_be_bytes())?;
+        f_journal.write_all(tail)?;
+        f_journal.sync_all()?;
+        Ok(std::fs::rename(&tmp_path, &journal)?)
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+// The old length of the archive, the offset of its saved metadata block and the block itself,
+// or None if the journal is incomplete
+fn parse_journal(saved: &[u8]) -> Option<(u64, u64, &[u8])> {
+    let saved = saved.strip_prefix(MAGIC_BYTES_JOURNAL.as_slice())?;
+    let (header, tail) = saved.split_first_chunk::<24>()?;
+    let field = |i: usize| u64::from_be_bytes(header[i * 8..(i + 1) * 8].try_into().unwrap());
+    let (old_len, metadata_offset, tail_len) = (field(0), field(1), field(2));
+    if tail.len() as u64 != tail_len || metadata_offset.checked_add(tail_len) != Some(old_len) {
+        return None;
+    }
+    Some((old_len, metadata_offset, tail))
+}
+
+// Undoes an interrupted `edit_archive_tail` by writing the saved metadata block back to its place.
+// An incomplete journal means that the archive was not touched yet, so it is deleted.
+// Returns whether there was anything to undo.
+pub fn recover_archive(archive: &Path) -> Ca2Result<bool> {
+    match std::fs::remove_file(journal_tmp_path(archive)) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+        _ => {}
+    }
+    let journal = journal_path(archive);
+    let saved = match std::fs::read(&journal) {
+        Ok(saved) => saved,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(false),
+        Err(e) => return Err(e.into())
+    };
+    let Some((old_len, metadata_offset, tail)) = parse_journal(&saved) else {
+        std::fs::remove_file(&journal)?;
+        return Ok(false);
+    };
+    let mut f = std::fs::OpenOptions::new().write(true).open(archive)?;
+    f.seek(SeekFrom::Start(metadata_offset))?;
+    f.write_all(tail)?;
+    f.set_len(old_len)?;
+    f.sync_all()?;
+    std::fs::remove_file(&journal)?;
+    Ok(true)
+}
+
+// Adds a note to the message of an error
+fn with_note(e: Ca2Error, note: String) -> Ca2Error {
+    match e {
+        Ca2Error::Io(e) => Ca2Error::Io(std::io::Error::new(e.kind(), format!("{}; {}", e, note))),
+        Ca2Error::Format(msg) => Ca2Error::Format(format!("{}; {}", msg, note)),
+        Ca2Error::Parse(msg) => Ca2Error::Parse(format!("{}; {}", msg, note)),
+        Ca2Error::InvalidArgument(msg) => Ca2Error::InvalidArgument(format!("{}; {}", msg, note)),
+        e => Ca2Error::Format(format!("{}; {}", e, note))
+    }
+}
+
+fn journal_path(archive: &Path) -> PathBuf {
+    let mut name = archive.as_os_str().to_owned();
+    name.push(".journal");
+    PathBuf::from(name)
+}
+
+fn journal_tmp_path(archive: &Path) -> PathBuf {
+    let mut name = archive.as_os_str().to_owned();
+    name.push(".journal.tmp");
+    PathBuf::from(name)
+}
+
+// Writes a new version of an archive with `write` into a temporary file next to it (named with `suffix`),
+// which then replaces the archive. On errors the temporary file is removed and the archive is left as it was.
+// Self-extracting archives stay executable, since the new file gets the permissions of the old one.
+pub fn replace_archive<T, F>(archive: &Path, suffix: &str, write: F) -> Ca2Result<T>
+where F: FnOnce(&mut BufWriter<File>) -> Ca2Result<T> {
+    let mut tmp_name = archive.as_os_str().to_owned();
+    tmp_name.push(suffix);
+    let tmp_path = std::path::PathBuf::from(tmp_name);
+    let permissions = std::fs::metadata(archive)?.permissions();
+
+    let result = File::create(&tmp_path).map_err(Ca2Error::from).and_then(|f_tmp| {
+        f_tmp.set_permissions(permissions)?;
+        let mut writer = BufWriter::new(f_tmp);
+        let value = write(&mut writer)?;
+        writer.flush()?;
+        writer.get_ref().sync_all()?;
+        drop(writer);
+        std::fs::rename(&tmp_path, archive)?;
+        Ok(value)
+    });
+    if result.is_err() {
+        let _ = std::fs::remove_file(&tmp_path);
+    }
+    result
+}
+
+// Rewrites an archive so that it only contains the data of entries that are still in its index.
+// Everything before the first entry or unused space is kept as is, since it may hold a prepended file.
+// Returns the number of reclaimed bytes.
+pub fn compact_archive(archive: &Path) -> Ca2Result<u64> {
+    recover_archive(archive)?;
+    let mut f = File::open(archive)?;
+    let old_size = f.metadata()?.len();
+    let index = read_index(&mut f)?;
+    let prefix_len = index.entries.iter()
+        .map(|(offset, _)| *offset)
+        .chain(index.unused.iter().copied())
+        .min()
+        .unwrap_or(index.metadata_offset);
+
+    // Write the compacted archive next to the original, then replace it
+    let new_size = replace_archive(archive, ".compact", |writer| {
+        f.seek(SeekFrom::Start(0))?;
+        let mut offset = std::io::copy(&mut (&mut f).take(prefix_len), writer)?;
+
+        let mut entries = Vec::with_capacity(index.entries.len());
+        for (old_offset, ca_spec) in index.entries {
+            let len = stored_length(&mut f, old_offset, &ca_spec)?;
+            f.seek(SeekFrom::Start(old_offset))?;
+            let copied = std::io::copy(&mut (&mut f).take(len), writer)?;
+            if copied != len {
+                return Err(Ca2Error::Format(String::from("Truncated CA data in archive")));
+            }
+            entries.push((offset, ca_spec));
+            offset += len;
+        }
+        write_index(writer, &entries)?;
+        writer.flush()?;
+        Ok(writer.get_ref().metadata()?.len())
+    })?;
+    Ok(old_size.saturating_sub(new_size))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::TestDir;
+    use std::io::Cursor;
+
+    fn find(contents: &[u8], capacity: usize) -> Ca2Result<u64> {
+        let mut reader = BufReader::with_capacity(capacity, Cursor::new(contents));
+        find_metadata(&mut reader, contents.len() as i64)
+    }
+
+    #[test]
+    fn metadata_after_a_stray_underscore() {
+        let mut contents = b"some data_".to_vec();
+        let magic_offset = contents.len();
+        contents.push(MAGIC_BYTES_CA2_PRE);
+        contents.extend_from_slice(MAGIC_BYTES_CA2);
+        contents.extend_from_slice(&[0; 8]);
+        let metadata_start = (magic_offset + MAGIC_BYTES_CA2.len() + 1) as u64;
+        // With the magic bytes in one window, and spread over several ones
+        for capacity in [8192, 17, 5, 1] {
+            assert_eq!(find(&contents, capacity).unwrap(), metadata_start, "capacity {}", capacity);
+        }
+    }
+
+    #[test]
+    fn interrupted_edits_are_undone() {
+        let dir = TestDir::new("archive-journal");
+        let archive = dir.join("archive.ca2");
+        let ca_spec = CASpec { version: CA2Version::Bzip2, kind: CAKind::Covering, n: 4, t: 2, vs: vec![2, 2, 2] };
+        let mut contents = b"compressed CA data".to_vec();
+        let metadata_offset = contents.len() as u64;
+        write_index(&mut contents, &[(0, ca_spec)]).unwrap();
+        std::fs::write(&archive, &contents).unwrap();
+
+        // Die in the middle of an edit, after the new data has overwritten the old metadata block
+        let crashed = std::panic::catch_unwind(|| {
+            edit_archive_tail(&archive, metadata_offset, |writer| -> Ca2Result<()> {
+                writer.write_all(&[0xff; 4096])?;
+                writer.flush()?;
+                panic!("crash");
+            })
+        });
+        assert!(crashed.is_err());
+        assert!(journal_path(&archive).is_file());
+        assert_ne!(std::fs::read(&archive).unwrap(), contents);
+
+        assert!(recover_archive(&archive).unwrap());
+        assert_eq!(std::fs::read(&archive).unwrap(), contents);
+        assert!(!journal_path(&archive).exists());
+        assert!(!recover_archive(&archive).unwrap());
+
+        // A failed edit is undone right away
+        assert!(edit_archive_tail(&archive, metadata_offset, |writer| -> Ca2Result<()> {
+            writer.write_all(b"partial data")?;
+            Err(Ca2Error::InvalidArgument(String::from("failed")))
+        }).is_err());
+        assert_eq!(std::fs::read(&archive).unwrap(), contents);
+        assert!(!journal_path(&archive).exists());
+    }
+
+    #[test]
+    fn incomplete_journals_are_ignored() {
+        let dir = TestDir::new("archive-incomplete");
+        let archive = dir.join("archive.ca2");
+        let ca_spec = CASpec { version: CA2Version::Bzip2, kind: CAKind::Covering, n: 4, t: 2, vs: vec![2, 2, 2] };
+        let mut contents = b"compressed CA data".to_vec();
+        let metadata_offset = contents.len() as u64;
+        write_index(&mut contents, &[(0, ca_spec)]).unwrap();
+        std::fs::write(&archive, &contents).unwrap();
+        write_journal(&archive, contents.len() as u64, metadata_offset, &contents[metadata_offset as usize..]).unwrap();
+        let journal = std::fs::read(journal_path(&archive)).unwrap();
+        assert!(!journal_tmp_path(&archive).exists());
+
+        // Die while the journal is written, before the archive is touched: Only the old length
+        // and part of the metadata block made it to disk
+        for len in [0, 10, MAGIC_BYTES_JOURNAL.len() + 8, journal.len() - 5, journal.len() - 1] {
+            std::fs::write(journal_path(&archive), &journal[..len]).unwrap();
+            std::fs::write(journal_tmp_path(&archive), &journal[..len]).unwrap();
+            assert!(!recover_archive(&archive).unwrap(), "length {}", len);
+            assert_eq!(std::fs::read(&archive).unwrap(), contents);
+            assert!(!journal_path(&archive).exists());
+            assert!(!journal_tmp_path(&archive).exists());
+        }
+
+        // A leftover journal is pointed out when the archive can not be read
+        std::fs::write(journal_path(&archive), &journal).unwrap();
+        std::fs::write(&archive, &contents[..metadata_offset as usize]).unwrap();
+        let e = read_archive_index(&archive).err().unwrap().to_string();
+        assert!(e.contains("--recover"), "{}", e);
+        assert!(recover_archive(&archive).unwrap());
+        assert_eq!(std::fs::read(&archive).unwrap(), contents);
+        assert_eq!(read_archive_index(&archive).unwrap().entries.len(), 1);
+    }
+
+    #[test]
+    fn failed_recoveries_keep_the_original_error() {
+        let e = with_note(Ca2Error::InvalidArgument(String::from("failed")), String::from("undoing the edit failed as well"));
+        assert_eq!(e.to_string(), "Invalid argument: failed; undoing the edit failed as well");
+        let e = with_note(std::io::Error::other("disk full").into(), String::from("undoing the edit failed as well"));
+        assert!(matches!(&e, Ca2Error::Io(_)));
+        assert_eq!(e.to_string(), "I/O error: disk full; undoing the edit failed as well");
+    }
+
+    #[test]
+    fn metadata_missing() {
+        assert!(find(b"_CCAA_INDEX_FIL", 8192).is_err());
+        assert!(find(b"", 8192).is_err());
+    }
+}
@@ -0,0 +1,448 @@
+// A decoder for archives that are held in memory, e.g. embedded with `include_bytes!`.
+// It only depends on `core`, so it is available without the `std` feature,
+// and it does not allocate unless the `alloc` feature is enabled.
+// Bzip2 data cannot be decoded this way, but uncompressed data can be, e.g. a .cca file
+// that was decompressed with `bunzip2` before embedding it.
+use core::fmt;
+use crate::{CA2Version, CAKind, MAGIC_BYTES_CA2_PRE, MAGIC_BYTES_CA2, VS_TERMINATOR, Value};
+
+#[cfg(feature = "alloc")]
+extern crate alloc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    // The archive does not contain an index
+    NoIndex,
+    // The index or the data of a CA ends early
+    Truncated,
+    // An entry was written by an unknown CA2 version
+    Version(u16),
+    // An entry has an unknown kind
+    Kind(u8),
+    // An entry does not fit into the archive
+    InvalidEntry(u64),
+    // The data of an entry is compressed with Bzip2
+    Compressed,
+    // The buffer for a row does not have one value per parameter
+    RowLength,
+    // The requested parameters do not fit the CA
+    Incompatible
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DecodeError::NoIndex => write!(f, "No CA metadata found."),
+            DecodeError::Truncated => write!(f, "Truncated CA data"),
+            DecodeError::Version(v) => write!(f, "Unknown CA2 version {}", v),
+            DecodeError::Kind(k) => write!(f, "Unknown CA kind {}", k),
+            DecodeError::InvalidEntry(offset) => write!(f, "CA at offset {} does not fit into the archive", offset),
+            DecodeError::Compressed => write!(f, "Compressed CA data can only be decoded with std"),
+            DecodeError::RowLength => write!(f, "Row buffer does not match the number of parameters"),
+            DecodeError::Incompatible => write!(f, "The requested parameters do not fit the CA")
+        }
+    }
+}
+
+// An archive in memory
+#[derive(Debug, Clone, Copy)]
+pub struct ArchiveSlice<'a> {
+    // Everything before the index
+    data: &'a [u8],
+    // The index entries after the magic bytes
+    index: &'a [u8]
+}
+
+impl<'a> ArchiveSlice<'a> {
+    pub fn parse(archive: &'a [u8]) -> Result<Self, DecodeError> {
+        let magic_len = MAGIC_BYTES_CA2.len() + 1;
+        let start = archive.windows(magic_len)
+            .rposition(|w| w[0] == MAGIC_BYTES_CA2_PRE && w[1..] == MAGIC_BYTES_CA2[..])
+            .ok_or(DecodeError::NoIndex)?;
+        Ok(ArchiveSlice { data: &archive[..start], index: &archive[start + magic_len..] })
+    }
+
+    // All entries in the index, except for unused space
+    pub fn entries(&self) -> Entries<'a> {
+        Entries { data: self.data, index: self.index }
+    }
+
+    // Finds the smallest CA of the given kind that covers all t-tuples of parameters with `vs` values.
+    // Unlike CASpec::is_compatible, `vs` does not need to be sorted.
+    pub fn find_best(&self, kind: CAKind, t: u8, vs: &[u16]) -> Result<Option<Entry<'a>>, DecodeError> {
+        let mut best : Option<Entry<'a>> = None;
+        for entry in self.entries() {
+            let entry = entry?;
+            if entry.is_compatible(kind, t, vs) && best.as_ref().is_none_or(|best| entry.n < best.n) {
+                best = Some(entry);
+            }
+        }
+        Ok(best)
+    }
+}
+
+// Iterates over the entries of an archive index
+pub struct Entries<'a> {
+    data: &'a [u8],
+    index: &'a [u8]
+}
+
+impl<'a> Entries<'a> {
+    fn next_entry(&mut self) -> Result<Entry<'a>, DecodeError> {
+        let offset = u64::from_be_bytes(take(&mut self.index, 8)?.try_into().unwrap());
+        let header = take(&mut self.index, 11)?;
+        let version_id = u16::from_be_bytes([header[0], header[1]]);
+        let version = CA2Version::from_id(version_id).ok_or(DecodeError::Version(version_id))?;
+        let n = u64::from_be_bytes(header[2..10].try_into().unwrap());
+        let t = header[10];
+        let kind = match version.has_kind() {
+            true => {
+                let kind_id = take(&mut self.index, 1)?[0];
+                CAKind::from_id(kind_id).ok_or(DecodeError::Kind(kind_id))?
+            },
+            false => CAKind::Covering
+        };
+
+        // The values are followed by the terminator
+        let k = self.index.chunks_exact(2)
+            .position(|v| u16::from_be_bytes([v[0], v[1]]) == VS_TERMINATOR)
+            .ok_or(DecodeError::Truncated)?;
+        let vs = take(&mut self.index, 2 * k)?;
+        take(&mut self.index, 2)?;
+
+        // Unused space has no data, but still has to lie within the archive
+        let data = usize::try_from(offset).ok()
+            .and_then(|offset| self.data.get(offset..))
+            .ok_or(DecodeError::InvalidEntry(offset))?;
+        let entry = Entry { version, kind, n, t, vs, data };
+        if entry.kind != CAKind::Unused && (entry.data.is_empty() || (entry.n > 0 && k == 0)) {
+            return Err(DecodeError::InvalidEntry(offset));
+        }
+        if entry.version == CA2Version::Basic && entry.basic_data_length().is_none_or(|len| len > entry.data.len() as u64) {
+            return Err(DecodeError::InvalidEntry(offset));
+        }
+        Ok(entry)
+    }
+}
+
+impl<'a> Iterator for Entries<'a> {
+    type Item = Result<Entry<'a>, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.index.is_empty() {
+                return None;
+            }
+            match self.next_entry() {
+                Ok(entry) if entry.kind == CAKind::Unused => continue,
+                Ok(entry) => return Some(Ok(entry)),
+                Err(e) => {
+                    // Nothing after a broken entry can be trusted
+                    self.index = &[];
+                    return Some(Err(e));
+                }
+            }
+        }
+    }
+}
+
+// A CA stored in an archive in memory
+#[derive(Debug, Clone, Copy)]
+pub struct Entry<'a> {
+    pub version: CA2Version,
+    pub kind: CAKind,
+    pub n: u64,
+    pub t: u8,
+    // The serialized values of each parameter
+    vs: &'a [u8],
+    // The data of this CA, up to the index of the archive
+    data: &'a [u8]
+}
+
+impl<'a> Entry<'a> {
+    // The number of parameters
+    pub fn k(&self) -> usize {
+        self.vs.len() / 2
+    }
+
+    // The numbers of values of all parameters, sorted descending
+    pub fn vs(&self) -> impl Iterator<Item = u16> + 'a {
+        self.vs.chunks_exact(2).map(|v| u16::from_be_bytes([v[0], v[1]]))
+    }
+
+    pub fn is_compatible(&self, kind: CAKind, t: u8, vs: &[u16]) -> bool {
+        // The stored values are sorted, so there is a suitable column for every requested parameter
+        // if each requested number of values r is exceeded by as many stored columns as requested ones
+        self.kind == kind && self.t >= t && self.k() >= vs.len() &&
+            vs.iter().all(|r| vs.iter().filter(|v| *v >= r).count() <= self.vs().filter(|v| v >= r).count())
+    }
+
+    // Maps each requested parameter to a stored column, so that map[i] is the column of parameter i.
+    // Parameters with more values are assigned first, just like dca does.
+    pub fn map_columns(&self, vs: &[u16], map: &mut [usize]) -> Result<(), DecodeError> {
+        if map.len() != vs.len() {
+            return Err(DecodeError::RowLength);
+        }
+        if vs.len() > self.k() {
+            return Err(DecodeError::Incompatible);
+        }
+        map.fill(usize::MAX);
+        for (column, stored_v) in self.vs().take(vs.len()).enumerate() {
+            // The largest parameter that has not been assigned yet, the first one of several
+            let (i, v) = vs.iter().enumerate()
+                .filter(|(i, _)| map[*i] == usize::MAX)
+                .fold(None, |max: Option<(usize, &u16)>, (i, v)| match max {
+                    Some((_, max_v)) if max_v >= v => max,
+                    _ => Some((i, v))
+                })
+                .unwrap();
+            if *v > stored_v {
+                return Err(DecodeError::Incompatible);
+            }
+            map[i] = column;
+        }
+        Ok(())
+    }
+
+    // The rows of this CA. Only Basic data can be decoded from an archive.
+    pub fn rows(&self) -> Result<Rows<'a>, DecodeError> {
+        match self.version {
+            CA2Version::Basic => Ok(Rows { data: self.data, vs: self.vs, n: self.n, row: 0, pos: 0, packed: true }),
+            CA2Version::Bzip2 | CA2Version::Extended => Err(DecodeError::Compressed)
+        }
+    }
+
+    // The rows of this CA, decoded from the decompressed data of a Bzip2 entry (e.g. a .cca file
+    // that was decompressed beforehand), which stores each value as a big endian u16
+    pub fn uncompressed_rows(&self, data: &'a [u8]) -> Rows<'a> {
+        Rows { data, vs: self.vs, n: self.n, row: 0, pos: 0, packed: false }
+    }
+
+    fn basic_data_length(&self) -> Option<u64> {
+        let bits_per_row : u64 = self.vs().map(|v| bit_size(v) as u64).sum();
+        let chunk_bits = crate::CompressionChunk::BITS as u64;
+        self.n.checked_mul(bits_per_row)?
+            .div_ceil(chunk_bits)
+            .checked_mul(chunk_bits / 8)
+    }
+}
+
+// Decodes the rows of a CA into a caller-provided buffer
+pub struct Rows<'a> {
+    data: &'a [u8],
+    vs: &'a [u8],
+    n: u64,
+    row: u64,
+    // Position in the data, in bits for Basic data and in values otherwise
+    pos: usize,
+    // Whether this is Basic data
+    packed: bool
+}
+
+impl<'a> Rows<'a> {
+    // Decodes the next row into `out`, which needs one value per parameter.
+    // Returns false once all rows have been decoded.
+    pub fn next_row(&mut self, out: &mut [Value]) -> Result<bool, DecodeError> {
+        if out.len() * 2 != self.vs.len() {
+            return Err(DecodeError::RowLength);
+        }
+        if self.row >= self.n {
+            return Ok(false);
+        }
+        for (value, v) in out.iter_mut().zip(self.vs.chunks_exact(2)) {
+            *value = match self.packed {
+                true => self.read_bits(bit_size(u16::from_be_bytes([v[0], v[1]])))?,
+                false => self.read_u16()?
+            };
+        }
+        self.row += 1;
+        Ok(true)
+    }
+
+    // Basic data is a stream of big endian chunks, each filled with values from its most significant
+    // bit on, so that values are stored most significant bit first in consecutive bytes
+    fn read_bits(&mut self, bits: u32) -> Result<Value, DecodeError> {
+        let byte = self.pos / 8;
+        let shift = (self.pos % 8) as u32;
+        let len = (shift + bits).div_ceil(8) as usize;
+        let bytes = self.data.get(byte..byte + len).ok_or(DecodeError::Truncated)?;
+        let window = bytes.iter().fold(0u32, |w, b| (w << 8) | *b as u32) << (8 * (3 - len as u32));
+        self.pos += bits as usize;
+        Ok(((window >> (24 - shift - bits)) & ((1 << bits) - 1)) as Value)
+    }
+
+    fn read_u16(&mut self) -> Result<Value, DecodeError> {
+        let bytes = self.data.get(2 * self.pos..2 * self.pos + 2).ok_or(DecodeError::Truncated)?;
+        self.pos += 1;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl Iterator for Rows<'_> {
+    type Item = Result<alloc::vec::Vec<Value>, DecodeError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut row = alloc::vec![0; self.vs.len() / 2];
+        match self.next_row(&mut row) {
+            Ok(true) => Some(Ok(row)),
+            Ok(false) => None,
+            Err(e) => {
+                self.row = self.n;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+// The number of bits a value with v possible values occupies in Basic data (see generate_bit_sizes)
+fn bit_size(v: u16) -> u32 {
+    match v {
+        0 | 1 => 1,
+        v => u16::BITS - (v - 1).leading_zeros()
+    }
+}
+
+fn take<'a>(buf: &mut &'a [u8], len: usize) -> Result<&'a [u8], DecodeError> {
+    if buf.len() < len {
+        return Err(DecodeError::Truncated);
+    }
+    let (out, rest) = buf.split_at(len);
+    *buf = rest;
+    Ok(out)
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use std::io::{Cursor, Write};
+    use bzip2::write::BzEncoder;
+    use bzip2::Compression;
+    use crate::{CASpec, Row, generate_bit_sizes, generate_reorder_map, open_ca, read_index, write_index};
+
+    // CA(15; 2, 5 3 2) with all combinations of the first two columns, stored as Basic data
+    fn basic_rows() -> Vec<Row> {
+        (0..15).map(|i| vec![i / 3, i % 3, (i / 3 + i % 3) % 2]).collect()
+    }
+
+    // CA(9; 2, 3^4), stored with Bzip2
+    fn bzip2_rows() -> Vec<Row> {
+        (0..9).map(|i| (i / 3, i % 3)).map(|(a, b)| vec![a, b, (a + b) % 3, (a + 2 * b) % 3]).collect()
+    }
+
+    fn u16_data(rows: &[Row]) -> Vec<u8> {
+        rows.iter().flatten().flat_map(|v| v.to_be_bytes()).collect()
+    }
+
+    // An archive with a Basic and a Bzip2 entry, separated by unused space.
+    // Returns the archive and the decompressed data of the Bzip2 entry.
+    fn test_archive() -> (Vec<u8>, Vec<u8>) {
+        let basic_spec = CASpec { version: CA2Version::Basic, kind: CAKind::Covering, n: 15, t: 2, vs: vec![5, 3, 2] };
+        let bzip2_spec = CASpec { version: CA2Version::Bzip2, kind: CAKind::Covering, n: 9, t: 2, vs: vec![3, 3, 3, 3] };
+        let uncompressed = u16_data(&bzip2_rows());
+
+        let mut archive = b"prepended unpacker".to_vec();
+        let basic_offset = archive.len() as u64;
+        archive.extend(crate::tests::pack(&basic_rows(), &generate_bit_sizes(&basic_spec.vs)));
+        let unused_offset = archive.len() as u64;
+        archive.extend_from_slice(b"replaced data");
+        let bzip2_offset = archive.len() as u64;
+        let mut encoder = BzEncoder::new(&mut archive, Compression::fast());
+        encoder.write_all(&uncompressed).unwrap();
+        encoder.finish().unwrap();
+        write_index(&mut archive, &[(basic_offset, basic_spec), (bzip2_offset, bzip2_spec)]).unwrap();
+        archive.extend_from_slice(&unused_offset.to_be_bytes());
+        archive.extend_from_slice(&CASpec::new_unused().serialize());
+        (archive, uncompressed)
+    }
+
+    fn decode_embedded(mut rows: Rows, k: usize) -> Vec<Row> {
+        let mut decoded = Vec::new();
+        let mut row = vec![0; k];
+        while rows.next_row(&mut row).unwrap() {
+            decoded.push(row.clone());
+        }
+        decoded
+    }
+
+    #[test]
+    fn both_decoders_return_the_same_rows() {
+        let (archive, uncompressed) = test_archive();
+        let index = read_index(&mut Cursor::new(archive.clone())).unwrap();
+        let slice = ArchiveSlice::parse(&archive).unwrap();
+        let entries : Vec<Entry> = slice.entries().collect::<Result<_, _>>().unwrap();
+        assert_eq!(entries.len(), index.entries.len());
+
+        for (entry, (offset, ca_spec)) in entries.iter().zip(index.entries.iter()) {
+            assert_eq!((entry.version, entry.kind, entry.n, entry.t), (ca_spec.version, ca_spec.kind, ca_spec.n, ca_spec.t));
+            assert_eq!(entry.vs().collect::<Vec<_>>(), ca_spec.vs);
+            let std_rows : Vec<Row> = open_ca(Cursor::new(archive.clone()), *offset, ca_spec).unwrap()
+                .collect::<Result<_, _>>().unwrap();
+            let embedded_rows = match entry.version {
+                CA2Version::Basic => decode_embedded(entry.rows().unwrap(), entry.k()),
+                _ => {
+                    assert_eq!(entry.rows().err(), Some(DecodeError::Compressed));
+                    decode_embedded(entry.uncompressed_rows(&uncompressed), entry.k())
+                }
+            };
+            assert_eq!(embedded_rows, std_rows);
+        }
+        assert_eq!(entries[0].rows().unwrap().collect::<Result<Vec<_>, _>>().unwrap(), basic_rows());
+        assert_eq!(decode_embedded(entries[1].uncompressed_rows(&uncompressed), 4), bzip2_rows());
+    }
+
+    #[test]
+    fn selection_matches_the_std_decoder() {
+        let (archive, _) = test_archive();
+        let index = read_index(&mut Cursor::new(archive.clone())).unwrap();
+        let slice = ArchiveSlice::parse(&archive).unwrap();
+        let requests : [(u8, &[u16]); 6] = [(2, &[2, 2]), (2, &[3, 3, 3]), (2, &[2, 5]), (2, &[3, 3, 3, 3, 3]), (3, &[2, 2]), (2, &[4, 4])];
+
+        for (t, vs) in requests {
+            let mut sorted = vs.to_vec();
+            sorted.sort_by(|a, b| b.cmp(a));
+            let requested = CASpec { version: CA2Version::Bzip2, kind: CAKind::Covering, n: 0, t, vs: sorted };
+            let expected = index.entries.iter()
+                .filter(|(_, ca_spec)| requested.is_compatible(ca_spec))
+                .min_by_key(|(_, ca_spec)| ca_spec.n);
+            let best = slice.find_best(CAKind::Covering, t, vs).unwrap();
+            assert_eq!(best.map(|entry| entry.n), expected.map(|(_, ca_spec)| ca_spec.n), "t={} vs={:?}", t, vs);
+            assert!(slice.find_best(CAKind::Sequence, t, vs).unwrap().is_none());
+
+            // Both decoders assign the parameters to the same columns
+            if let (Some(entry), Some((_, ca_spec))) = (best, expected) {
+                let mut map = vec![0; vs.len()];
+                entry.map_columns(vs, &mut map).unwrap();
+                assert_eq!(map, generate_reorder_map(vs, ca_spec).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_input_is_reported() {
+        let (archive, uncompressed) = test_archive();
+        assert_eq!(ArchiveSlice::parse(b"no index").err(), Some(DecodeError::NoIndex));
+        let slice = ArchiveSlice::parse(&archive).unwrap();
+        let entry = slice.find_best(CAKind::Covering, 2, &[5, 2]).unwrap().unwrap();
+
+        let mut map = [0; 2];
+        assert_eq!(entry.map_columns(&[2, 2, 2], &mut map), Err(DecodeError::RowLength));
+        assert_eq!(entry.map_columns(&[6, 2], &mut map), Err(DecodeError::Incompatible));
+        assert!(!entry.is_compatible(CAKind::Covering, 2, &[3, 3, 3]));
+        assert!(entry.is_compatible(CAKind::Covering, 1, &[2, 5, 3]));
+
+        let mut row = [0; 2];
+        assert_eq!(entry.rows().unwrap().next_row(&mut row), Err(DecodeError::RowLength));
+        let bzip2_entry = slice.find_best(CAKind::Covering, 2, &[3, 3, 3]).unwrap().unwrap();
+        let mut row = [0; 4];
+        let mut rows = bzip2_entry.uncompressed_rows(&uncompressed[..11]);
+        assert_eq!(rows.next_row(&mut row), Ok(true));
+        assert_eq!(rows.next_row(&mut row), Err(DecodeError::Truncated));
+
+        // The index is cut off in the middle of the last entry
+        let truncated = ArchiveSlice::parse(&archive[..archive.len() - 3]).unwrap();
+        let results : Vec<_> = truncated.entries().collect();
+        assert_eq!(results.len(), 3);
+        assert_eq!(results[2].err(), Some(DecodeError::Truncated));
+    }
+}
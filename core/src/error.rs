@@ -0,0 +1,64 @@
+use std::fmt;
+
+// Errors returned by the functions of this crate
+#[derive(Debug)]
+pub enum Ca2Error {
+    // Reading or writing data failed
+    Io(std::io::Error),
+    // Data is not in the expected format, e.g. a corrupted archive or metadata file
+    Format(String),
+    // An input model or other textual input could not be parsed
+    Parse(String),
+    // The data was written by an unknown CA2 version
+    Version(u16),
+    // An argument does not fit the data it is applied to, e.g. a column index that is out of range
+    InvalidArgument(String),
+}
+
+pub type Ca2Result<T> = Result<T, Ca2Error>;
+
+impl fmt::Display for Ca2Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Ca2Error::Io(e) => write!(f, "I/O error: {}", e),
+            Ca2Error::Format(msg) => write!(f, "Invalid format: {}", msg),
+            Ca2Error::Parse(msg) => write!(f, "Parse error: {}", msg),
+            Ca2Error::Version(v) => write!(f, "Unknown CA2 version {}", v),
+            Ca2Error::InvalidArgument(msg) => write!(f, "Invalid argument: {}", msg)
+        }
+    }
+}
+
+impl std::error::Error for Ca2Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Ca2Error::Io(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<std::io::Error> for Ca2Error {
+    fn from(e: std::io::Error) -> Self {
+        Ca2Error::Io(e)
+    }
+}
+
+impl From<csv::Error> for Ca2Error {
+    fn from(e: csv::Error) -> Self {
+        let msg = e.to_string();
+        match e.into_kind() {
+            csv::ErrorKind::Io(e) => Ca2Error::Io(e),
+            _ => Ca2Error::Parse(msg)
+        }
+    }
+}
+
+impl From<crate::embedded::DecodeError> for Ca2Error {
+    fn from(e: crate::embedded::DecodeError) -> Self {
+        match e {
+            crate::embedded::DecodeError::Version(v) => Ca2Error::Version(v),
+            e => Ca2Error::Format(e.to_string())
+        }
+    }
+}
@@ -0,0 +1,771 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+// The CA2 archive format, decoding and parsing input models and CA specifications.
+// Shared by ca2 (which re-exports all of it) and the test macro in ca2-macros.
+
+#[cfg(feature = "std")]
+use std::io::Read;
+#[cfg(feature = "std")]
+use std::sync::Arc;
+
+// Errors
+#[cfg(feature = "std")]
+pub mod error;
+#[cfg(feature = "std")]
+pub use self::error::{Ca2Error, Ca2Result};
+
+// Parsers
+#[cfg(feature = "std")]
+extern crate pest;
+#[cfg(feature = "std")]
+extern crate pest_derive;
+#[cfg(feature = "std")]
+pub mod parsers;
+#[cfg(feature = "std")]
+pub use self::parsers::acts::try_parse_acts;
+#[cfg(feature = "std")]
+pub use self::parsers::ctwedge::try_parse_ctwedge;
+#[cfg(feature = "std")]
+pub use self::parsers::events::try_parse_events;
+#[cfg(feature = "std")]
+pub use self::parsers::parse_model;
+
+// Reading and writing archives
+#[cfg(feature = "std")]
+pub mod archive;
+#[cfg(feature = "std")]
+pub use self::archive::{ArchiveIndex, extract_ca_specs, read_archive_index, read_index, write_index, write_edited_index, edit_archive_tail, recover_archive, replace_archive, compact_archive, setup_decoder
+                        , find_archives, default_archive_locations, contains_index, find_best_ca, open_ca};
+#[cfg(feature = "mmap")]
+pub use self::archive::map_archive;
+
+// Parsing CA specifications written in common notations
+#[cfg(feature = "std")]
+pub mod spec;
+#[cfg(feature = "std")]
+pub use self::spec::SpecNotation;
+
+// Decoding archives in memory, without std
+pub mod embedded;
+
+// Helpers for the tests of this crate and of ca2
+#[cfg(any(test, feature = "testing"))]
+#[doc(hidden)]
+pub mod testing;
+
+// Magic bytes for a CA2 archive
+pub const MAGIC_BYTES_CA2_PRE : u8 = b'_';
+pub const MAGIC_BYTES_CA2 : &[u8; 16] = b"CCAA_INDEX_FILE\n";
+
+// Magic bytes for a compressed raw CA file; note the space!
+pub const MAGIC_BYTES_CCA : &str = " CCA";
+
+// Known CA2 versions
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum CA2Version {
+    Basic,
+    Bzip2,
+    // Same data encoding as Bzip2, but the metadata also stores the CAKind
+    Extended
+}
+
+// The version of the files we are processing
+pub const CA2_DEFAULT_VERSION : CA2Version = CA2Version::Bzip2;
+
+impl Default for CA2Version {
+    fn default() -> Self {
+        CA2_DEFAULT_VERSION
+    }
+}
+// CA2Version -> u16
+impl From<CA2Version> for u16 {
+    fn from(v: CA2Version) -> Self {
+        match v {
+            CA2Version::Basic => 1,
+            CA2Version::Bzip2 => 2,
+            CA2Version::Extended => 3
+        }
+    }
+}
+// u16 -> CA2Version
+#[cfg(feature = "std")]
+impl TryFrom<u16> for CA2Version {
+    type Error = Ca2Error;
+
+    fn try_from(v: u16) -> Result<Self, Self::Error> {
+        CA2Version::from_id(v).ok_or(Ca2Error::Version(v))
+    }
+}
+
+impl CA2Version {
+    pub fn from_id(v: u16) -> Option<Self> {
+        match v {
+            1 => Some(CA2Version::Basic),
+            2 => Some(CA2Version::Bzip2),
+            3 => Some(CA2Version::Extended),
+            _ => None
+        }
+    }
+
+    // Whether the serialized metadata of this version contains a CAKind
+    pub fn has_kind(&self) -> bool {
+        matches!(self, CA2Version::Extended)
+    }
+}
+
+// Known kinds of arrays that can be stored in a CA2 archive
+#[derive(Debug,Clone,Copy,PartialEq,Eq,Default)]
+pub enum CAKind {
+    // An ordinary (mixed-level) covering array
+    #[default]
+    Covering,
+    // A sequence covering array: each row is a permutation of the same events,
+    // and every t-permutation of events appears as a subsequence of some row
+    Sequence,
+    // Not an array, but space left behind by a replaced or removed entry of an archive
+    Unused
+}
+
+// CAKind -> u8
+impl From<CAKind> for u8 {
+    fn from(k: CAKind) -> Self {
+        match k {
+            CAKind::Covering => 0,
+            CAKind::Sequence => 1,
+            CAKind::Unused => 2
+        }
+    }
+}
+// u8 -> CAKind
+#[cfg(feature = "std")]
+impl TryFrom<u8> for CAKind {
+    type Error = Ca2Error;
+
+    fn try_from(k: u8) -> Result<Self, Self::Error> {
+        CAKind::from_id(k).ok_or_else(|| Ca2Error::Format(format!("Unknown CA kind {}", k)))
+    }
+}
+
+impl CAKind {
+    pub fn from_id(k: u8) -> Option<Self> {
+        match k {
+            0 => Some(CAKind::Covering),
+            1 => Some(CAKind::Sequence),
+            2 => Some(CAKind::Unused),
+            _ => None
+        }
+    }
+}
+
+// Terminator for the serialized list of v_i aka vs
+pub const VS_TERMINATOR : u16 = 0;
+
+// The CA specification contains metadata required to uncompress a CA2 file.
+// For sequence covering arrays, every entry of `vs` is the number of events.
+#[cfg(feature = "std")]
+#[derive(Debug,Clone)]
+pub struct CASpec {
+    pub version: CA2Version,
+    pub kind: CAKind,
+    pub n: u64,
+    pub t: u8,
+    pub vs: Vec<u16>
+}
+
+// A requested CA instance, derived from an ACTS or CTWedge input file
+#[cfg(feature = "std")]
+#[derive(Clone)]
+pub struct RequestedCA {
+    pub parameter_names: Vec<String>,
+    pub parameter_values: Vec<Arc<[String]>>,
+    pub parameter_sizes: Vec<u16>,
+    pub ca_spec: CASpec
+}
+
+#[cfg(feature = "std")]
+impl RequestedCA {
+    // Creates a request without an input model. Parameter i is named p{i+1},
+    // and its values are the numbers 0 to sizes[i]-1.
+    pub fn from_sizes(strength: u8, sizes: &[u16]) -> Ca2Result<RequestedCA> {
+        if sizes.contains(&0) {
+            return Err(Ca2Error::InvalidArgument(String::from("Every parameter needs at least one value")));
+        }
+        let mut vs = sizes.to_vec();
+        vs.sort_by(|a, b| b.cmp(a));
+        Ok(RequestedCA {
+            parameter_names: (1..=sizes.len()).map(|i| format!("p{}", i)).collect(),
+            parameter_values: sizes.iter().map(|v| (0..*v).map(|value| value.to_string()).collect()).collect(),
+            parameter_sizes: sizes.to_vec(),
+            ca_spec: CASpec {
+                version: CA2Version::default(),
+                kind: CAKind::Covering,
+                n: 0,
+                t: strength,
+                vs
+            }
+        })
+    }
+
+    // Creates a request without an input model from a CA specification, with parameters and values
+    // named like `from_sizes`. The events of sequence covering arrays are numbered as well.
+    pub fn from_ca_spec(ca_spec: &CASpec) -> Ca2Result<RequestedCA> {
+        match ca_spec.kind {
+            CAKind::Sequence => Ok(RequestedCA {
+                parameter_names: (1..=ca_spec.vs.len()).map(|i| format!("step_{}", i)).collect(),
+                parameter_values: vec![(0..ca_spec.vs.len()).map(|event| event.to_string()).collect(); ca_spec.vs.len()],
+                parameter_sizes: ca_spec.vs.clone(),
+                ca_spec: CASpec::new_sequence(0, ca_spec.t, ca_spec.vs.len() as u16)
+            }),
+            _ => RequestedCA::from_sizes(ca_spec.t, &ca_spec.vs)
+        }
+    }
+
+    // Translates a decoded row of a stored CA into the values of the requested parameters,
+    // using a reorder map from generate_reorder_map
+    pub fn translate_row<'a>(&'a self, row: &'a [Value], reorder_map: &'a [usize]) -> impl Iterator<Item = &'a str> + 'a {
+        self.translate_indices(row, reorder_map).map(|(i, v)| self.parameter_values[i][v].as_str())
+    }
+
+    // Like translate_row, but returns (parameter, index of its value) pairs
+    pub fn translate_indices<'a>(&'a self, row: &'a [Value], reorder_map: &'a [usize]) -> TranslatedRow<'a> {
+        TranslatedRow { requested_ca: self, row, reorder_map, pos: 0, parameter: 0 }
+    }
+}
+
+// An iterator over the translated values of a row
+#[cfg(feature = "std")]
+pub struct TranslatedRow<'a> {
+    requested_ca: &'a RequestedCA,
+    row: &'a [Value],
+    reorder_map: &'a [usize],
+    // Position in the stored row (for SCAs)
+    pos: usize,
+    // The next requested parameter
+    parameter: usize
+}
+
+#[cfg(feature = "std")]
+impl Iterator for TranslatedRow<'_> {
+    type Item = (usize, usize);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sizes = &self.requested_ca.parameter_sizes;
+        if self.requested_ca.ca_spec.kind == CAKind::Sequence {
+            // Each stored row is a permutation of event indices. Events that were not requested
+            // are simply dropped, which preserves all t-permutations of the remaining events.
+            // Rows that are no permutation (see `CompressedCA::require_permutations`) end early.
+            if self.parameter == sizes.len() {
+                return None;
+            }
+            let events = *sizes.first()? as usize;
+            let event = self.row[self.pos..].iter().position(|e| (*e as usize) < events)?;
+            self.pos += event + 1;
+            self.parameter += 1;
+            return Some((self.parameter - 1, self.row[self.pos - 1] as usize));
+        }
+
+        // We take the stored value of the parameter modulo the requested parameter size,
+        // which yields the index of the value of the requested parameter
+        let i = self.parameter;
+        let stored_i = *self.reorder_map.get(i)?;
+        self.parameter += 1;
+        Some((i, (self.row[stored_i] % sizes[i]) as usize))
+    }
+}
+
+#[cfg(feature = "std")]
+impl CASpec {
+    // Creates the specification of a sequence covering array SCA(N; t, events)
+    pub fn new_sequence(n: u64, t: u8, events: u16) -> Self {
+        CASpec {
+            version: CA2Version::Extended,
+            kind: CAKind::Sequence,
+            n,
+            t,
+            vs: vec![events; events as usize]
+        }
+    }
+
+    // Creates the index record for unused space in an archive
+    pub fn new_unused() -> Self {
+        CASpec {
+            version: CA2Version::Extended,
+            kind: CAKind::Unused,
+            n: 0,
+            t: 0,
+            vs: Vec::new()
+        }
+    }
+
+    // The number of events of a sequence covering array
+    pub fn events(&self) -> Option<u16> {
+        match self.kind {
+            CAKind::Sequence => Some(self.vs.len() as u16),
+            _ => None
+        }
+    }
+
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut out : Vec<u8> = Vec::new();
+
+        out.extend(u16::from(self.version).to_be_bytes());
+        out.extend(self.n.to_be_bytes());
+        out.extend(self.t.to_be_bytes());
+        if self.version.has_kind() {
+            out.push(u8::from(self.kind));
+        }
+        for v in self.vs.iter() {
+            out.extend(v.to_be_bytes());
+        }
+        out.extend(VS_TERMINATOR.to_be_bytes());
+        out
+    }
+
+    // Parses a serialized CA specification.
+    // Returns the specification and the number of bytes it occupied in `buf`.
+    pub fn unserialize(buf: &[u8]) -> Ca2Result<(Self, usize)> {
+        let truncated = || Ca2Error::Format(String::from("Truncated CA specification"));
+        let header = buf.get(0..11).ok_or_else(truncated)?;
+        let version = CA2Version::try_from(u16::from_be_bytes([header[0], header[1]]))?;
+
+        // NOTE: All known CA2 versions share this part of the metadata.
+        let n = u64::from_be_bytes(header[2..10].try_into().unwrap());
+        let t = header[10];
+        let mut i = 11;
+
+        // Extended metadata also stores the kind of array
+        let mut kind = CAKind::Covering;
+        if version.has_kind() {
+            kind = CAKind::try_from(*buf.get(i).ok_or_else(truncated)?)?;
+            i += 1;
+        }
+
+        // Parse values
+        let mut vs = Vec::new();
+
+        // Loop until we reach the terminator (which is not a valid count of values)
+        loop {
+            let v = buf.get(i..i+2).ok_or_else(truncated)?;
+            let v = u16::from_be_bytes([v[0], v[1]]);
+            i += 2; // We need 2 bytes for each u16
+            if v == VS_TERMINATOR {
+                break;
+            }
+            vs.push(v);
+
+        }
+
+        Ok((Self { version, kind, n, t, vs }, i))
+    }
+
+    // The number of bytes occupied by the raw data of a Basic CA, which packs its rows into
+    // full compression chunks. Returns None for compressed data, or if the size overflows.
+    pub fn basic_data_length(&self) -> Option<u64> {
+        if self.version != CA2Version::Basic {
+            return None;
+        }
+        let bits_per_row : u64 = generate_bit_sizes(&self.vs).iter().map(|b| *b as u64).sum();
+        let chunk_bits = CompressionChunk::BITS as u64;
+        self.n.checked_mul(bits_per_row)?
+            .div_ceil(chunk_bits)
+            .checked_mul(chunk_bits / 8)
+    }
+
+    // Note that this also works for sequence covering arrays:
+    // Removing events from every row of an SCA yields an SCA over the remaining events.
+    #[inline]
+    pub fn is_compatible(&self, stored_spec: &CASpec) -> bool {
+        stored_spec.kind == self.kind &&
+            stored_spec.t >= self.t &&
+            stored_spec.vs.len() >= self.vs.len() &&
+            (0..self.vs.len()).all(|i| stored_spec.vs[i] >= self.vs[i])
+    }
+
+    // A stored CA dominates another one if it is compatible with every request
+    // the other one is compatible with, without having more rows
+    #[inline]
+    pub fn dominates(&self, other: &CASpec) -> bool {
+        self.n <= other.n && other.is_compatible(self)
+    }
+
+}
+
+// Formats a CA specification in exponential notation, e.g. CA(36; 4, 3^2 2^2)
+#[cfg(feature = "std")]
+impl std::fmt::Display for CASpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.kind {
+            CAKind::Covering => {
+                write!(f, "CA({}; {}, ", self.n, self.t)?;
+                let mut first = true;
+                for (v, count) in group_vs(&self.vs) {
+                    if !first {
+                        write!(f, " ")?;
+                    }
+                    first = false;
+                    write!(f, "{}^{}", v, count)?;
+                }
+                write!(f, ")")
+            },
+            CAKind::Sequence => write!(f, "SCA({}; {}, {})", self.n, self.t, self.vs.len()),
+            CAKind::Unused => write!(f, "unused")
+        }
+    }
+}
+
+// Groups consecutive identical values, e.g. [3, 3, 2] -> [(3, 2), (2, 1)]
+#[cfg(feature = "std")]
+pub fn group_vs(vs: &[u16]) -> Vec<(u16, usize)> {
+    let mut out : Vec<(u16, usize)> = Vec::new();
+    for v in vs.iter() {
+        match out.last_mut() {
+            Some((last_v, count)) if last_v == v => *count += 1,
+            _ => out.push((*v, 1))
+        }
+    }
+    out
+}
+
+// Finds the CA specifications that are dominated by another one in the same set.
+// Returns pairs of (dominated, dominating) indices, where the dominating CA is not dominated itself.
+// Of several identical CA specifications, the first one is kept.
+#[cfg(feature = "std")]
+pub fn find_dominated(ca_specs: &[&CASpec]) -> Vec<(usize, usize)> {
+    let is_dominated_by = |i: usize, j: usize| {
+        i != j && ca_specs[j].dominates(ca_specs[i]) &&
+            // Mutual dominance means the specifications are identical
+            !(j > i && ca_specs[i].dominates(ca_specs[j]))
+    };
+    let dominated : Vec<bool> = (0..ca_specs.len())
+        .map(|i| (0..ca_specs.len()).any(|j| is_dominated_by(i, j)))
+        .collect();
+
+    // Domination is transitive, so each dominated CA is dominated by a surviving one
+    (0..ca_specs.len())
+        .filter(|i| dominated[*i])
+        .map(|i| (i, (0..ca_specs.len()).find(|j| !dominated[*j] && is_dominated_by(i, *j)).unwrap()))
+        .collect()
+}
+
+// The primitive data type used to hold compressed data for bit shifts
+pub type CompressionChunk = u64;
+pub type Value = u16;
+#[cfg(feature = "std")]
+pub type Row = Vec<Value>;
+
+// Decodes the rows of a compressed CA.
+// Rows can either be iterated one by one, or decoded in batches into a caller-provided buffer
+// with `read_rows`, which does not allocate per row and is much faster for large CAs.
+#[cfg(feature = "std")]
+pub struct CompressedCA<R: Read> {
+    // Reader for our underlying data
+    reader: R,
+    // The stored chunk for bit shift operations
+    chunk: CompressionChunk,
+    // Number of bits in the chunk that have not been decoded yet
+    bits_left: u32,
+    // Total number of rows
+    rows_total: u64,
+    // Current row
+    row_current: u64,
+    // Bit sizes for each value in the row
+    bit_sizes: Vec<u8>,
+    // The version of compressed data we're handling
+    ca2_version: CA2Version,
+    // Raw bytes of Bzip2 data, reused across batches
+    buf: Vec<u8>,
+    // Set once reading failed, so that the error is only returned once
+    failed: bool,
+    // Whether every row has to be a permutation of 0..k, as rows of SCAs are
+    permutations: bool,
+    // Events seen in the current row while checking permutations, reused across rows
+    seen: Vec<bool>
+}
+
+#[cfg(feature = "std")]
+impl<R: Read> CompressedCA<R> {
+    pub fn new(reader: R, bit_sizes: Vec<u8>, rows_total: u64, ca2_version: CA2Version) -> CompressedCA<R> {
+        CompressedCA {
+            reader,
+            rows_total,
+            chunk: 0,
+            bits_left: 0,
+            row_current: 0,
+            bit_sizes,
+            ca2_version,
+            buf: Vec::new(),
+            failed: false,
+            permutations: false,
+            seen: Vec::new()
+        }
+    }
+
+    // Makes `read_rows` fail with a format error on rows that are no permutation of 0..k,
+    // so that corrupted SCAs are rejected before their rows are translated
+    pub fn require_permutations(&mut self) {
+        self.permutations = true;
+    }
+
+    // The number of values in each row
+    pub fn row_len(&self) -> usize {
+        self.bit_sizes.len()
+    }
+
+    // The number of rows that have not been decoded yet
+    pub fn rows_remaining(&self) -> u64 {
+        self.rows_total - self.row_current
+    }
+
+    // Decodes as many rows as fit into `out`, one after the other, and returns their number.
+    // Zero is returned once all rows have been decoded, or after an error has been returned.
+    pub fn read_rows(&mut self, out: &mut [Value]) -> Ca2Result<usize> {
+        if self.failed {
+            return Ok(0);
+        }
+        let row_len = self.row_len();
+        let rows = match row_len {
+            // Rows without values occupy no data at all
+            0 => usize::MAX,
+            _ => out.len() / row_len
+        };
+        let rows = rows.min(usize::try_from(self.rows_remaining()).unwrap_or(usize::MAX));
+        let values = &mut out[..rows * row_len];
+
+        let res = match self.ca2_version {
+            _ if values.is_empty() => Ok(()),
+            CA2Version::Basic => self.unpack_basic(values),
+            CA2Version::Bzip2 | CA2Version::Extended => self.unpack_bzip2(values)
+        };
+        let res = res.map_err(Ca2Error::from).and_then(|_| match self.permutations {
+            true => self.check_permutations(&out[..rows * row_len]),
+            false => Ok(())
+        });
+        if let Err(e) = res {
+            self.failed = true;
+            return Err(e);
+        }
+        self.row_current += rows as u64;
+        Ok(rows)
+    }
+
+    fn check_permutations(&mut self, values: &[Value]) -> Ca2Result<()> {
+        let row_len = self.row_len();
+        for (i, row) in values.chunks_exact(row_len.max(1)).enumerate() {
+            self.seen.clear();
+            self.seen.resize(row_len, false);
+            for event in row.iter() {
+                match self.seen.get_mut(*event as usize) {
+                    Some(seen) if !*seen => *seen = true,
+                    _ => return Err(Ca2Error::Format(format!(
+                        "Row {} of the sequence covering array is not a permutation of its events", self.row_current + i as u64 + 1
+                    )))
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Bzip2 data stores each value as a big endian u16
+    fn unpack_bzip2(&mut self, out: &mut [Value]) -> std::io::Result<()> {
+        self.buf.resize(out.len() * 2, 0);
+        self.reader.read_exact(&mut self.buf)?;
+        for (value, bytes) in out.iter_mut().zip(self.buf.chunks_exact(2)) {
+            *value = u16::from_be_bytes([bytes[0], bytes[1]]);
+        }
+        Ok(())
+    }
+
+    // Basic data packs values with the bit sizes of their parameters into big endian chunks.
+    // Values may span two chunks.
+    fn unpack_basic(&mut self, out: &mut [Value]) -> std::io::Result<()> {
+        for row in out.chunks_exact_mut(self.row_len()) {
+            for (i, value) in row.iter_mut().enumerate() {
+                *value = self.read_bits(self.bit_sizes[i] as u32)? as Value;
+            }
+        }
+        Ok(())
+    }
+
+    #[inline]
+    fn read_bits(&mut self, bits: u32) -> std::io::Result<CompressionChunk> {
+        if bits <= self.bits_left {
+            // Fast path: The whole value is in the current chunk
+            self.bits_left -= bits;
+            return Ok(self.chunk.checked_shr(self.bits_left).unwrap_or(0) & mask(bits));
+        }
+        // Take the rest of the current chunk as the high bits, and the remaining bits from the next one
+        let high = self.chunk & mask(self.bits_left);
+        let missing = bits - self.bits_left;
+        self.fill_chunk()?;
+        self.bits_left = CompressionChunk::BITS - missing;
+        Ok(high.checked_shl(missing).unwrap_or(0) | (self.chunk >> self.bits_left))
+    }
+
+    fn fill_chunk(&mut self) -> std::io::Result<()> {
+        let mut buf = [0; (CompressionChunk::BITS/8) as usize];
+        self.reader.read_exact(&mut buf)?;
+        self.chunk = CompressionChunk::from_be_bytes(buf);
+        Ok(())
+    }
+}
+
+// A mask for the lowest `bits` bits of a chunk
+#[cfg(feature = "std")]
+#[inline]
+fn mask(bits: u32) -> CompressionChunk {
+    CompressionChunk::MAX.checked_shr(CompressionChunk::BITS - bits).unwrap_or(0)
+}
+
+// Returns rows from a compressed CA.
+// If the data ends early or cannot be read, an error is returned once, and then nothing.
+#[cfg(feature = "std")]
+impl<R: Read> Iterator for CompressedCA<R> {
+    type Item = Ca2Result<Row>;
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.rows_remaining() == 0 {
+            return None;
+        }
+        let mut row = vec![0; self.row_len()];
+        match self.read_rows(&mut row) {
+            Ok(_) => Some(Ok(row)),
+            Err(e) => Some(Err(e))
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+pub fn generate_bit_sizes(vs_out: &Vec<u16>) -> Vec<u8> {
+    let mut bit_sizes = Vec::with_capacity(vs_out.len());
+    for v in vs_out {
+        bit_sizes.push(std::cmp::max(1, (*v as f64).log2().ceil() as u8));
+    }
+    bit_sizes
+}
+
+// The reorder map contains a mapping of requested parameters to stored parameters.
+// reorder_map[i] points to the index of the requested parameter i in a decoded row.
+#[cfg(feature = "std")]
+pub fn generate_reorder_map(requested_parameter_sizes: &[u16], stored_ca_spec: &CASpec) -> Ca2Result<Vec<usize>> {
+    // Reorder map is initially filled with impossible indices (can only go from 0 to vs.len()-1)
+    let mut reorder_map = vec![stored_ca_spec.vs.len(); requested_parameter_sizes.len()];
+    // This is an enumerated version of requested_parameter_sizes, sorted desc by parameter size
+    let mut sorted_requested_parameter_sizes : Vec<(usize, &u16)> = requested_parameter_sizes.iter().enumerate().collect();
+    sorted_requested_parameter_sizes.sort_by(|(_i_a, a), (_i_b, b)| b.cmp(a));
+
+    for (req_idx, req_size) in sorted_requested_parameter_sizes.iter() {
+        let (mapping, _v) = stored_ca_spec
+                .vs.iter().enumerate()
+                .find(|(i, v)| req_size <= v && !reorder_map.contains(i))
+                .ok_or_else(|| Ca2Error::InvalidArgument(format!("The request does not fit {}", stored_ca_spec)))?;
+        reorder_map[*req_idx] = mapping;
+    }
+    Ok(reorder_map)
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+
+    // The per-value decoder of Basic data that `unpack_basic` replaced, kept as a reference.
+    // Its shift is widened, since the original one overflowed for 16 bit values.
+    fn decode_per_value(data: &[u8], bit_sizes: &[u8], n: usize) -> Vec<Row> {
+        let mut chunks = data.chunks_exact(8).map(|c| CompressionChunk::from_be_bytes(c.try_into().unwrap()));
+        let mut chunk = 0;
+        let mut pos = 0;
+        let mut rows = Vec::with_capacity(n);
+        for _ in 0..n {
+            let mut row = vec![0; bit_sizes.len()];
+            for (value, bits) in row.iter_mut().zip(bit_sizes.iter()) {
+                let mut bits_remaining = *bits;
+                while bits_remaining > 0 {
+                    if pos == 0 {
+                        chunk = chunks.next().unwrap();
+                    }
+                    let bits_available = bits_remaining.min(CompressionChunk::BITS as u8 - pos);
+                    pos += bits_available;
+                    *value = (((*value as u32) << bits_available) as u16)
+                        | (chunk.rotate_left(pos as u32) & (CompressionChunk::MAX >> (CompressionChunk::BITS - bits_available as u32))) as u16;
+                    bits_remaining -= bits_available;
+                    if pos as u32 == CompressionChunk::BITS {
+                        pos = 0;
+                    }
+                }
+            }
+            rows.push(row);
+        }
+        rows
+    }
+
+    // Packs rows like cca did for Basic data: Big endian chunks, filled from their most significant bit
+    pub(crate) fn pack(rows: &[Row], bit_sizes: &[u8]) -> Vec<u8> {
+        let mut bits : Vec<bool> = Vec::new();
+        for row in rows.iter() {
+            for (value, size) in row.iter().zip(bit_sizes.iter()) {
+                bits.extend((0..*size).rev().map(|bit| value >> bit & 1 == 1));
+            }
+        }
+        bits.resize(bits.len().div_ceil(64) * 64, false);
+        bits.chunks(8).map(|byte| byte.iter().fold(0, |acc, bit| acc << 1 | *bit as u8)).collect()
+    }
+
+    fn decode_in_batches(data: &[u8], bit_sizes: &[u8], n: usize, batch_rows: usize) -> Vec<Row> {
+        let mut compressed_ca = CompressedCA::new(data, bit_sizes.to_vec(), n as u64, CA2Version::Basic);
+        let mut out = vec![0; batch_rows * bit_sizes.len()];
+        let mut rows = Vec::new();
+        loop {
+            let read = compressed_ca.read_rows(&mut out).unwrap();
+            if read == 0 {
+                break;
+            }
+            rows.extend(out[..read * bit_sizes.len()].chunks_exact(bit_sizes.len()).map(|row| row.to_vec()));
+        }
+        rows
+    }
+
+    fn check_basic(rows: &[Row], bit_sizes: &[u8]) {
+        let data = pack(rows, bit_sizes);
+        assert_eq!(decode_per_value(&data, bit_sizes, rows.len()), rows);
+        for batch_rows in [1, 3, rows.len()] {
+            assert_eq!(decode_in_batches(&data, bit_sizes, rows.len(), batch_rows), rows, "{} rows per batch", batch_rows);
+        }
+    }
+
+    #[test]
+    fn basic_data_packed_by_hand() {
+        // 0001 0010 | 1111 0000, then padding up to the end of the chunk
+        let data = [0x12, 0xf0, 0, 0, 0, 0, 0, 0];
+        let rows = vec![vec![1, 2], vec![15, 0]];
+        assert_eq!(decode_per_value(&data, &[4, 4], 2), rows);
+        assert_eq!(decode_in_batches(&data, &[4, 4], 2, 1), rows);
+        assert_eq!(CASpec { version: CA2Version::Basic, kind: CAKind::Covering, n: 2, t: 2, vs: vec![16, 16] }.basic_data_length(), Some(8));
+    }
+
+    #[test]
+    fn basic_values_spanning_two_chunks() {
+        // Rows of 29 bits, so values keep crossing chunk boundaries at different bits
+        let bit_sizes = [7, 9, 13];
+        let rows : Vec<Row> = (0..40u16).map(|i| vec![i * 3 % 128, (i * 37 + 5) % 512, (i * 1031 + 17) % 8192]).collect();
+        check_basic(&rows, &bit_sizes);
+        // Single bits and full u16 values
+        let bit_sizes = [1, 16, 3];
+        let rows : Vec<Row> = (0..30u16).map(|i| vec![i % 2, i.wrapping_mul(2731) ^ 0xa5a5, i % 8]).collect();
+        check_basic(&rows, &bit_sizes);
+    }
+
+    #[test]
+    fn basic_rows_ending_on_chunk_boundaries() {
+        // 64 bits per row, and 16 bits per row (four rows per chunk)
+        check_basic(&(0..5u16).map(|i| vec![u16::MAX - i, i, 0x8000 | i, 0x7fff]).collect::<Vec<_>>(), &[16, 16, 16, 16]);
+        check_basic(&(0..9u16).map(|i| vec![i % 32, 2047 - i]).collect::<Vec<_>>(), &[5, 11]);
+    }
+
+    #[test]
+    fn truncated_basic_data() {
+        let rows : Vec<Row> = (0..4u16).map(|i| vec![i, i]).collect();
+        let data = pack(&rows, &[16, 16]);
+        let mut compressed_ca = CompressedCA::new(&data[..8], vec![16, 16], 4, CA2Version::Basic);
+        let mut out = vec![0; 8];
+        assert!(compressed_ca.read_rows(&mut out).is_err());
+        assert_eq!(compressed_ca.read_rows(&mut out).unwrap(), 0);
+    }
+}
@@ -1,26 +1,26 @@
 use pest::{Parser};
 use pest::iterators::Pairs;
 use pest_derive::Parser;
-use crate::{RequestedCA, CASpec, CA2Version};
+use crate::{RequestedCA, CASpec, CA2Version, CAKind, Ca2Error, Ca2Result};
+use super::parameter_size;
 
 #[derive(Parser)]
 #[grammar = "acts.pest"]
 struct ActsParser;
 
-pub fn try_parse_acts(contents: &str, strength: u8) -> Option<RequestedCA> {
-    let acts_result = ActsParser::parse(Rule::file, &contents);
-    if acts_result.is_ok() {
+pub fn try_parse_acts(contents: &str, strength: u8) -> Ca2Result<RequestedCA> {
+    match ActsParser::parse(Rule::file, contents) {
         // This is an ACTS file
-        return Some(parse_acts(acts_result.unwrap(), strength));
+        Ok(acts_result) => parse_acts(acts_result, strength),
+        Err(e) => Err(Ca2Error::Parse(format!("Not a valid ACTS file: {}", e)))
     }
-    None
 }
 
-fn parse_acts(pairs: Pairs<Rule>, strength: u8) -> RequestedCA {
+fn parse_acts(pairs: Pairs<Rule>, strength: u8) -> Ca2Result<RequestedCA> {
     // Data for RequestedCA
     let mut first_parameter = true;
     let mut current_parameter_values = Vec::new();
-    let mut parameter_names = Vec::new();
+    let mut parameter_names : Vec<String> = Vec::new();
     let mut parameter_values = Vec::new();
     let mut parameter_sizes = Vec::new();
 
@@ -42,8 +42,8 @@ fn parse_acts(pairs: Pairs<Rule>, strength: u8) -> RequestedCA {
                                                         if first_parameter {
                                                             first_parameter = false;
                                                         } else {
-                                                            parameter_sizes.push(current_parameter_values.len() as u16);
-                                                            parameter_values.push(current_parameter_values.clone());
+                                                            parameter_sizes.push(parameter_size(&parameter_names[parameter_names.len()-1], &current_parameter_values)?);
+                                                            parameter_values.push(current_parameter_values.clone().into());
                                                             current_parameter_values = Vec::new();
                                                         }
                                                         parameter_names.push(parameter_token.as_str().to_string());
@@ -56,28 +56,28 @@ fn parse_acts(pairs: Pairs<Rule>, strength: u8) -> RequestedCA {
                                                             }
                                                         }
                                                     },
-                                                    _ => unreachable!()
+                                                    rule => return Err(unexpected(rule))
                                                 }
                                             }
                                         }
                                     }
 
                                     // Last parameter
-                                    if current_parameter_values.len() > 0 {
-                                        parameter_sizes.push(current_parameter_values.len() as u16);
-                                        parameter_values.push(current_parameter_values.clone());
+                                    if !first_parameter {
+                                        parameter_sizes.push(parameter_size(&parameter_names[parameter_names.len()-1], &current_parameter_values)?);
+                                        parameter_values.push(current_parameter_values.clone().into());
                                     }
                                 }
                             }
                             Rule::constraint_section => eprintln!("ACTS Parser Warning: Constraints are unsupported."),
                             Rule::test_set_section => eprintln!("ACTS Parser Warning: Predefined test sets are unsupported."),
                             Rule::relation_section => eprintln!("ACTS Parser Warning: Relations/VCAs are unsupported."),
-                            _ => unreachable!("ACTS Parser Warning: Unknown section {:?}", section.as_rule())
+                            rule => return Err(unexpected(rule))
                         }
                     }
                 },
                 Rule::EOI => {},
-                _ => unreachable!("{:?}", inner_pair.as_rule())
+                rule => return Err(unexpected(rule))
             };
         }
     }
@@ -86,15 +86,21 @@ fn parse_acts(pairs: Pairs<Rule>, strength: u8) -> RequestedCA {
     let mut vs = parameter_sizes.clone();
     vs.sort_by(|a, b| b.cmp(a));
 
-    RequestedCA {
+    Ok(RequestedCA {
         parameter_names,
         parameter_values,
         parameter_sizes,
         ca_spec: CASpec {
             version: CA2Version::default(),
+            kind: CAKind::Covering,
             n: 0,
             t: strength,
             vs
         }
-    }
+    })
+}
+
+// The grammar guarantees that this never happens, but we'd rather not panic
+fn unexpected(rule: Rule) -> Ca2Error {
+    Ca2Error::Parse(format!("Unexpected element {:?} in ACTS file", rule))
 }
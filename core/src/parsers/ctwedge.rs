@@ -1,26 +1,27 @@
+use std::sync::Arc;
 use pest::Parser;
 use pest::iterators::Pairs;
 use pest_derive::Parser;
-use crate::{RequestedCA, CASpec, CA2Version};
+use crate::{RequestedCA, CASpec, CA2Version, CAKind, Ca2Error, Ca2Result};
+use super::parameter_size;
 
 #[derive(Parser)]
 #[grammar = "ctwedge.pest"]
 struct CTWedgeParser;
 
-pub fn try_parse_ctwedge(contents: &str, strength: u8) -> Option<RequestedCA> {
-    let ctwedge_result = CTWedgeParser::parse(Rule::cit_model, &contents);
-    if ctwedge_result.is_ok() {
-        return Some(parse_ctwedge(ctwedge_result.unwrap(), strength));
+pub fn try_parse_ctwedge(contents: &str, strength: u8) -> Ca2Result<RequestedCA> {
+    match CTWedgeParser::parse(Rule::cit_model, contents) {
+        Ok(ctwedge_result) => parse_ctwedge(ctwedge_result, strength),
+        Err(e) => Err(Ca2Error::Parse(format!("Not a valid CTWedge file: {}", e)))
     }
-    None
 }
 
-fn parse_ctwedge(pairs: Pairs<Rule>, strength: u8) -> RequestedCA {
+fn parse_ctwedge(pairs: Pairs<Rule>, strength: u8) -> Ca2Result<RequestedCA> {
     // Data for RequestedCA
     let mut first_parameter = true;
     let mut current_parameter_values : Vec<String> = Vec::new();
-    let mut parameter_names = Vec::new();
-    let mut parameter_values : Vec<Vec<String>>= Vec::new();
+    let mut parameter_names : Vec<String> = Vec::new();
+    let mut parameter_values : Vec<Arc<[String]>> = Vec::new();
     let mut parameter_sizes : Vec<u16> = Vec::new();
 
     // Traverse parse result to extract data for RequestedCA
@@ -39,8 +40,8 @@ fn parse_ctwedge(pairs: Pairs<Rule>, strength: u8) -> RequestedCA {
                                                 if first_parameter {
                                                     first_parameter = false;
                                                 } else {
-                                                    parameter_sizes.push(current_parameter_values.len() as u16);
-                                                    parameter_values.push(current_parameter_values.clone());
+                                                    parameter_sizes.push(parameter_size(&parameter_names[parameter_names.len()-1], &current_parameter_values)?);
+                                                    parameter_values.push(current_parameter_values.clone().into());
                                                     current_parameter_values = Vec::new();
                                                 }
                                                 parameter_names.push(enum_part.as_str().to_string());
@@ -52,7 +53,7 @@ fn parse_ctwedge(pairs: Pairs<Rule>, strength: u8) -> RequestedCA {
                                                     }
                                                 }
                                             },
-                                            _ => unreachable!("{:?}", enum_part.as_rule())
+                                            rule => return Err(unexpected(rule))
                                         }
                                     }
                                 },
@@ -63,8 +64,8 @@ fn parse_ctwedge(pairs: Pairs<Rule>, strength: u8) -> RequestedCA {
                                                 if first_parameter {
                                                     first_parameter = false;
                                                 } else {
-                                                    parameter_sizes.push(current_parameter_values.len() as u16);
-                                                    parameter_values.push(current_parameter_values.clone());
+                                                    parameter_sizes.push(parameter_size(&parameter_names[parameter_names.len()-1], &current_parameter_values)?);
+                                                    parameter_values.push(current_parameter_values.clone().into());
                                                     current_parameter_values = Vec::new();
                                                 }
                                                 parameter_names.push(bool_part.as_str().to_string());
@@ -73,15 +74,17 @@ fn parse_ctwedge(pairs: Pairs<Rule>, strength: u8) -> RequestedCA {
                                                 current_parameter_values.push(String::from("true"));
                                                 current_parameter_values.push(String::from("false"));
                                             },
-                                            _ => unreachable!("{:?}", bool_part.as_rule())
+                                            rule => return Err(unexpected(rule))
 
                                         }
                                     }
                                 },
                                 Rule::range => {
-                                    unimplemented!("The CTWedge range notation is currently unimplemented.");
+                                    return Err(Ca2Error::Parse(String::from(
+                                        "The CTWedge range notation is currently unimplemented."
+                                    )));
                                 },
-                                _ => unreachable!("{:?}", param_kind.as_rule())
+                                rule => return Err(unexpected(rule))
                             }
                         }
                     }
@@ -90,23 +93,36 @@ fn parse_ctwedge(pairs: Pairs<Rule>, strength: u8) -> RequestedCA {
                     eprintln!("CTWedge Parser Warning: Constraints are unsupported.");
                 }
                 Rule::EOI => {},
-                _ => unreachable!("{:?}", inner_pair.as_rule())
+                rule => return Err(unexpected(rule))
             }
         }
     }
+
+    // Last parameter
+    if !first_parameter {
+        parameter_sizes.push(parameter_size(&parameter_names[parameter_names.len()-1], &current_parameter_values)?);
+        parameter_values.push(current_parameter_values.into());
+    }
+
     // Derive the sorted (descending) parameter sizes
     let mut vs = parameter_sizes.clone();
     vs.sort_by(|a, b| b.cmp(a));
 
-    RequestedCA {
+    Ok(RequestedCA {
         parameter_names,
         parameter_values,
         parameter_sizes,
         ca_spec: CASpec {
             version: CA2Version::default(),
+            kind: CAKind::Covering,
             n: 0,
             t: strength,
             vs
         }
-    }
+    })
+}
+
+// The grammar guarantees that this never happens, but we'd rather not panic
+fn unexpected(rule: Rule) -> Ca2Error {
+    Ca2Error::Parse(format!("Unexpected element {:?} in CTWedge file", rule))
 }
@@ -0,0 +1,77 @@
+use std::sync::Arc;
+use pest::Parser;
+use pest::iterators::Pairs;
+use pest_derive::Parser;
+use crate::{RequestedCA, CASpec, Ca2Error, Ca2Result};
+
+#[derive(Parser)]
+#[grammar = "events.pest"]
+struct EventsParser;
+
+// Event lists describe a request for a sequence covering array, e.g.:
+//
+// Model Login
+// Events: open, authenticate, read, close
+pub fn try_parse_events(contents: &str, strength: u8) -> Ca2Result<RequestedCA> {
+    match EventsParser::parse(Rule::event_model, contents) {
+        Ok(events_result) => parse_events(events_result, strength),
+        Err(e) => Err(Ca2Error::Parse(format!("Not a valid event list: {}", e)))
+    }
+}
+
+fn parse_events(pairs: Pairs<Rule>, strength: u8) -> Ca2Result<RequestedCA> {
+    let mut events = Vec::new();
+
+    // Traverse parse result to extract the event names
+    for pair in pairs {
+        match pair.as_rule() {
+            Rule::model_spec => { /* Model name, ignored */ },
+            Rule::event_spec => {
+                for events_part in pair.into_inner() {
+                    for event in events_part.into_inner() {
+                        if event.as_rule() == Rule::event {
+                            let name = event.as_str().to_string();
+                            if events.contains(&name) {
+                                return Err(Ca2Error::Parse(format!("Event {} appears more than once in the event list", name)));
+                            }
+                            events.push(name);
+                        }
+                    }
+                }
+            },
+            Rule::EOI => {},
+            rule => return Err(Ca2Error::Parse(format!("Unexpected element {:?} in event list", rule)))
+        }
+    }
+
+    // Each output column is a position in the sequence, and each of them
+    // can hold any of the events. This allows translating the stored
+    // event indices to event names just like ordinary parameter values.
+    // All columns share one list of the events.
+    let num_events = u16::try_from(events.len())
+        .map_err(|_| Ca2Error::Parse(format!("Event lists are limited to {} events", u16::MAX)))?;
+    let parameter_names = (1..=events.len()).map(|i| format!("step_{}", i)).collect();
+    let parameter_values = vec![Arc::from(events); num_events as usize];
+    let parameter_sizes = vec![num_events; num_events as usize];
+
+    Ok(RequestedCA {
+        parameter_names,
+        parameter_values,
+        parameter_sizes,
+        ca_spec: CASpec::new_sequence(0, strength, num_events)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn columns_share_the_events() {
+        let requested_ca = try_parse_events("Model Login\nEvents: open, authenticate, read, close\n", 3).unwrap();
+        assert_eq!(requested_ca.parameter_names, ["step_1", "step_2", "step_3", "step_4"]);
+        assert_eq!(requested_ca.parameter_sizes, [4, 4, 4, 4]);
+        assert_eq!(&requested_ca.parameter_values[0][..], ["open", "authenticate", "read", "close"]);
+        assert!(requested_ca.parameter_values.iter().all(|values| Arc::ptr_eq(values, &requested_ca.parameter_values[0])));
+    }
+}
@@ -0,0 +1,30 @@
+
+pub mod acts;
+pub mod ctwedge;
+pub mod events;
+
+use crate::{RequestedCA, Ca2Error, Ca2Result};
+
+// Parses an input model in any of the supported formats (ACTS, CTWedge or an event list)
+pub fn parse_model(contents: &str, strength: u8) -> Ca2Result<RequestedCA> {
+    let errors = match (acts::try_parse_acts(contents, strength), ctwedge::try_parse_ctwedge(contents, strength)) {
+        (Ok(requested_ca), _) | (_, Ok(requested_ca)) => return Ok(requested_ca),
+        (Err(acts_error), Err(ctwedge_error)) => [acts_error, ctwedge_error]
+    };
+    match events::try_parse_events(contents, strength) {
+        Ok(requested_ca) => Ok(requested_ca),
+        Err(events_error) => Err(Ca2Error::Parse(format!(
+            "This input file format is not supported.\n{}\n{}\n{}", errors[0], errors[1], events_error
+        )))
+    }
+}
+
+// The number of values of a parameter, which has to fit into the stored CA metadata
+pub(crate) fn parameter_size(name: &str, values: &[String]) -> Ca2Result<u16> {
+    match u16::try_from(values.len()) {
+        Ok(0) | Err(_) => Err(Ca2Error::Parse(format!(
+            "Parameter {} must have between 1 and {} values", name, u16::MAX
+        ))),
+        Ok(size) => Ok(size)
+    }
+}
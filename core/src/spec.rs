@@ -0,0 +1,199 @@
+use std::str::FromStr;
+use crate::{CASpec, CAKind, CA2Version, Ca2Error, Ca2Result};
+
+// Upper limit for the number of parameters in a specification, so that ones like 2^99999999 are rejected
+pub const MAX_SPEC_PARAMETERS : usize = u16::MAX as usize;
+
+// A CA specification in one of the common notations:
+//
+// t=3, 5^2 3^4 2^10      exponential notation; N can be given as n=..., t can also be left out
+// CA(9;2,3^4)            N = 9, t = 2, like in the Colbourn tables
+// CA(9;2,4,3)            N = 9, t = 2, k = 4, v = 3
+// MCA(12;2,3^2 2^2)      mixed covering arrays; groups can be separated by spaces, `_`, `-`, `,` or `.`
+// SCA(6;3,4)             sequence covering array of 4 events
+//
+// N is optional in the notations with parentheses, e.g. CA(2,3^4), and the specifications CASpec
+// displays are accepted as well. Numbers of values are kept in the order they are written in.
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct SpecNotation {
+    pub n: Option<u64>,
+    pub t: Option<u8>,
+    pub kind: CAKind,
+    pub vs: Vec<u16>
+}
+
+impl FromStr for SpecNotation {
+    type Err = Ca2Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_notation(&s.trim().to_ascii_lowercase())
+            .ok_or_else(|| Ca2Error::Parse(format!("Invalid CA specification: {}", s)))
+    }
+}
+
+impl SpecNotation {
+    // The CA specification with the numbers of values sorted descending.
+    // The strength has to be stated by the notation or given as `t`, a missing N becomes 0.
+    pub fn into_ca_spec(self, t: Option<u8>) -> Ca2Result<CASpec> {
+        let t = match (self.t, t) {
+            (Some(stated), Some(t)) if stated != t => return Err(Ca2Error::InvalidArgument(format!(
+                "The CA specification states strength {}, but {} was given", stated, t
+            ))),
+            (Some(t), _) | (None, Some(t)) => t,
+            (None, None) => return Err(Ca2Error::InvalidArgument(String::from("The CA specification does not state the strength")))
+        };
+        let n = self.n.unwrap_or(0);
+        if self.kind == CAKind::Sequence {
+            return Ok(CASpec::new_sequence(n, t, self.vs.len() as u16));
+        }
+        let mut vs = self.vs;
+        vs.sort_by(|a, b| b.cmp(a));
+        Ok(CASpec { version: CA2Version::default(), kind: self.kind, n, t, vs })
+    }
+}
+
+// Parses any of the notations of `SpecNotation`
+impl FromStr for CASpec {
+    type Err = Ca2Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.parse::<SpecNotation>()?.into_ca_spec(None)
+    }
+}
+
+fn parse_notation(s: &str) -> Option<SpecNotation> {
+    let (kind, inner) = match s.strip_prefix("sca(") {
+        Some(inner) => (CAKind::Sequence, inner),
+        None => match s.strip_prefix("ca(").or_else(|| s.strip_prefix("mca(")) {
+            Some(inner) => (CAKind::Covering, inner),
+            None => return parse_exponential(s)
+        }
+    };
+    let inner = inner.strip_suffix(')')?;
+    let (n, rest) = match inner.split_once(';') {
+        Some((n, rest)) => (Some(n.trim().parse().ok()?), rest),
+        None => (None, inner)
+    };
+    let (t, rest) = rest.split_once(',')?;
+    let vs = match kind {
+        CAKind::Sequence => {
+            let k : u16 = rest.trim().parse().ok()?;
+            expand(k, k.into())?
+        },
+        _ if rest.contains('^') => parse_groups(rest)?,
+        _ => {
+            // k, v
+            let (k, v) = rest.split_once(',')?;
+            expand(v.trim().parse().ok()?, k.trim().parse().ok()?)?
+        }
+    };
+    Some(SpecNotation { n, t: Some(t.trim().parse().ok()?), kind, vs })
+}
+
+// Parses "t=3, 5^2 3^4 2^10"
+fn parse_exponential(s: &str) -> Option<SpecNotation> {
+    // Whitespace around `=` is dropped, so that `t = 2` is a single token
+    let s = s.split('=').map(str::trim).collect::<Vec<&str>>().join("=");
+    let (mut n, mut t) = (None, None);
+    let mut groups = Vec::new();
+    for token in split_groups(&s) {
+        if let Some(value) = token.strip_prefix("t=") {
+            t = Some(value.parse().ok()?);
+        } else if let Some(value) = token.strip_prefix("n=") {
+            n = Some(value.parse().ok()?);
+        } else {
+            groups.push(token);
+        }
+    }
+    Some(SpecNotation { n, t, kind: CAKind::Covering, vs: parse_groups(&groups.join(" "))? })
+}
+
+fn split_groups(groups: &str) -> impl Iterator<Item = &str> {
+    groups.split(|c: char| c.is_whitespace() || "_-,.".contains(c)).filter(|g| !g.is_empty())
+}
+
+// Parses groups like "4^3 2^5" into [4, 4, 4, 2, 2, 2, 2, 2]
+pub fn parse_groups(groups: &str) -> Option<Vec<u16>> {
+    let mut vs = Vec::new();
+    for group in split_groups(groups) {
+        let (v, k) = group.split_once('^')?;
+        vs.extend(expand(v.parse().ok()?, k.parse().ok()?)?);
+        if vs.len() > MAX_SPEC_PARAMETERS {
+            return None;
+        }
+    }
+    match vs.is_empty() {
+        true => None,
+        false => Some(vs)
+    }
+}
+
+fn expand(v: u16, k: usize) -> Option<Vec<u16>> {
+    match v > 0 && k > 0 && k <= MAX_SPEC_PARAMETERS {
+        true => Some(vec![v; k]),
+        false => None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notation(n: Option<u64>, t: Option<u8>, kind: CAKind, vs: &[u16]) -> SpecNotation {
+        SpecNotation { n, t, kind, vs: vs.to_vec() }
+    }
+
+    #[test]
+    fn notations_with_parentheses() {
+        let expected = notation(Some(9), Some(2), CAKind::Covering, &[3, 3, 3, 3]);
+        assert_eq!("CA(9; 2, 4, 3)".parse::<SpecNotation>().unwrap(), expected);
+        assert_eq!("CA(9;2,3^4)".parse::<SpecNotation>().unwrap(), expected);
+        assert_eq!(" ca(9;2,3^4) ".parse::<SpecNotation>().unwrap(), expected);
+        assert_eq!("MCA(12;2,3^2 2^2)".parse::<SpecNotation>().unwrap(),
+                   notation(Some(12), Some(2), CAKind::Covering, &[3, 3, 2, 2]));
+        assert_eq!("SCA(6;3,4)".parse::<SpecNotation>().unwrap(),
+                   notation(Some(6), Some(3), CAKind::Sequence, &[4, 4, 4, 4]));
+        // N is optional
+        assert_eq!("CA(2,3^4)".parse::<SpecNotation>().unwrap(), notation(None, Some(2), CAKind::Covering, &[3, 3, 3, 3]));
+    }
+
+    #[test]
+    fn exponential_notation() {
+        assert_eq!("t=3, 5^2 3^1 2^2".parse::<SpecNotation>().unwrap(),
+                   notation(None, Some(3), CAKind::Covering, &[5, 5, 3, 2, 2]));
+        assert_eq!("n = 15, t = 2, 2^3".parse::<SpecNotation>().unwrap(),
+                   notation(Some(15), Some(2), CAKind::Covering, &[2, 2, 2]));
+        assert_eq!("t =  2,  n  = 15 2^3".parse::<SpecNotation>().unwrap(),
+                   notation(Some(15), Some(2), CAKind::Covering, &[2, 2, 2]));
+        assert_eq!("t\t=\t2\t3^2".parse::<SpecNotation>().unwrap(), notation(None, Some(2), CAKind::Covering, &[3, 3]));
+        // Numbers of values are kept in the order they are written in
+        assert_eq!("2^1_3^2".parse::<SpecNotation>().unwrap(), notation(None, None, CAKind::Covering, &[2, 3, 3]));
+    }
+
+    #[test]
+    fn invalid_notations_are_rejected() {
+        for s in ["", "CA(9;2)", "CA(9;2,3^4", "CA(x;2,3^4)", "t=2", "t=2, 3^0", "t=2, 0^3", "2^99999999", "SCA(6;3,x)"] {
+            assert!(s.parse::<SpecNotation>().is_err(), "{} was accepted", s);
+        }
+    }
+
+    #[test]
+    fn displayed_specifications_are_parsed_sorted() {
+        let ca_spec : CASpec = "MCA(12;2,2^2 3^2)".parse().unwrap();
+        assert_eq!((ca_spec.kind, ca_spec.n, ca_spec.t, ca_spec.vs.clone()), (CAKind::Covering, 12, 2, vec![3, 3, 2, 2]));
+        let reparsed : CASpec = ca_spec.to_string().parse().unwrap();
+        assert_eq!((reparsed.n, reparsed.t, reparsed.vs), (12, 2, vec![3, 3, 2, 2]));
+
+        let sca : CASpec = "SCA(6;3,4)".parse().unwrap();
+        assert_eq!(sca.to_string().parse::<SpecNotation>().unwrap(), notation(Some(6), Some(3), CAKind::Sequence, &[4, 4, 4, 4]));
+    }
+
+    #[test]
+    fn strength_has_to_be_stated_once() {
+        let spec = |s: &str| s.parse::<SpecNotation>().unwrap();
+        assert_eq!(spec("3^4").into_ca_spec(Some(2)).unwrap().t, 2);
+        assert_eq!(spec("CA(9;2,3^4)").into_ca_spec(Some(2)).unwrap().t, 2);
+        assert!(matches!(spec("CA(9;2,3^4)").into_ca_spec(Some(3)), Err(Ca2Error::InvalidArgument(_))));
+        assert!(matches!(spec("3^4").into_ca_spec(None), Err(Ca2Error::InvalidArgument(_))));
+    }
+}
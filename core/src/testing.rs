@@ -0,0 +1,36 @@
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+// A fresh directory for the files of one test. It is removed when dropped, also when an assertion
+// fails, so tests that pass or fail leave nothing behind.
+pub struct TestDir(PathBuf);
+
+impl TestDir {
+    // `name` has to be unique among the tests of a crate, e.g. prefixed with the module
+    pub fn new(name: &str) -> TestDir {
+        let dir = std::env::temp_dir().join(format!("ca2-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        TestDir(dir)
+    }
+}
+
+impl Deref for TestDir {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for TestDir {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
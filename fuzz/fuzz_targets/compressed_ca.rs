@@ -0,0 +1,33 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use std::io::{BufReader, Cursor};
+use ca2::{CASpec, CompressedCA, setup_decoder, generate_bit_sizes};
+
+// Rows decoded per input at most, so that large values of N don't stall the fuzzer
+const MAX_ROWS : usize = 4096;
+// Rows decoded per batch
+const BATCH_ROWS : usize = 64;
+
+fuzz_target!(|data: &[u8]| {
+    // The input is a CA specification followed by the raw data of the CA
+    let Ok((ca_spec, len)) = CASpec::unserialize(data) else {
+        return;
+    };
+    let reader = BufReader::new(Cursor::new(data[len..].to_vec()));
+    let decoder = setup_decoder(&ca_spec, reader);
+    let mut compressed_ca = CompressedCA::new(decoder, generate_bit_sizes(&ca_spec.vs), ca_spec.n, ca_spec.version);
+
+    // Decode a single row first, then the rest in batches
+    match compressed_ca.next() {
+        Some(Ok(row)) => assert_eq!(row.len(), ca_spec.vs.len()),
+        _ => return
+    }
+    let mut buf = vec![0; BATCH_ROWS * ca_spec.vs.len()];
+    let mut rows_total = 1;
+    while rows_total < MAX_ROWS {
+        match compressed_ca.read_rows(&mut buf) {
+            Ok(0) | Err(_) => break,
+            Ok(rows) => rows_total = rows_total.saturating_add(rows)
+        }
+    }
+});
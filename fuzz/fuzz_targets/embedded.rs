@@ -0,0 +1,28 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use ca2::embedded::ArchiveSlice;
+
+// Rows decoded per entry at most, so that large values of N don't stall the fuzzer
+const MAX_ROWS : usize = 4096;
+
+fuzz_target!(|data: &[u8]| {
+    let Ok(archive) = ArchiveSlice::parse(data) else {
+        return;
+    };
+    for entry in archive.entries() {
+        let Ok(entry) = entry else {
+            break;
+        };
+        let mut row = vec![0; entry.k()];
+        let mut rows = match entry.rows() {
+            Ok(rows) => rows,
+            // Compressed data is decoded from the same bytes as if it had been decompressed
+            Err(_) => entry.uncompressed_rows(data)
+        };
+        for _ in 0..MAX_ROWS {
+            if !matches!(rows.next_row(&mut row), Ok(true)) {
+                break;
+            }
+        }
+    }
+});
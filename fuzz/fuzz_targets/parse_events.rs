@@ -0,0 +1,12 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use ca2::try_parse_events;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(contents) = std::str::from_utf8(data) {
+        if let Ok(requested_ca) = try_parse_events(contents, 2) {
+            assert_eq!(requested_ca.parameter_names.len(), requested_ca.parameter_values.len());
+            assert_eq!(requested_ca.parameter_sizes.len(), requested_ca.parameter_values.len());
+        }
+    }
+});
@@ -0,0 +1,14 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use std::io::Cursor;
+use ca2::read_index;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(index) = read_index(&mut Cursor::new(data)) {
+        // Validated entries never point past the index
+        assert!(index.metadata_offset < data.len() as u64);
+        for (offset, _) in index.entries.iter() {
+            assert!(*offset < index.metadata_offset);
+        }
+    }
+});
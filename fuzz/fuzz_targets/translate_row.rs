@@ -0,0 +1,41 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use std::io::{BufReader, Cursor};
+use ca2::{CASpec, CAKind, CompressedCA, RequestedCA, setup_decoder, generate_bit_sizes, generate_reorder_map};
+
+// Rows decoded per input at most, so that large values of N don't stall the fuzzer
+const MAX_ROWS : usize = 4096;
+
+fuzz_target!(|data: &[u8]| {
+    // The input is the number of requested parameters (or events), a CA specification
+    // and the raw data of the CA
+    let Some((requested, data)) = data.split_first() else {
+        return;
+    };
+    let Ok((ca_spec, len)) = CASpec::unserialize(data) else {
+        return;
+    };
+    let k = (*requested as usize).min(ca_spec.vs.len());
+    let requested_spec = match ca_spec.kind {
+        CAKind::Sequence => CASpec::new_sequence(0, ca_spec.t, k as u16),
+        _ => CASpec { vs: ca_spec.vs[..k].to_vec(), ..ca_spec.clone() }
+    };
+    let Ok(requested_ca) = RequestedCA::from_ca_spec(&requested_spec) else {
+        return;
+    };
+    let Ok(reorder_map) = generate_reorder_map(&requested_ca.parameter_sizes, &ca_spec) else {
+        return;
+    };
+
+    // Rows are translated without checking that rows of SCAs are permutations,
+    // so that translate_row has to cope with any decoded values
+    let reader = BufReader::new(Cursor::new(data[len..].to_vec()));
+    let decoder = setup_decoder(&ca_spec, reader);
+    let compressed_ca = CompressedCA::new(decoder, generate_bit_sizes(&ca_spec.vs), ca_spec.n, ca_spec.version);
+    for row in compressed_ca.take(MAX_ROWS) {
+        let Ok(row) = row else {
+            break;
+        };
+        assert!(requested_ca.translate_row(&row, &reorder_map).count() <= requested_ca.parameter_sizes.len());
+    }
+});
@@ -0,0 +1,11 @@
+#![no_main]
+use libfuzzer_sys::fuzz_target;
+use ca2::CASpec;
+
+fuzz_target!(|data: &[u8]| {
+    // Whatever was parsed has to serialize to the same bytes again
+    if let Ok((ca_spec, len)) = CASpec::unserialize(data) {
+        assert!(len <= data.len());
+        assert_eq!(ca_spec.serialize(), &data[..len]);
+    }
+});
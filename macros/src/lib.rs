@@ -0,0 +1,169 @@
+// Generates Rust tests from archived covering arrays at compile time.
+//
+// #[covering_test(archive = "cas.ca2", t = 3, model = "model.acts")]
+// fn test(p1: &str, p2: bool, p3: u32) { ... }
+//
+// expands into a module `test` that contains the function and one #[test] per row of the smallest
+// compatible CA, which call it with the values of that row. See covering_test for details.
+// The attribute is also available as ca2::covering_test with the `macros` feature of ca2.
+
+use std::fs::File;
+use std::path::PathBuf;
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, FnArg, ItemFn, LitInt, LitStr, Type};
+use ca2_core::{RequestedCA, Ca2Error, Ca2Result, find_archives, default_archive_locations, find_best_ca
+          , generate_reorder_map, open_ca, parse_model};
+
+// Number of rows that are decoded at once
+const DECODE_BATCH_ROWS : usize = 4096;
+
+// Arguments of the attribute
+#[derive(Default)]
+struct Args {
+    archive: Option<LitStr>,
+    t: Option<LitInt>,
+    model: Option<LitStr>
+}
+
+// Expands a function into one test per row of a covering array.
+//
+// Arguments:
+// - `model`: An ACTS or CTWedge model (or an event list), relative to the crate root
+// - `t`: The strength
+// - `archive`: An archive, a directory containing archives or a glob pattern, relative to the crate root.
+//   Defaults to the locations in $CA2_PATH.
+//
+// The function has to take one argument per parameter of the model, in the same order.
+// `&str` and `String` arguments receive the values as they are, arguments of any other type receive
+// them as Rust expressions, e.g. `true`, `42` or an enum variant that is in scope.
+// Other attributes of the function (like #[should_panic]) are applied to every generated test.
+// Changes of the model rebuild the tests, changes of the archives only if a build script tracks them.
+#[proc_macro_attribute]
+pub fn covering_test(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let mut args = Args::default();
+    let parser = syn::meta::parser(|meta| {
+        if meta.path.is_ident("archive") {
+            args.archive = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("t") {
+            args.t = Some(meta.value()?.parse()?);
+        } else if meta.path.is_ident("model") {
+            args.model = Some(meta.value()?.parse()?);
+        } else {
+            return Err(meta.error("Expected `archive`, `t` or `model`"));
+        }
+        Ok(())
+    });
+    parse_macro_input!(attr with parser);
+    let function = parse_macro_input!(item as ItemFn);
+
+    expand(args, function).unwrap_or_else(|e| e.to_compile_error()).into()
+}
+
+fn expand(args: Args, mut function: ItemFn) -> syn::Result<proc_macro2::TokenStream> {
+    let model = args.model.ok_or_else(|| syn::Error::new(Span::call_site(), "Missing argument `model`"))?;
+    let t = args.t.ok_or_else(|| syn::Error::new(Span::call_site(), "Missing argument `t`"))?;
+    let strength : u8 = t.base10_parse()?;
+
+    // Paths are relative to the crate that uses the macro
+    let root = PathBuf::from(std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default());
+    let model_path = root.join(model.value());
+    let contents = std::fs::read_to_string(&model_path)
+        .map_err(|e| syn::Error::new(model.span(), format!("Cannot read {}: {}", model_path.display(), e)))?;
+    let requested_ca = parse_model(&contents, strength).map_err(|e| syn::Error::new(model.span(), e))?;
+
+    let archive_span = args.archive.as_ref().map_or(Span::call_site(), |archive| archive.span());
+    let locations = match &args.archive {
+        Some(archive) => vec![root.join(archive.value())],
+        None => default_archive_locations()
+    };
+    let archive_files = find_archives(&locations).map_err(|e| syn::Error::new(archive_span, e))?;
+    let rows = decode_rows(&archive_files, &requested_ca).map_err(|e| syn::Error::new(archive_span, e))?;
+
+    // Translate the values into arguments, depending on the types the function expects
+    if function.sig.inputs.len() != requested_ca.parameter_names.len() {
+        return Err(syn::Error::new_spanned(&function.sig.inputs, format!(
+            "Expected one argument per parameter: {}", requested_ca.parameter_names.join(", ")
+        )));
+    }
+    let mut types = Vec::new();
+    for input in function.sig.inputs.iter() {
+        match input {
+            FnArg::Typed(pat_type) => types.push(pat_type.ty.as_ref()),
+            FnArg::Receiver(receiver) => return Err(syn::Error::new_spanned(receiver, "Methods are not supported"))
+        }
+    }
+    let mut tests = Vec::with_capacity(rows.len());
+    let width = rows.len().saturating_sub(1).to_string().len();
+    let name = &function.sig.ident;
+    let output = &function.sig.output;
+    let attrs = std::mem::take(&mut function.attrs);
+    for (i, row) in rows.iter().enumerate() {
+        let values = row.iter().zip(types.iter()).enumerate()
+            .map(|(p, (value, ty))| argument(value, ty, &requested_ca.parameter_names[p]))
+            .collect::<syn::Result<Vec<_>>>()?;
+        let test_name = format_ident!("row_{}", format!("{:0width$}", i, width = width));
+        tests.push(quote! {
+            #(#attrs)*
+            #[test]
+            fn #test_name() #output {
+                #name(#(#values),*)
+            }
+        });
+    }
+
+    // Including the model makes cargo rebuild the tests when it changes. Archives can be large (and there
+    // may be many of them on $CA2_PATH), so they are not embedded; see the README for tracking them in build.rs.
+    // TODO: Track both with proc_macro::tracked_path once it is stable
+    let model_dependency = model_path.canonicalize().unwrap_or(model_path).display().to_string();
+    let vis = &function.vis;
+    Ok(quote! {
+        #vis mod #name {
+            #[allow(unused_imports)]
+            use super::*;
+
+            const _ : &[u8] = include_bytes!(#model_dependency);
+
+            #function
+
+            #(#tests)*
+        }
+    })
+}
+
+// Turns a value into an argument of the given type
+fn argument(value: &str, ty: &Type, parameter: &str) -> syn::Result<proc_macro2::TokenStream> {
+    let literal = LitStr::new(value, Span::call_site());
+    match ty {
+        Type::Reference(reference) if matches!(reference.elem.as_ref(), Type::Path(p) if p.path.is_ident("str")) => {
+            Ok(quote! { #literal })
+        },
+        Type::Path(p) if p.path.is_ident("String") => Ok(quote! { ::std::string::String::from(#literal) }),
+        _ => syn::parse_str::<syn::Expr>(value).map(|expr| quote! { #expr }).map_err(|_| syn::Error::new_spanned(
+            ty, format!("Value `{}` of parameter {} is not a Rust expression, use &str instead", value, parameter)
+        ))
+    }
+}
+
+// Decodes the smallest CA compatible with the request and translates its rows, just like dca
+fn decode_rows(archive_files: &[PathBuf], requested_ca: &RequestedCA) -> Ca2Result<Vec<Vec<String>>> {
+    let (file, offset, ca_spec) = find_best_ca(archive_files, &requested_ca.ca_spec)?
+        .ok_or_else(|| Ca2Error::InvalidArgument(String::from("No compatible CA found in archives")))?;
+    let reorder_map = generate_reorder_map(&requested_ca.parameter_sizes, &ca_spec)?;
+
+    let mut compressed_ca = open_ca(File::open(file)?, offset, &ca_spec)?;
+    let row_len = compressed_ca.row_len().max(1);
+    let mut buf = vec![0; DECODE_BATCH_ROWS * row_len];
+
+    let mut rows = Vec::new();
+    loop {
+        let n = compressed_ca.read_rows(&mut buf)?;
+        if n == 0 {
+            return Ok(rows);
+        }
+        for row in buf[..n * compressed_ca.row_len()].chunks(row_len) {
+            rows.push(requested_ca.translate_row(row, &reorder_map).map(String::from).collect());
+        }
+    }
+}
@@ -0,0 +1,39 @@
+use std::process::Command;
+use ca2_macros::covering_test;
+
+// The fixture archive holds a single CA(6; 2, 3^1 2^2), which fits the model exactly
+const FIXTURE_ROWS : usize = 6;
+
+#[covering_test(archive = "tests/fixtures/cas.ca2", t = 2, model = "tests/fixtures/model.acts")]
+fn pairwise(p1: &str, _p2: bool, p3: String) {
+    assert!(["a", "b", "c"].contains(&p1), "Unexpected value {} of p1", p1);
+    assert!(p3 == "x" || p3 == "y", "Unexpected value {} of p3", p3);
+}
+
+#[covering_test(archive = "tests/fixtures/cas.ca2", t = 2, model = "tests/fixtures/model.acts")]
+#[should_panic]
+fn attributes_apply_to_every_row(_p1: &str, _p2: bool, _p3: &str) {
+    panic!("Every generated test is expected to panic");
+}
+
+// Lists the tests of this binary, as `cargo test -- --list` does
+fn generated_tests(module: &str) -> Vec<String> {
+    let output = Command::new(std::env::current_exe().unwrap())
+        .args(["--list", "--format", "terse"])
+        .output()
+        .unwrap();
+    String::from_utf8(output.stdout).unwrap()
+        .lines()
+        .filter_map(|line| line.strip_suffix(": test"))
+        .filter(|name| name.starts_with(&format!("{}::", module)))
+        .map(String::from)
+        .collect()
+}
+
+#[test]
+fn one_test_per_row() {
+    let tests = generated_tests("pairwise");
+    let expected : Vec<String> = (0..FIXTURE_ROWS).map(|i| format!("pairwise::row_{}", i)).collect();
+    assert_eq!(tests, expected);
+    assert_eq!(generated_tests("attributes_apply_to_every_row").len(), FIXTURE_ROWS);
+}
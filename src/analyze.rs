@@ -0,0 +1,145 @@
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use crate::{Value, Ca2Error, Ca2Result};
+
+// Duplicate rows listed individually by `CAAnalysis::warnings` before they are only counted
+const MAX_LISTED_DUPLICATES : usize = 10;
+
+// Properties of the rows of a CA file, gathered before compressing it
+#[derive(Debug,Clone,Default)]
+pub struct CAAnalysis {
+    // Parameter names from a header line
+    pub names: Option<Vec<String>>,
+    pub n: u64,
+    // Number of distinct values in each column
+    pub distinct: Vec<usize>,
+    // Rows (counted from 1) that repeat an earlier row, together with the first occurrence
+    pub duplicate_rows: Vec<(u64, u64)>
+}
+
+impl CAAnalysis {
+    // Columns (counted from 0) that hold the same value in every row
+    pub fn constant_columns(&self) -> Vec<usize> {
+        match self.n {
+            0 => Vec::new(),
+            _ => (0..self.distinct.len()).filter(|c| self.distinct[*c] == 1).collect()
+        }
+    }
+
+    // A column for messages, e.g. "column 3 (p3)"
+    pub fn column_name(&self, column: usize) -> String {
+        match self.names.as_ref().and_then(|names| names.get(column)) {
+            Some(name) => format!("column {} ({})", column + 1, name),
+            None => format!("column {}", column + 1)
+        }
+    }
+
+    // The column with this header name
+    pub fn find_column(&self, name: &str) -> Ca2Result<usize> {
+        let names = self.names.as_ref().ok_or_else(|| Ca2Error::InvalidArgument(
+            format!("Cannot match parameter {} without a header line", name)
+        ))?;
+        let mut matching = names.iter().enumerate().filter(|(_, n)| n.as_str() == name).map(|(c, _)| c);
+        match (matching.next(), matching.next()) {
+            (Some(column), None) => Ok(column),
+            (Some(_), Some(_)) => Err(Ca2Error::InvalidArgument(format!("Parameter {} appears more than once in the header line", name))),
+            (None, _) => Err(Ca2Error::InvalidArgument(format!("Parameter {} does not appear in the header line", name)))
+        }
+    }
+
+    // Checks the numbers of values declared for the columns against the values that occur
+    pub fn validate_vs(&self, vs: &[u16]) -> Ca2Result<()> {
+        if vs.len() != self.distinct.len() {
+            return Err(Ca2Error::InvalidArgument(format!(
+                "Got {} numbers of values, but the file has {} columns", vs.len(), self.distinct.len()
+            )));
+        }
+        match (0..vs.len()).find(|c| self.distinct[*c] > vs[*c].into()) {
+            Some(c) => Err(Ca2Error::Parse(format!(
+                "Found {} distinct values in {}, but only {} were declared", self.distinct[c], self.column_name(c), vs[c]
+            ))),
+            None => Ok(())
+        }
+    }
+
+    // Things worth reporting that do not prevent compression: Duplicate rows, constant columns
+    // and (given the declared numbers of values) values that never occur
+    pub fn warnings(&self, vs: Option<&[u16]>) -> Vec<String> {
+        let mut warnings : Vec<String> = self.duplicate_rows.iter()
+            .take(MAX_LISTED_DUPLICATES)
+            .map(|(row, first)| format!("Row {} repeats row {}", row, first))
+            .collect();
+        if self.duplicate_rows.len() > MAX_LISTED_DUPLICATES {
+            warnings.push(format!("{} more duplicate rows", self.duplicate_rows.len() - MAX_LISTED_DUPLICATES));
+        }
+        for c in self.constant_columns() {
+            warnings.push(format!("Found a single value in {}", self.column_name(c)));
+        }
+        for (c, v) in vs.unwrap_or_default().iter().enumerate() {
+            if self.distinct.get(c).is_some_and(|distinct| *distinct > 1 && *distinct < (*v).into()) {
+                warnings.push(format!("Declared {} values for {}, but only {} occur", v, self.column_name(c), self.distinct[c]));
+            }
+        }
+        warnings
+    }
+}
+
+// A 128 bit hash of a row, so that rows do not have to be kept to find duplicates.
+// Two rows with the same hash are practically always the same row.
+fn row_hash(row: &[Value]) -> u128 {
+    let (mut low, mut high) = (DefaultHasher::new(), DefaultHasher::new());
+    row.hash(&mut low);
+    // A different prefix makes the second hash independent of the first one
+    (1u8, row).hash(&mut high);
+    (u128::from(high.finish()) << 64) | u128::from(low.finish())
+}
+
+// Counts the distinct values of each column and finds duplicate rows.
+// All rows must have the same length. Besides the distinct values, 24 bytes are kept per distinct row,
+// so a CA with 100 million rows needs about 2.4 GB.
+pub fn analyze_rows<I, R>(rows: I, names: Option<Vec<String>>) -> Ca2Result<CAAnalysis>
+where I: IntoIterator<Item = Ca2Result<R>>, R: AsRef<[String]> {
+    // Rows are remembered by the hash of their abstract form to find duplicates
+    let mut value_maps : Vec<HashMap<String, Value>> = Vec::new();
+    let mut seen : HashMap<u128, u64> = HashMap::new();
+    let mut duplicate_rows = Vec::new();
+    let mut n = 0;
+    for result in rows {
+        let record = result?;
+        let record = record.as_ref();
+        if n == 0 {
+            value_maps = vec![HashMap::new(); record.len()];
+        }
+        if record.len() != value_maps.len() {
+            return Err(Ca2Error::Parse(format!("Row {} has {} values, expected {}", n + 1, record.len(), value_maps.len())));
+        }
+
+        let mut row = Vec::with_capacity(record.len());
+        for (column, value) in record.iter().enumerate() {
+            let value_out = match value_maps[column].get(value) {
+                Some(value_out) => *value_out,
+                None => {
+                    let next_v = Value::try_from(value_maps[column].len()).map_err(|_| Ca2Error::Parse(
+                        format!("Column {} has more than {} distinct values", column + 1, Value::MAX)
+                    ))?;
+                    value_maps[column].insert(value.to_string(), next_v);
+                    next_v
+                }
+            };
+            row.push(value_out);
+        }
+        n += 1;
+        let first = *seen.entry(row_hash(&row)).or_insert(n);
+        if first != n {
+            duplicate_rows.push((n, first));
+        }
+    }
+
+    Ok(CAAnalysis {
+        names,
+        n,
+        distinct: value_maps.iter().map(HashMap::len).collect(),
+        duplicate_rows
+    })
+}
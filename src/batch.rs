@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::{CASpec, CAKind, SpecNotation, CompressOptions, CompressReport, compress_file, group_vs, Ca2Error, Ca2Result};
+use crate::spec::parse_groups;
+
+// A CA specification derived from a file name
+#[derive(Debug,Clone,PartialEq,Eq)]
+pub struct NamedCA {
+    // Number of rows, if the name states it
+    pub n: Option<u64>,
+    pub t: u8,
+    // Numbers of values in the order of the name, which is assumed to be the order of the columns
+    pub vs: Vec<u16>
+}
+
+// A file to compress in a batch
+pub struct BatchJob {
+    pub input: PathBuf,
+    pub named: NamedCA,
+    pub cca: PathBuf,
+    pub ccmeta: PathBuf
+}
+
+// The files found by `find_batch_jobs`
+#[derive(Default)]
+pub struct BatchJobs {
+    pub jobs: Vec<BatchJob>,
+    // Files whose names do not state t and vs
+    pub unrecognized: Vec<PathBuf>,
+    // Files that would be compressed into the same .cca file, e.g. ca.2.3^4.txt and ca.2.3^4.csv,
+    // together with that file. None of them is compressed.
+    pub conflicts: Vec<(PathBuf, Vec<PathBuf>)>
+}
+
+// Derives strength and numbers of values from the common naming conventions for CA files:
+//
+// ca.2.3^4.txt           t = 2, four parameters with three values (as in the NIST tables)
+// ca.3.4^3_2^5.txt       mixed levels; groups can be separated by spaces, `_`, `-`, `,` or `.`
+// CA(9;2,3^4).txt        N = 9, exponential notation like in the Colbourn tables
+// CA(9; 2, 4, 3).txt     N = 9, t = 2, k = 4, v = 3
+// MCA(12;2,3^2 2^2)      mixed covering arrays are named like CAs
+pub fn parse_ca_name(file_name: &str) -> Option<NamedCA> {
+    split_ca_name(file_name).map(|(named, _)| named)
+}
+
+// The standard name of a CA, which `parse_ca_name` understands, e.g. CA(36;2,3^2_2^2).
+// Groups are separated by `_` to keep file names free of spaces. SCAs are named SCA(N;t,k).
+pub fn ca_name(ca_spec: &CASpec) -> String {
+    match ca_spec.kind {
+        CAKind::Sequence => format!("SCA({};{},{})", ca_spec.n, ca_spec.t, ca_spec.vs.len()),
+        _ => {
+            let groups : Vec<String> = group_vs(&ca_spec.vs).iter().map(|(v, k)| format!("{}^{}", v, k)).collect();
+            format!("CA({};{},{})", ca_spec.n, ca_spec.t, groups.join("_"))
+        }
+    }
+}
+
+// Parses a file name like `parse_ca_name` and also returns the name without its extension.
+// The extension is optional, but names like ca.2.3^4 have a dot of their own.
+fn split_ca_name(file_name: &str) -> Option<(NamedCA, &str)> {
+    match parse_name(file_name) {
+        Some(named) => Some((named, file_name)),
+        None => {
+            let (stem, _) = file_name.rsplit_once('.')?;
+            Some((parse_name(stem)?, stem))
+        }
+    }
+}
+
+fn parse_name(name: &str) -> Option<NamedCA> {
+    let lower = name.trim().to_ascii_lowercase();
+    if let Some(rest) = lower.strip_prefix("ca.") {
+        let (t, groups) = rest.split_once('.')?;
+        return Some(NamedCA { n: None, t: t.parse().ok()?, vs: parse_groups(groups)? });
+    }
+
+    // The other conventions are CA specifications that state N
+    let notation = name.parse::<SpecNotation>().ok().filter(|notation| notation.kind == CAKind::Covering)?;
+    Some(NamedCA { n: Some(notation.n?), t: notation.t?, vs: notation.vs })
+}
+
+// Walks a directory recursively and creates a job for every file with a recognized name.
+// Without an output directory, the compressed files are written next to their inputs,
+// otherwise into the same relative location below `output_dir`.
+// Files that would be compressed into the same files are left out as conflicts, since the jobs run in parallel.
+pub fn find_batch_jobs(dir: &Path, output_dir: Option<&Path>) -> Ca2Result<BatchJobs> {
+    let mut files = Vec::new();
+    collect_files(dir, &mut files)?;
+
+    let mut jobs = Vec::new();
+    let mut unrecognized = Vec::new();
+    for input in files {
+        // Skip the results of earlier runs
+        if input.extension().is_some_and(|ext| ext == "cca" || ext == "ccmeta") {
+            continue;
+        }
+        let named = input.file_name().and_then(|name| name.to_str()).and_then(split_ca_name);
+        let Some((named, stem)) = named else {
+            unrecognized.push(input);
+            continue;
+        };
+        let target = match output_dir {
+            Some(output_dir) => output_dir.join(input.strip_prefix(dir).unwrap_or(&input)),
+            None => input.clone()
+        };
+        let (cca, ccmeta) = (target.with_file_name(format!("{}.cca", stem)), target.with_file_name(format!("{}.ccmeta", stem)));
+        jobs.push(BatchJob { input, named, cca, ccmeta });
+    }
+
+    let mut targets : HashMap<PathBuf, Vec<PathBuf>> = HashMap::new();
+    for job in jobs.iter() {
+        targets.entry(job.cca.clone()).or_default().push(job.input.clone());
+    }
+    jobs.retain(|job| targets[&job.cca].len() == 1);
+    let mut conflicts : Vec<(PathBuf, Vec<PathBuf>)> = targets.into_iter().filter(|(_, inputs)| inputs.len() > 1).collect();
+    conflicts.sort();
+    Ok(BatchJobs { jobs, unrecognized, conflicts })
+}
+
+fn collect_files(dir: &Path, out: &mut Vec<PathBuf>) -> Ca2Result<()> {
+    let mut dir_entries = std::fs::read_dir(dir)?.collect::<std::io::Result<Vec<_>>>()?;
+    dir_entries.sort_by_key(|e| e.file_name());
+    for dir_entry in dir_entries {
+        let file_type = dir_entry.file_type()?;
+        if file_type.is_dir() {
+            collect_files(&dir_entry.path(), out)?;
+        } else if file_type.is_file() {
+            out.push(dir_entry.path());
+        }
+    }
+    Ok(())
+}
+
+// Compresses all jobs using up to `threads` threads. Returns the results in the order of the jobs.
+// A file whose number of rows differs from the one in its name is an error, and its output is removed.
+pub fn compress_batch(jobs: &[BatchJob], has_header: bool, threads: usize) -> Vec<Ca2Result<CompressReport>> {
+    let next = AtomicUsize::new(0);
+    let results : Mutex<Vec<Option<Ca2Result<CompressReport>>>> = Mutex::new((0..jobs.len()).map(|_| None).collect());
+    std::thread::scope(|scope| {
+        for _ in 0..threads.clamp(1, jobs.len().max(1)) {
+            scope.spawn(|| loop {
+                let i = next.fetch_add(1, Ordering::Relaxed);
+                let Some(job) = jobs.get(i) else {
+                    break;
+                };
+                let result = compress_job(job, has_header);
+                results.lock().unwrap()[i] = Some(result);
+            });
+        }
+    });
+    results.into_inner().unwrap().into_iter().map(|result| result.unwrap()).collect()
+}
+
+fn compress_job(job: &BatchJob, has_header: bool) -> Ca2Result<CompressReport> {
+    if let Some(parent) = job.cca.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // The format is detected per file, so that tables in different formats can be mixed
+    let options = CompressOptions { has_header, t: Some(job.named.t), vs: Some(job.named.vs.clone()), ..Default::default() };
+    let report = compress_file(&job.input, &options, &job.cca, &job.ccmeta)?;
+    match job.named.n {
+        Some(n) if n != report.ca_spec.n => {
+            let _ = std::fs::remove_file(&job.cca);
+            let _ = std::fs::remove_file(&job.ccmeta);
+            Err(Ca2Error::Format(format!("{} rows, but the name states {}", report.ca_spec.n, n)))
+        },
+        _ => Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ca2_core::testing::TestDir;
+
+    fn named(n: Option<u64>, t: u8, vs: &[u16]) -> NamedCA {
+        NamedCA { n, t, vs: vs.to_vec() }
+    }
+
+    #[test]
+    fn nist_names_are_split_from_their_extension() {
+        assert_eq!(split_ca_name("ca.2.3^4.txt"), Some((named(None, 2, &[3, 3, 3, 3]), "ca.2.3^4")));
+        assert_eq!(split_ca_name("ca.3.4^3_2^5.txt"), Some((named(None, 3, &[4, 4, 4, 2, 2, 2, 2, 2]), "ca.3.4^3_2^5")));
+        // Without an extension, the stem is the whole name
+        assert_eq!(split_ca_name("ca.2.3^4"), Some((named(None, 2, &[3, 3, 3, 3]), "ca.2.3^4")));
+        assert_eq!(parse_ca_name("CA.2.3^4.TXT"), Some(named(None, 2, &[3, 3, 3, 3])));
+    }
+
+    #[test]
+    fn spec_names_state_n() {
+        assert_eq!(split_ca_name("CA(9;2,3^4).txt"), Some((named(Some(9), 2, &[3, 3, 3, 3]), "CA(9;2,3^4)")));
+        assert_eq!(parse_ca_name("CA(9; 2, 4, 3).txt"), Some(named(Some(9), 2, &[3, 3, 3, 3])));
+        assert_eq!(parse_ca_name("MCA(12;2,3^2 2^2)"), Some(named(Some(12), 2, &[3, 3, 2, 2])));
+    }
+
+    #[test]
+    fn other_names_are_not_recognized() {
+        assert_eq!(parse_ca_name("readme.txt"), None);
+        assert_eq!(parse_ca_name("ca.txt"), None);
+        assert_eq!(parse_ca_name("ca.x.3^4.txt"), None);
+        // SCAs and CAs without N cannot be compressed from their name alone
+        assert_eq!(parse_ca_name("SCA(6;3,4).txt"), None);
+        assert_eq!(parse_ca_name("CA(2,3^4).txt"), None);
+    }
+
+    #[test]
+    fn files_with_the_same_target_are_conflicts() {
+        let dir = TestDir::new("batch-conflicts");
+        std::fs::create_dir_all(dir.join("sub")).unwrap();
+        for name in ["ca.2.3^4.txt", "ca.2.3^4.csv", "ca.2.2^3.txt", "sub/ca.2.3^4.txt", "readme.md"] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+
+        let found = find_batch_jobs(&dir, None).unwrap();
+        let inputs : Vec<&Path> = found.jobs.iter().map(|job| job.input.strip_prefix(&dir).unwrap()).collect();
+        assert_eq!(inputs, [Path::new("ca.2.2^3.txt"), Path::new("sub/ca.2.3^4.txt")]);
+        assert_eq!(found.unrecognized, [dir.join("readme.md")]);
+        assert_eq!(found.conflicts, [(dir.join("ca.2.3^4.cca"), vec![dir.join("ca.2.3^4.csv"), dir.join("ca.2.3^4.txt")])]);
+
+        // The same holds in an output directory
+        let found = find_batch_jobs(&dir, Some(&dir.join("out"))).unwrap();
+        assert_eq!(found.jobs.len(), 2);
+        assert_eq!(found.conflicts[0].0, dir.join("out").join("ca.2.3^4.cca"));
+
+    }
+
+    #[test]
+    fn standard_names_can_be_parsed() {
+        let ca_spec = CASpec { kind: CAKind::Covering, n: 36, t: 2, vs: vec![3, 3, 2, 2], ..CASpec::new_unused() };
+        let name = ca_name(&ca_spec);
+        assert_eq!(name, "CA(36;2,3^2_2^2)");
+        assert_eq!(parse_ca_name(&format!("{}.txt", name)), Some(named(Some(36), 2, &[3, 3, 2, 2])));
+    }
+}
@@ -0,0 +1,9 @@
+use std::process::ExitCode;
+use ca2::cli;
+
+// Compresses, packs, lists, verifies and extracts CAs, see ca2::cli.
+// cca, pca and dca are aliases for `ca2 compress`, `ca2 pack` and `ca2 extract`.
+pub fn main() -> ExitCode {
+    let matches = cli::command().get_matches();
+    cli::exit_code(cli::run(&matches))
+}
@@ -0,0 +1,451 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fs::File;
+use std::io::{Write, Read, BufReader, BufWriter, ErrorKind};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+use crate::{CASpec, extract_ca_specs, Ca2Error, Ca2Result};
+
+// Magic bytes for an index cache file
+pub const MAGIC_BYTES_CACHE : &[u8; 16] = b"CA2_INDEX_CACHE\n";
+
+// The index cache in the cache directory of the user, e.g. ~/.cache/ca2/index on Linux.
+// None if the environment does not name a cache directory.
+pub fn default_cache_file() -> Option<PathBuf> {
+    user_cache_dir().map(|dir| dir.join("ca2").join("index"))
+}
+
+#[cfg(windows)]
+fn user_cache_dir() -> Option<PathBuf> {
+    std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+}
+
+#[cfg(target_os = "macos")]
+fn user_cache_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library").join("Caches"))
+}
+
+// See the XDG Base Directory Specification
+#[cfg(not(any(windows, target_os = "macos")))]
+fn user_cache_dir() -> Option<PathBuf> {
+    std::env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+}
+
+// The cached index of a single archive
+pub struct CachedArchive {
+    pub path: PathBuf,
+    // Size and modification time of the archive when its index was read
+    pub size: u64,
+    pub mtime: (u64, u32),
+    pub entries: Vec<(u64, CASpec)>
+}
+
+// Kind, strength and number of parameters of the entries in a bucket
+type BucketKey = (u8, u8, usize);
+// Kind, strength and sorted numbers of values of the entries in a group
+type GroupKey = (u8, u8, Vec<u16>);
+
+// The entries of a bucket with the same (sorted) numbers of values
+struct Group {
+    vs: Vec<u16>,
+    // (archive, entry) pairs, sorted by N
+    entries: Vec<(usize, usize)>
+}
+
+// The groups of entries with the same kind, strength and number of parameters
+#[derive(Default)]
+struct Bucket {
+    // The largest number of values at each position over all groups, so that a request that exceeds it
+    // skips the whole bucket
+    max_vs: Vec<u16>,
+    // Sorted by the smallest N of their entries
+    groups: Vec<Group>
+}
+
+// A persistent index over the entries of many archives.
+// Entries are grouped into buckets by kind, strength and number of parameters. Lookups only visit the buckets
+// with at least the requested strength and number of parameters whose largest numbers of values can serve
+// the request, and stop within a bucket at the first group that can not improve on the best CA found so far.
+#[derive(Default)]
+pub struct IndexCache {
+    archives: Vec<CachedArchive>,
+    buckets: BTreeMap<BucketKey, Bucket>,
+    // Whether the cache differs from the file it was loaded from
+    modified: bool,
+    // Why the contents of the cache file were discarded, if they were
+    discarded: Option<String>
+}
+
+impl IndexCache {
+    // Loads a cache file. A missing file results in an empty cache.
+    // The cache only holds data derived from the archives, so a truncated or corrupted file also results in
+    // an empty cache, which is rebuilt by `update` and overwrites the file when saved. See `discarded`.
+    pub fn load(cache_file: &Path) -> Ca2Result<IndexCache> {
+        let f = match File::open(cache_file) {
+            Ok(f) => f,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(IndexCache::default()),
+            Err(e) => return Err(e.into())
+        };
+        let mut buf = Vec::new();
+        BufReader::new(f).read_to_end(&mut buf)?;
+
+        let Some(archives) = parse_archives(&buf) else {
+            return Ok(IndexCache {
+                modified: true,
+                discarded: Some(format!("Corrupted index cache {}", cache_file.display())),
+                ..Default::default()
+            });
+        };
+        let mut cache = IndexCache { archives, ..Default::default() };
+        cache.build_buckets();
+        Ok(cache)
+    }
+
+    // The reason why the contents of the cache file were discarded when it was loaded, if they were
+    pub fn discarded(&self) -> Option<&str> {
+        self.discarded.as_deref()
+    }
+
+    // Writes the cache to a file. The file is replaced atomically,
+    // so concurrent readers never see a partially written cache.
+    pub fn save(&self, cache_file: &Path) -> Ca2Result<()> {
+        if let Some(dir) = cache_file.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut tmp_name = cache_file.as_os_str().to_owned();
+        tmp_name.push(format!(".{}", std::process::id()));
+        let tmp_path = PathBuf::from(tmp_name);
+
+        let result = File::create(&tmp_path).map_err(Ca2Error::from).and_then(|f_tmp| {
+            let mut writer = BufWriter::new(f_tmp);
+            writer.write_all(MAGIC_BYTES_CACHE)?;
+            writer.write_all(&(self.archives.len() as u32).to_be_bytes())?;
+            for archive in self.archives.iter() {
+                let path = path_to_bytes(&archive.path)?;
+                writer.write_all(&(path.len() as u32).to_be_bytes())?;
+                writer.write_all(path)?;
+                writer.write_all(&archive.size.to_be_bytes())?;
+                writer.write_all(&archive.mtime.0.to_be_bytes())?;
+                writer.write_all(&archive.mtime.1.to_be_bytes())?;
+                writer.write_all(&(archive.entries.len() as u32).to_be_bytes())?;
+                for (offset, ca_spec) in archive.entries.iter() {
+                    writer.write_all(&offset.to_be_bytes())?;
+                    writer.write_all(&ca_spec.serialize())?;
+                }
+            }
+            writer.flush()?;
+            // The data has to be on disk before the rename replaces the old cache
+            writer.get_ref().sync_all()?;
+            drop(writer);
+            Ok(std::fs::rename(&tmp_path, cache_file)?)
+        });
+        if result.is_err() {
+            let _ = std::fs::remove_file(&tmp_path);
+        }
+        result
+    }
+
+    // Makes sure the cache contains up-to-date entries for the given archives.
+    // Only archives that are new or whose size or modification time changed are read.
+    // Archives that no longer exist are dropped from the cache.
+    // Returns the canonical paths of the archives, which `find_best` can be restricted to.
+    pub fn update(&mut self, archive_files: &[PathBuf]) -> Ca2Result<HashSet<PathBuf>> {
+        let mut canonical = HashSet::with_capacity(archive_files.len());
+        let num_archives = self.archives.len();
+        self.archives.retain(|archive| archive.path.is_file());
+        self.modified |= self.archives.len() != num_archives;
+
+        for archive_file in archive_files.iter() {
+            let path = archive_file.canonicalize()?;
+            // Fail early for paths that can not be saved
+            path_to_bytes(&path)?;
+            let metadata = path.metadata()?;
+            let size = metadata.len();
+            let mtime = metadata.modified()?
+                .duration_since(UNIX_EPOCH)
+                .map(|d| (d.as_secs(), d.subsec_nanos()))
+                .unwrap_or((0, 0));
+
+            let cached = self.archives.iter().position(|archive| archive.path == path);
+            if let Some(i) = cached {
+                if self.archives[i].size == size && self.archives[i].mtime == mtime {
+                    canonical.insert(path);
+                    continue;
+                }
+                self.archives.remove(i);
+            }
+
+            let entries = extract_ca_specs(&path)?;
+            self.archives.push(CachedArchive { path: path.clone(), size, mtime, entries });
+            self.modified = true;
+            canonical.insert(path);
+        }
+
+        if self.modified {
+            self.build_buckets();
+        }
+        Ok(canonical)
+    }
+
+    // Whether the cache should be saved
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    pub fn archives(&self) -> &[CachedArchive] {
+        &self.archives
+    }
+
+    // Finds the smallest CA compatible with `requested` in the given archives (canonical paths as returned
+    // by `update`), or in all cached archives if `allowed` is None.
+    // Returns the archive path, the offset of the CA and its specification.
+    pub fn find_best(&self, requested: &CASpec, allowed: Option<&HashSet<PathBuf>>) -> Option<(&Path, u64, &CASpec)> {
+        let kind = u8::from(requested.kind);
+        let k = requested.vs.len();
+        let mut best : Option<(usize, usize)> = None;
+
+        // Visit the strengths from the requested one on, and seek to the requested number of parameters in each
+        let mut t = requested.t;
+        loop {
+            let mut buckets = self.buckets.range((kind, t, k)..).peekable();
+            let Some(&(&(bucket_kind, bucket_t, _), _)) = buckets.peek() else {
+                break;
+            };
+            if bucket_kind != kind {
+                break;
+            }
+            if bucket_t != t {
+                // No bucket with enough parameters for this strength
+                t = bucket_t;
+                continue;
+            }
+            for (_, bucket) in buckets.take_while(|((_, bucket_t, _), _)| *bucket_t == t) {
+                if is_compatible_vs(&requested.vs, &bucket.max_vs) {
+                    self.search_bucket(bucket, requested, allowed, &mut best);
+                }
+            }
+            match t.checked_add(1) {
+                Some(next) => t = next,
+                None => break
+            }
+        }
+
+        best.map(|(a, e)| {
+            let (offset, ca_spec) = &self.archives[a].entries[e];
+            (self.archives[a].path.as_path(), *offset, ca_spec)
+        })
+    }
+
+    fn search_bucket(&self, bucket: &Bucket, requested: &CASpec, allowed: Option<&HashSet<PathBuf>>, best: &mut Option<(usize, usize)>) {
+        let n = |(a, e): (usize, usize)| self.archives[a].entries[e].1.n;
+        for group in bucket.groups.iter() {
+            // Groups are sorted by their smallest N, so no later group can improve on the best CA either
+            if best.is_some_and(|best| n(best) <= n(group.entries[0])) {
+                break;
+            }
+            if !is_compatible_vs(&requested.vs, &group.vs) {
+                continue;
+            }
+            // Entries are sorted by N, so the first allowed entry is the best one in this group
+            for (a, e) in group.entries.iter() {
+                if best.is_some_and(|best| n(best) <= n((*a, *e))) {
+                    break;
+                }
+                if allowed.is_some_and(|allowed| !allowed.contains(&self.archives[*a].path)) {
+                    continue;
+                }
+                *best = Some((*a, *e));
+                break;
+            }
+        }
+    }
+
+    fn build_buckets(&mut self) {
+        let mut groups : BTreeMap<GroupKey, Vec<(usize, usize)>> = BTreeMap::new();
+        for (a, archive) in self.archives.iter().enumerate() {
+            for (e, (_, ca_spec)) in archive.entries.iter().enumerate() {
+                groups.entry((u8::from(ca_spec.kind), ca_spec.t, ca_spec.vs.clone())).or_default().push((a, e));
+            }
+        }
+
+        let n = |(a, e): &(usize, usize)| self.archives[*a].entries[*e].1.n;
+        self.buckets.clear();
+        for ((kind, t, vs), mut entries) in groups {
+            entries.sort_by_key(n);
+            let bucket = self.buckets.entry((kind, t, vs.len())).or_default();
+            bucket.max_vs.resize(vs.len(), 0);
+            for (max, v) in bucket.max_vs.iter_mut().zip(vs.iter()) {
+                *max = (*max).max(*v);
+            }
+            bucket.groups.push(Group { vs, entries });
+        }
+        for bucket in self.buckets.values_mut() {
+            bucket.groups.sort_by_key(|group| n(&group.entries[0]));
+        }
+    }
+}
+
+// Whether a CA with the (sorted) numbers of values `stored` can serve a request for `requested`, see `CASpec::is_compatible`
+fn is_compatible_vs(requested: &[u16], stored: &[u16]) -> bool {
+    stored.len() >= requested.len() && requested.iter().zip(stored.iter()).all(|(r, s)| s >= r)
+}
+
+// Parses the archives stored in a cache file, or returns None if it is truncated or corrupted
+fn parse_archives(buf: &[u8]) -> Option<Vec<CachedArchive>> {
+    let mut buf = buf.strip_prefix(MAGIC_BYTES_CACHE.as_slice())?;
+    let mut archives = Vec::new();
+    let num_archives = take_u32(&mut buf)?;
+    for _ in 0..num_archives {
+        let path_len = take_u32(&mut buf)? as usize;
+        let path = take(&mut buf, path_len).and_then(path_from_bytes)?;
+        let size = take_u64(&mut buf)?;
+        let mtime = (take_u64(&mut buf)?, take_u32(&mut buf)?);
+        let num_entries = take_u32(&mut buf)?;
+        let mut entries = Vec::new();
+        for _ in 0..num_entries {
+            let offset = take_u64(&mut buf)?;
+            let (ca_spec, len) = CASpec::unserialize(buf).ok()?;
+            buf = &buf[len..];
+            entries.push((offset, ca_spec));
+        }
+        archives.push(CachedArchive { path, size, mtime, entries });
+    }
+    Some(archives)
+}
+
+// Paths are stored as raw bytes on Unix. Elsewhere, only paths that are valid Unicode can be stored.
+#[cfg(unix)]
+fn path_to_bytes(path: &Path) -> Ca2Result<&[u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    Ok(path.as_os_str().as_bytes())
+}
+
+#[cfg(not(unix))]
+fn path_to_bytes(path: &Path) -> Ca2Result<&[u8]> {
+    path.to_str()
+        .map(str::as_bytes)
+        .ok_or_else(|| Ca2Error::InvalidArgument(format!("The path {} can not be stored in an index cache", path.display())))
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> Option<PathBuf> {
+    use std::os::unix::ffi::OsStrExt;
+    Some(PathBuf::from(std::ffi::OsStr::from_bytes(bytes)))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> Option<PathBuf> {
+    std::str::from_utf8(bytes).ok().map(PathBuf::from)
+}
+
+// Helpers for reading big endian integers from the front of a buffer
+fn take<'a>(buf: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if buf.len() < len {
+        return None;
+    }
+    let (out, rest) = buf.split_at(len);
+    *buf = rest;
+    Some(out)
+}
+
+fn take_u32(buf: &mut &[u8]) -> Option<u32> {
+    take(buf, 4).map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+}
+
+fn take_u64(buf: &mut &[u8]) -> Option<u64> {
+    take(buf, 8).map(|b| u64::from_be_bytes(b.try_into().unwrap()))
+}
+
+// Finds the smallest CA compatible with the request using an index cache file,
+// which is updated first if any of the archives changed
+pub fn find_best_ca_cached(cache_file: &Path, archive_files: &[PathBuf]
+                           , requested: &CASpec) -> Ca2Result<Option<(PathBuf, u64, CASpec)>> {
+    find_best_ca_in_cache(IndexCache::load(cache_file)?, cache_file, archive_files, requested)
+}
+
+// Like `find_best_ca_cached`, for a cache that has already been loaded from `cache_file`
+pub fn find_best_ca_in_cache(mut cache: IndexCache, cache_file: &Path, archive_files: &[PathBuf]
+                             , requested: &CASpec) -> Ca2Result<Option<(PathBuf, u64, CASpec)>> {
+    let allowed = cache.update(archive_files)?;
+    if cache.is_modified() {
+        cache.save(cache_file)?;
+    }
+    Ok(cache
+        .find_best(requested, Some(&allowed))
+        .map(|(file, offset, ca)| (file.to_path_buf(), offset, ca.clone())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ca2_core::testing::TestDir;
+    use crate::{CoveringArray, InputCA, create_archive, write_ccmeta};
+
+    // Packs a single CA into an archive in a fresh directory for one test
+    fn test_archive(name: &str) -> (TestDir, PathBuf, CASpec) {
+        let dir = TestDir::new(&format!("cache-{}", name));
+        let ca = CoveringArray::from_rows(2, vec![2, 2, 2], &[vec![0, 0, 0], vec![0, 1, 1], vec![1, 0, 1], vec![1, 1, 0]]).unwrap();
+        let cca = dir.join("ca.cca");
+        let ca_spec = ca.write_compressed(File::create(&cca).unwrap()).unwrap();
+        write_ccmeta(File::create(dir.join("ca.ccmeta")).unwrap(), &ca_spec).unwrap();
+        let archive = dir.join("archive.ca2");
+        create_archive(&archive, None, vec![InputCA { ca_spec: ca_spec.clone(), cca }], false).unwrap();
+        (dir, archive, ca_spec)
+    }
+
+    #[test]
+    fn caches_are_saved_into_new_directories() {
+        let (_dir, archive, ca_spec) = test_archive("new-dir");
+        let cache_file = archive.with_file_name("cache").join("ca2").join("index");
+        let found = find_best_ca_cached(&cache_file, std::slice::from_ref(&archive), &ca_spec).unwrap();
+        assert!(found.is_some());
+        assert_eq!(IndexCache::load(&cache_file).unwrap().archives().len(), 1);
+        assert!(default_cache_file().is_none_or(|f| f.ends_with(Path::new("ca2").join("index"))));
+    }
+
+    #[test]
+    fn failed_saves_leave_no_temporary_file() {
+        let (_dir, archive, _) = test_archive("failed-save");
+        let dir = archive.parent().unwrap();
+        let mut cache = IndexCache::default();
+        cache.update(std::slice::from_ref(&archive)).unwrap();
+
+        // The cache can not replace a directory
+        let cache_file = dir.join("cache-dir");
+        std::fs::create_dir_all(cache_file.join("entry")).unwrap();
+        assert!(cache.save(&cache_file).is_err());
+        let names : Vec<_> = std::fs::read_dir(dir).unwrap().map(|entry| entry.unwrap().file_name()).collect();
+        assert!(!names.iter().any(|name| name.to_string_lossy().starts_with("cache-dir.")), "{:?}", names);
+    }
+
+    #[test]
+    fn truncated_caches_are_rebuilt() {
+        let (_dir, archive, ca_spec) = test_archive("truncated");
+        let cache_file = archive.with_extension("idx");
+        let archives = [archive];
+        let found = find_best_ca_cached(&cache_file, &archives, &ca_spec).unwrap();
+        assert_eq!(found.map(|(_, _, ca)| ca.to_string()), Some(ca_spec.to_string()));
+        let saved = std::fs::read(&cache_file).unwrap();
+
+        // Every proper prefix of the file is discarded instead of failing the lookup
+        for len in [0, 8, MAGIC_BYTES_CACHE.len() + 2, saved.len() - 1] {
+            std::fs::write(&cache_file, &saved[..len]).unwrap();
+            let cache = IndexCache::load(&cache_file).unwrap();
+            assert!(cache.discarded().is_some());
+            assert!(cache.is_modified());
+            assert!(cache.archives().is_empty());
+
+            // The lookup rebuilds the cache from the archives and overwrites the file
+            let found = find_best_ca_in_cache(cache, &cache_file, &archives, &ca_spec).unwrap();
+            assert_eq!(found.map(|(_, _, ca)| ca.to_string()), Some(ca_spec.to_string()));
+            assert_eq!(std::fs::read(&cache_file).unwrap(), saved);
+        }
+
+        let cache = IndexCache::load(&cache_file).unwrap();
+        assert!(cache.discarded().is_none());
+        assert!(!cache.is_modified());
+        assert_eq!(cache.archives().len(), 1);
+    }
+}
@@ -0,0 +1,245 @@
+use std::io::Write;
+use std::path::Path;
+use clap::{App, Arg, ArgMatches};
+use crate::{CompressOptions, InputFormat, AnalyzedFile, INPUT_FORMATS, analyze_file, compress_analyzed, compressed_paths, BatchJobs, find_batch_jobs, compress_batch
+            , Ca2Error, Ca2Result};
+use super::{Verbosity, with_verbosity, output_arg, no_header_arg, open_output, path_value, parse_value, parse_values};
+
+// CA compression
+//
+// Command line arguments:
+// 1. Path to a CA file (CSV or another format, see ca2::import)
+// 2. Strength t (not required if the file states it)
+// 3. v_i, exactly in the order of parameters in the file, or matched to header names
+//    (not required for sequence covering arrays, if the file states them or with --infer)
+//
+// With --batch, all files in a directory are compressed instead, see ca2::batch.
+pub fn command() -> App<'static> {
+    with_verbosity(
+        App::new("compress")
+        .about("Compresses a CA file into a .cca (raw compressed CA) and a .ccmeta (CA metadata) file")
+        .arg(
+            Arg::new("ca")
+                .help("Path to the CA file")
+                .short('c')
+                .long("ca")
+                .required_unless_present("batch")
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .allow_invalid_utf8(true),
+        )
+        .arg(
+            Arg::new("strength")
+                .help("Strength t. Required unless the file states it.")
+                .short('t')
+                .long("strength")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("vs")
+                .help("Parameter value counts v_i, once for every column of the file, or as NAME=V for the column with this header name. Required unless the file states them or --infer is given.")
+                .short('v')
+                .long("vs")
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            Arg::new("infer")
+                .help("Count the distinct values of columns whose number of values is neither given nor stated by the file")
+                .short('i')
+                .long("infer")
+                .conflicts_with("sequence")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::new("format")
+                .help("Format of the CA file. Detected from its contents if not given; transposed files (one line per parameter) are never detected.")
+                .short('f')
+                .long("format")
+                .takes_value(true)
+                .possible_values(INPUT_FORMATS)
+                .ignore_case(true),
+        )
+        .arg(
+            Arg::new("sequence")
+                .help("The input is a sequence covering array (each row is a permutation of the same events)")
+                .short('s')
+                .long("sequence")
+                .conflicts_with("vs")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::new("check")
+                .help("Only analyze the CA file: print the numbers of values and any warnings to stdout, without writing anything")
+                .long("check")
+                .conflicts_with("batch")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::new("batch")
+                .help("Compress all files in this directory (recursively) whose names state t and vs, e.g. ca.2.3^4.txt or CA(9;2,3^4).txt")
+                .short('b')
+                .long("batch")
+                .conflicts_with_all(&["ca", "strength", "vs", "sequence", "format", "infer"])
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .allow_invalid_utf8(true),
+        )
+        .arg(
+            Arg::new("jobs")
+                .help("Number of files compressed in parallel with --batch. Defaults to the number of CPUs.")
+                .short('j')
+                .long("jobs")
+                .requires("batch")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("summary")
+                .help("Path to a file for the summary of --batch. If not given, it is printed to stdout.")
+                .long("summary")
+                .requires("batch")
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .allow_invalid_utf8(true),
+        )
+        .arg(output_arg("Path to the output .cca file; the .ccmeta file is written next to it. Defaults to the CA file with the extension .cca. With --batch, the output directory (defaults to the input directory)."))
+        .arg(no_header_arg("Assume the CSV or PICT file has no header line"))
+    )
+}
+
+pub fn run(matches: &ArgMatches) -> Ca2Result<()> {
+    let verbosity = Verbosity::from_matches(matches);
+    let has_header = !matches.is_present("no-header");
+    if let Some(dir) = path_value(matches, "batch") {
+        return run_batch(matches, &dir, has_header, verbosity);
+    }
+    let ca_file = path_value(matches, "ca").unwrap();
+    let mut options = CompressOptions {
+        format: parse_value::<InputFormat>(matches, "format")?,
+        has_header,
+        t: parse_value(matches, "strength")?,
+        infer_vs: matches.is_present("infer"),
+        sequence: matches.is_present("sequence"),
+        ..Default::default()
+    };
+    parse_vs(matches, &mut options)?;
+    if !ca_file.is_file() {
+        return Err(Ca2Error::InvalidArgument(format!("CA file {} does not exist.", ca_file.display())));
+    }
+    let analyzed = analyze_file(&ca_file, &options)?;
+    if matches.is_present("check") {
+        let mut writer = std::io::stdout();
+        for line in analysis_report(&analyzed, &options) {
+            writeln!(writer, "{}", line)?;
+        }
+        writeln!(writer, "Checked {}: {} rows with strength {}", ca_file.display(), analyzed.analysis.n, analyzed.t)?;
+        return Ok(());
+    }
+
+    // The analysis is reported before anything is written
+    if verbosity.normal() {
+        for line in analysis_report(&analyzed, &options) {
+            eprintln!("{}", line);
+        }
+    }
+    let (output_compressed, output_meta) = compressed_paths(&path_value(matches, "output").unwrap_or(ca_file.clone()));
+    if verbosity.verbose() {
+        eprintln!("Compressing {} into {} and {}", ca_file.display(), output_compressed.display(), output_meta.display());
+    }
+    let report = compress_analyzed(&ca_file, &options, analyzed, &output_compressed, &output_meta)?;
+    if verbosity.normal() {
+        eprintln!("Compressed {} into {} and {}", report.ca_spec, output_compressed.display(), output_meta.display());
+    }
+    Ok(())
+}
+
+// Inferred numbers of values and warnings about the rows
+fn analysis_report(analyzed: &AnalyzedFile, options: &CompressOptions) -> Vec<String> {
+    let mut lines = Vec::new();
+    if options.infer_vs {
+        let vs : Vec<String> = analyzed.vs.iter().enumerate()
+            .map(|(c, v)| match analyzed.analysis.names.as_ref().and_then(|names| names.get(c)) {
+                Some(name) => format!("{}={}", name, v),
+                None => v.to_string()
+            })
+            .collect();
+        lines.push(format!("Numbers of values: {}", vs.join(" ")));
+    }
+    let vs = (!options.sequence).then_some(analyzed.vs.as_slice());
+    lines.extend(analyzed.analysis.warnings(vs).into_iter().map(|warning| format!("Warning: {}", warning)));
+    lines
+}
+
+// Numbers of values are either all given in the order of the columns, or all as NAME=V
+fn parse_vs(matches: &ArgMatches, options: &mut CompressOptions) -> Ca2Result<()> {
+    let values : Vec<&str> = matches.values_of("vs").map(Iterator::collect).unwrap_or_default();
+    if !values.iter().any(|value| value.contains('=')) {
+        let vs = parse_values::<u16>(matches, "vs")?;
+        options.vs = if vs.is_empty() { None } else { Some(vs) };
+        return Ok(());
+    }
+    options.named_vs = values.iter()
+        .map(|value| {
+            let (name, v) = value.rsplit_once('=').ok_or_else(|| Ca2Error::InvalidArgument(
+                String::from("Give all numbers of values either in the order of the columns or as NAME=V")
+            ))?;
+            let v = v.parse().map_err(|_| Ca2Error::InvalidArgument(format!("Invalid vs: {}", value)))?;
+            Ok((name.to_string(), v))
+        })
+        .collect::<Ca2Result<_>>()?;
+    Ok(())
+}
+
+// Compresses every file in a directory whose name states its strength and numbers of values.
+// The summary lists the result for each file.
+fn run_batch(matches: &ArgMatches, dir: &Path, has_header: bool, verbosity: Verbosity) -> Ca2Result<()> {
+    if !dir.is_dir() {
+        return Err(Ca2Error::InvalidArgument(format!("Directory {} does not exist.", dir.display())));
+    }
+    let threads = match parse_value::<usize>(matches, "jobs")? {
+        Some(threads) => threads,
+        None => std::thread::available_parallelism().map_or(1, |threads| threads.get())
+    };
+    let output_dir = path_value(matches, "output");
+    let BatchJobs { jobs, unrecognized, conflicts } = find_batch_jobs(dir, output_dir.as_deref())?;
+    if verbosity.normal() {
+        for (cca, inputs) in conflicts.iter() {
+            let inputs : Vec<String> = inputs.iter().map(|input| input.display().to_string()).collect();
+            eprintln!("Skipping {}: they would all be compressed into {}", inputs.join(", "), cca.display());
+        }
+    }
+    if verbosity.verbose() {
+        for file in unrecognized.iter() {
+            eprintln!("Skipping {}: name does not state t and vs", file.display());
+        }
+        eprintln!("Compressing {} files using {} threads...", jobs.len(), threads);
+    }
+
+    let results = compress_batch(&jobs, has_header, threads);
+    let mut writer = open_output(path_value(matches, "summary").as_deref())?;
+    let mut failed = 0;
+    for (job, result) in jobs.iter().zip(results.iter()) {
+        match result {
+            Ok(report) => {
+                writeln!(writer, "{}: {} -> {}", job.input.display(), report.ca_spec, job.cca.display())?;
+                for warning in report.analysis.warnings(Some(&report.vs)) {
+                    writeln!(writer, "{}: warning: {}", job.input.display(), warning)?;
+                }
+            },
+            Err(e) => {
+                failed += 1;
+                writeln!(writer, "{}: FAILED: {}", job.input.display(), e)?;
+            }
+        }
+    }
+    writer.flush()?;
+
+    if verbosity.normal() {
+        let skipped = unrecognized.len() + conflicts.iter().map(|(_, inputs)| inputs.len()).sum::<usize>();
+        eprintln!("Compressed {} of {} files, {} failed, {} skipped", jobs.len() - failed, jobs.len(), failed, skipped);
+    }
+    match failed {
+        0 => Ok(()),
+        _ => Err(Ca2Error::Format(format!("{} of {} files could not be compressed", failed, jobs.len())))
+    }
+}
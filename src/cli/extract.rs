@@ -0,0 +1,422 @@
+use std::fs::File;
+use std::io::{Read, Seek};
+use std::path::{Path, PathBuf};
+use clap::{App, Arg, ArgMatches};
+use crate::{CASpec, CAKind, CAWriter, OutputFormat, OUTPUT_FORMATS, TemplateRenderer, RequestedCA, SpecNotation, Spool
+            , Explanation, Mismatch, ListedCA, parse_model, read_index, find_best_ca, find_best_ca_in_cache, extract_ca_specs, IndexCache, default_cache_file
+            , explain_request, extract_ca, extract_raw, unpack_archive, unpack_index, list_archive, list_index, ArchiveIndex, stdin_path, spool, Ca2Error, Ca2Result};
+use super::{Verbosity, with_verbosity, output_arg, no_header_arg, archives_arg, find_input_archives
+            , open_output, path_value, parse_value, parse_values};
+use crate::render::{MANIFEST_FILE_NAME, default_file_name};
+use super::list::{where_arg, where_filters, format_vs};
+
+// Environment variable naming an index cache file that is used if it exists
+pub const CACHE_ENV_VAR : &str = "CA2_CACHE";
+
+// Compressed CA unpacker
+//
+// Command line arguments:
+// 1. Input model (in ACTS or CTWedge format, or an event list), or a CA specification like "t=2, 3^4"
+// 2. Strength, unless the specification states it
+// 3. Zero or more ca2 files, directories or glob patterns, where `-` denotes stdin
+//
+// The CA is written as CSV or in another format of `OutputFormat`, or a template is rendered once per row.
+// With --raw, a stored CA selected by index or conditions is written as is instead,
+// and --unpack writes all (selected) CAs of the archives into a directory.
+pub fn command() -> App<'static> {
+    with_verbosity(
+        App::new("extract")
+        .about("Finds the smallest CA for an input parameter model in the archives and translates it to CSV")
+        .arg(
+            Arg::new("ipm")
+                .help("The input parameter model file (an ACTS or CTWedge file, or an event list)")
+                .long("ipm")
+                .short('i')
+                .required_unless_present_any(["raw", "unpack", "spec"])
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .allow_invalid_utf8(true),
+        )
+        .arg(
+            Arg::new("spec")
+                .help("A CA specification instead of an input model, e.g. \"t=3, 5^2 3^4 2^10\", CA(9;2,3^4), CA(9;2,4,3), MCA(12;2,3^2 2^2) or SCA(6;3,4). A stated N is ignored. The CA is written with parameters p1 to pk and values 0 to v - 1.")
+                .long("spec")
+                .short('s')
+                .conflicts_with_all(&["ipm", "raw", "unpack"])
+                .takes_value(true)
+                .allow_hyphen_values(true),
+        )
+        .arg(output_arg("Path to the output CSV file. If not given, the CA will be printed to stdout (if found)."))
+        .arg(
+            Arg::new("format")
+                .help("Output format; JSON and NDJSON map parameter names to values, ACTS writes the model with the rows as test set, and pytest, junit, rstest and gherkin generate test code with one case per row")
+                .short('f')
+                .long("format")
+                .takes_value(true)
+                .possible_values(OUTPUT_FORMATS)
+                .default_value("csv"),
+        )
+        .arg(no_header_arg("Disable the header of CSV, TSV and AsciiDoc output"))
+        .arg(
+            Arg::new("template")
+                .help("Render this template once per row into --output-dir instead of writing the CA. Placeholders like {{name}} are replaced by the value of a parameter, {{row}} by the number of the row.")
+                .long("template")
+                .requires("output-dir")
+                .conflicts_with_all(&["output", "format", "no-header", "unpack"])
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .allow_invalid_utf8(true),
+        )
+        .arg(
+            Arg::new("output-dir")
+                .help("Directory for the files rendered from --template, together with a manifest.csv that lists them with their row and values")
+                .long("output-dir")
+                .requires("template")
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .allow_invalid_utf8(true),
+        )
+        .arg(
+            Arg::new("file-name")
+                .help("Names of the files rendered from --template, with placeholders like the template. Defaults to the name of the template with _{{row}} before its extension, where the row number is padded with zeros.")
+                .long("file-name")
+                .requires("template")
+                .takes_value(true)
+                .allow_hyphen_values(true),
+        )
+        .arg(
+            Arg::new("cache")
+                .help("An index cache file that speeds up lookups in many archives; created if it does not exist. Defaults to $CA2_CACHE, or else to ca2/index in the cache directory of the user (e.g. ~/.cache), if that file exists.")
+                .long("cache")
+                .required(false)
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .allow_invalid_utf8(true),
+        )
+        .arg(
+            Arg::new("spool-limit")
+                .help("Maximum number of bytes of an archive read from stdin to keep in memory; larger archives are spooled to a temporary file")
+                .long("spool-limit")
+                .required(false)
+                .takes_value(true)
+                .allow_hyphen_values(true),
+        )
+        .arg(
+            Arg::new("strength")
+                .help("The required strength of CA")
+                .short('t')
+                .long("strength")
+                .required_unless_present_any(["raw", "unpack", "spec"])
+                .takes_value(true)
+                .allow_hyphen_values(true),
+        )
+        .arg(
+            Arg::new("explain")
+                .help("If no compatible CA is found, show the nearest stored CAs for every reason and the smallest CAs for slightly relaxed requests")
+                .long("explain")
+                .conflicts_with_all(&["raw", "unpack"])
+                .takes_value(false),
+        )
+        .arg(
+            Arg::new("raw")
+                .help("Write a stored CA as is, with columns sorted by their number of values and values from 0 to v - 1. Selects the CA with the fewest rows among the ones chosen by --index and --where.")
+                .long("raw")
+                .conflicts_with_all(&["ipm", "strength", "cache"])
+                .takes_value(false),
+        )
+        .arg(
+            Arg::new("unpack")
+                .help("Write every CA of the archives (or the ones chosen by --index and --where) into this directory, as raw CSV together with .cca and .ccmeta files that can be packed again")
+                .long("unpack")
+                .conflicts_with_all(&["ipm", "strength", "cache", "raw", "output", "no-header", "format"])
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .allow_invalid_utf8(true),
+        )
+        .arg(
+            Arg::new("index")
+                .help("With --raw or --unpack, select the entry with this index (requires a single archive)")
+                .long("index")
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
+        .arg(where_arg("With --raw or --unpack, select the CAs matching this condition"))
+        .arg(archives_arg("The input .ca2 archives or directories containing them, `-` reads an archive from stdin. If none are given, this executable (if it is a self-extracting archive) and the locations in $CA2_PATH are used."))
+    )
+}
+
+pub fn run(matches: &ArgMatches) -> Ca2Result<()> {
+    let verbosity = Verbosity::from_matches(matches);
+    if matches.is_present("raw") || matches.is_present("unpack") {
+        return run_stored(matches, verbosity);
+    }
+    if matches.is_present("index") || matches.is_present("where") {
+        return Err(Ca2Error::InvalidArgument(String::from("--index and --where require --raw or --unpack")));
+    }
+    let strength = parse_value::<u8>(matches, "strength")?;
+    let ipm = path_value(matches, "ipm");
+    if let Some(ipm) = ipm.as_ref().filter(|ipm| !ipm.is_file()) {
+        return Err(Ca2Error::InvalidArgument(format!("IPM file {} does not exist.", ipm.display())));
+    }
+    let output = path_value(matches, "output");
+    if output.as_ref().is_some_and(|output| output.exists()) {
+        return Err(Ca2Error::InvalidArgument(format!("Output file {} already exists.", output.unwrap().display())));
+    }
+    let header = !matches.is_present("no-header");
+    let format = matches.value_of("format").unwrap().parse::<OutputFormat>()?;
+    let cache = path_value(matches, "cache")
+        .or_else(|| std::env::var_os(CACHE_ENV_VAR).map(PathBuf::from).filter(|f| f.is_file()))
+        .or_else(|| default_cache_file().filter(|f| f.is_file()));
+    let spool_limit = parse_value::<usize>(matches, "spool-limit")?
+        .unwrap_or(spool::SPOOL_DEFAULT_MEMORY_LIMIT);
+
+    // Archives read from stdin need to be spooled first
+    let (input_files, read_stdin) = find_input_archives(matches)?;
+
+    // Parse the input model or the specification
+    let requested_ca = match (matches.value_of("spec"), ipm) {
+        (Some(spec), _) => RequestedCA::from_ca_spec(&spec.parse::<SpecNotation>()?.into_ca_spec(strength)?)?,
+        (None, ipm) => parse_request(&ipm.unwrap(), strength.unwrap())?
+    };
+
+    let mut stdin_spool = match read_stdin {
+        true => Some(Spool::new(std::io::stdin().lock(), spool_limit)?),
+        false => None
+    };
+
+    // Find the smallest compatible CA
+    let mut stdin_entries = Vec::new();
+    let mut best_compatible_ca = match &cache {
+        Some(cache_file) => {
+            let index_cache = IndexCache::load(cache_file)?;
+            if let Some(reason) = index_cache.discarded().filter(|_| verbosity.normal()) {
+                eprintln!("Warning: {}, rebuilding it from the archives", reason);
+            }
+            find_best_ca_in_cache(index_cache, cache_file, &input_files, &requested_ca.ca_spec)?
+        },
+        None => find_best_ca(&input_files, &requested_ca.ca_spec)?
+    };
+    if let Some(spool) = stdin_spool.as_mut() {
+        stdin_entries = read_index(spool)?.entries;
+        let best_stdin_ca = stdin_entries
+            .iter()
+            .filter(|(_, ca)| requested_ca.ca_spec.is_compatible(ca))
+            .min_by_key(|(_, ca)| ca.n)
+            .cloned();
+        if let Some((offset, ca)) = best_stdin_ca {
+            if best_compatible_ca.as_ref().is_none_or(|best| ca.n < best.2.n) {
+                best_compatible_ca = Some((stdin_path(), offset, ca));
+            }
+        }
+    }
+
+    let (file, offset, ca) = match best_compatible_ca {
+        Some(best) => best,
+        None => {
+            if verbosity.normal() || matches.is_present("explain") {
+                eprintln!("No compatible CA found in archives.");
+            }
+            if matches.is_present("explain") {
+                let mut stored = Vec::new();
+                for input_file in input_files.iter() {
+                    stored.extend(extract_ca_specs(input_file)?.into_iter().map(|(offset, ca)| (input_file.clone(), offset, ca)));
+                }
+                stored.extend(stdin_entries.into_iter().map(|(offset, ca)| (stdin_path(), offset, ca)));
+                print_explanation(&requested_ca.ca_spec, &explain_request(&requested_ca.ca_spec, &stored));
+            }
+            return Ok(());
+        }
+    };
+    if verbosity.verbose() {
+        eprintln!("Best compatible CA: {} at offset {} of {}", ca, offset, file.display());
+    }
+
+    let mut writer = row_writer(matches, format, output.as_deref(), header, ca.n)?;
+    let rows = match stdin_spool {
+        Some(spool) if file == stdin_path() => extract_ca(spool, offset, &ca, &requested_ca, writer.as_mut())?,
+        _ => extract_ca(open_archive(&file)?, offset, &ca, &requested_ca, writer.as_mut())?
+    };
+
+    if verbosity.normal() {
+        eprintln!("Decompressed CA with {} rows.", rows);
+    }
+    print_rendered(matches, verbosity, rows);
+    Ok(())
+}
+
+// The writer for the decoded rows: a `TemplateRenderer` with --template, or the output format
+fn row_writer(matches: &ArgMatches, format: OutputFormat, output: Option<&Path>, header: bool, rows: u64) -> Ca2Result<Box<dyn CAWriter>> {
+    let Some(template_path) = path_value(matches, "template") else {
+        return Ok(format.writer(open_output(output)?, header));
+    };
+    let template = std::fs::read_to_string(&template_path)?;
+    let file_name = matches.value_of("file-name").map(String::from)
+        .unwrap_or_else(|| default_file_name(&template_path));
+    Ok(Box::new(TemplateRenderer::new(template, file_name, &path_value(matches, "output-dir").unwrap(), rows)))
+}
+
+fn print_rendered(matches: &ArgMatches, verbosity: Verbosity, rows: u64) {
+    if let (true, Some(dir)) = (verbosity.normal(), path_value(matches, "output-dir")) {
+        eprintln!("Rendered {} files and {} into {}", rows, MANIFEST_FILE_NAME, dir.display());
+    }
+}
+
+// Prints the nearest candidates for every reason and the relaxed requests to stderr
+fn print_explanation(requested: &CASpec, explanation: &Explanation) {
+    eprintln!("Requested: {}", describe_request(requested));
+    eprintln!("Examined {} stored CAs, {} of them of a different kind.", explanation.examined, explanation.other_kinds);
+    for (reason, candidates) in [("Strength too low", &explanation.too_low_strength)
+                                 , (columns_name(requested.kind), &explanation.too_few_columns)
+                                 , ("Too few values", &explanation.too_few_values)] {
+        if candidates.is_empty() {
+            continue;
+        }
+        eprintln!("{}:", reason);
+        for candidate in candidates {
+            let mismatches : Vec<String> = candidate.mismatches.iter().map(|m| describe_mismatch(requested.kind, m)).collect();
+            eprintln!("  {} at offset {} of {}: {}", candidate.ca_spec, candidate.offset, candidate.archive.display(), mismatches.join(", "));
+        }
+    }
+    if !explanation.relaxations.is_empty() {
+        eprintln!("Smallest compatible CAs for relaxed requests:");
+    }
+    for relaxation in explanation.relaxations.iter() {
+        match &relaxation.best {
+            Some((archive, offset, ca)) => eprintln!("  {}: N = {}, {} at offset {} of {}"
+                                                     , describe_request(&relaxation.requested), ca.n, ca, offset, archive.display()),
+            None => eprintln!("  {}: none", describe_request(&relaxation.requested))
+        }
+    }
+}
+
+// A request without N, e.g. t=2, 3^2 2^2
+fn describe_request(ca_spec: &CASpec) -> String {
+    format!("t={}, {}", ca_spec.t, format_vs(ca_spec))
+}
+
+fn columns_name(kind: CAKind) -> &'static str {
+    match kind {
+        CAKind::Sequence => "Too few events",
+        _ => "Too few parameters"
+    }
+}
+
+fn describe_mismatch(kind: CAKind, mismatch: &Mismatch) -> String {
+    match *mismatch {
+        Mismatch::Strength { stored, requested } => format!("strength {} instead of {}", stored, requested),
+        Mismatch::Columns { stored, requested } => match kind {
+            CAKind::Sequence => format!("{} events instead of {}", stored, requested),
+            _ => format!("{} parameters instead of {}", stored, requested)
+        },
+        Mismatch::Values { position, stored, requested } =>
+            format!("{} values instead of {} for parameter {} (sorted by number of values)", stored, requested, position + 1)
+    }
+}
+
+// Writes stored CAs without translating them to a model (--raw and --unpack)
+fn run_stored(matches: &ArgMatches, verbosity: Verbosity) -> Ca2Result<()> {
+    let filters = where_filters(matches)?;
+    let indices = parse_values::<usize>(matches, "index")?;
+    let (mut input_files, read_stdin) = find_input_archives(matches)?;
+    if !indices.is_empty() && input_files.len() + usize::from(read_stdin) != 1 {
+        return Err(Ca2Error::InvalidArgument(String::from("--index requires a single archive")));
+    }
+    let spool_limit = parse_value::<usize>(matches, "spool-limit")?
+        .unwrap_or(spool::SPOOL_DEFAULT_MEMORY_LIMIT);
+    let stdin_archive = match read_stdin {
+        true => Some(StdinArchive::read(spool_limit)?),
+        false => None
+    };
+    let mut selected : Vec<ListedCA> = Vec::new();
+    for input_file in input_files.iter() {
+        selected.extend(list_archive(input_file, &filters)?);
+    }
+    if let Some(stdin_archive) = stdin_archive.as_ref() {
+        selected.extend(list_index(&stdin_path(), &stdin_archive.index, &filters));
+        input_files.push(stdin_path());
+    }
+    selected.retain(|entry| indices.is_empty() || indices.contains(&entry.index));
+
+    if let Some(dir) = path_value(matches, "unpack") {
+        let mut unpacked = 0;
+        for input_file in input_files.iter() {
+            let indices : Vec<usize> = selected.iter().filter(|entry| entry.archive == *input_file).map(|entry| entry.index).collect();
+            if indices.is_empty() {
+                continue;
+            }
+            let unpacked_cas = match stdin_archive.as_ref() {
+                Some(stdin_archive) if *input_file == stdin_path() =>
+                    unpack_index(|| stdin_archive.spool.try_clone(), input_file, &stdin_archive.index, &dir, Some(&indices))?,
+                _ => unpack_archive(input_file, &dir, Some(&indices))?
+            };
+            for ca in unpacked_cas {
+                if verbosity.verbose() {
+                    eprintln!("Unpacked entry {} of {} {} into {}", ca.index, input_file.display(), ca.ca_spec, ca.csv.display());
+                }
+                unpacked += 1;
+            }
+        }
+        if verbosity.normal() {
+            eprintln!("Unpacked {} CAs into {}", unpacked, dir.display());
+        }
+        return Ok(());
+    }
+
+    let Some(best) = selected.into_iter().min_by_key(|entry| entry.ca_spec.n) else {
+        if verbosity.normal() {
+            eprintln!("No matching CA found in archives.");
+        }
+        return Ok(());
+    };
+    if verbosity.verbose() {
+        eprintln!("Selected CA: {} at offset {} of {}", best.ca_spec, best.offset, best.archive.display());
+    }
+    let format = matches.value_of("format").unwrap().parse::<OutputFormat>()?;
+    let mut writer = row_writer(matches, format, path_value(matches, "output").as_deref(), !matches.is_present("no-header"), best.ca_spec.n)?;
+    let rows = match stdin_archive.as_ref() {
+        Some(stdin_archive) if best.archive == stdin_path() =>
+            extract_raw(stdin_archive.spool.try_clone()?, best.offset, &best.ca_spec, writer.as_mut())?,
+        _ => extract_raw(open_archive(&best.archive)?, best.offset, &best.ca_spec, writer.as_mut())?
+    };
+    if verbosity.normal() {
+        eprintln!("Decompressed CA with {} rows.", rows);
+    }
+    print_rendered(matches, verbosity, rows);
+    Ok(())
+}
+
+// An archive read from stdin (-), spooled so that its CAs can be decoded
+pub struct StdinArchive {
+    pub spool: Spool,
+    pub index: ArchiveIndex
+}
+
+impl StdinArchive {
+    pub fn read(spool_limit: usize) -> Ca2Result<StdinArchive> {
+        let mut spool = Spool::new(std::io::stdin().lock(), spool_limit)?;
+        let index = read_index(&mut spool).map_err(|e| match e {
+            Ca2Error::Format(msg) => Ca2Error::Format(format!("stdin: {}", msg)),
+            e => e
+        })?;
+        Ok(StdinArchive { spool, index })
+    }
+}
+
+#[cfg(feature = "mmap")]
+fn open_archive(path: &Path) -> Ca2Result<impl Read + Seek> {
+    crate::map_archive(path)
+}
+
+#[cfg(not(feature = "mmap"))]
+fn open_archive(path: &Path) -> Ca2Result<impl Read + Seek> {
+    Ok(File::open(path)?)
+}
+
+pub fn parse_request(path: &Path, strength: u8) -> Ca2Result<RequestedCA> {
+    let mut file = File::open(path)?;
+    let mut contents = String::new();
+    file.read_to_string(&mut contents)?;
+
+    // Try all supported model formats
+    parse_model(&contents, strength)
+}
@@ -0,0 +1,69 @@
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::path::Path;
+use clap::{App, ArgMatches};
+use crate::{read_archive_index, read_ccmeta, kind_name, Ca2Result};
+use super::{with_verbosity, output_arg, archives_arg, input_archives, open_output, path_value};
+
+pub fn command() -> App<'static> {
+    with_verbosity(
+        App::new("info")
+        .about("Summarizes archives (sizes, prepended data, CAs by kind and strength) and .ccmeta files")
+        .arg(output_arg("Path to the output file. If not given, the summary is printed to stdout."))
+        .arg(archives_arg("The .ca2 archives, directories containing them or .ccmeta files. If none are given, this executable (if it is a self-extracting archive) and the locations in $CA2_PATH are used."))
+    )
+}
+
+pub fn run(matches: &ArgMatches) -> Ca2Result<()> {
+    let input_files = input_archives(matches)?;
+    let mut writer = open_output(path_value(matches, "output").as_deref())?;
+    for input_file in input_files.iter() {
+        if input_file.extension().is_some_and(|ext| ext == "ccmeta") {
+            let ca_spec = read_ccmeta(input_file)?;
+            writeln!(writer, "{}: {} (version {})", input_file.display(), ca_spec, u16::from(ca_spec.version))?;
+        } else {
+            archive_info(&mut writer, input_file)?;
+        }
+    }
+    Ok(writer.flush()?)
+}
+
+fn archive_info<W: Write>(writer: &mut W, input_file: &Path) -> Ca2Result<()> {
+    let size = input_file.metadata()?.len();
+    let index = read_archive_index(input_file)?;
+
+    // Everything before the first CA is a prepended file, e.g. a self-extracting executable
+    let prepended = index.entries.iter()
+        .map(|(offset, _)| *offset)
+        .chain(index.unused.iter().copied())
+        .min()
+        .unwrap_or(index.metadata_offset);
+    let mut by_kind : BTreeMap<(u8, &str), usize> = BTreeMap::new();
+    let mut by_strength : BTreeMap<u8, usize> = BTreeMap::new();
+    for (_, ca_spec) in index.entries.iter() {
+        *by_kind.entry((u8::from(ca_spec.kind), kind_name(ca_spec.kind))).or_default() += 1;
+        *by_strength.entry(ca_spec.t).or_default() += 1;
+    }
+    let format_counts = |counts: Vec<String>| match counts.is_empty() {
+        true => String::from("none"),
+        false => counts.join(", ")
+    };
+
+    writeln!(writer, "{}", input_file.display())?;
+    writeln!(writer, "  size:       {} bytes", size)?;
+    writeln!(writer, "  prepended:  {} bytes", prepended)?;
+    writeln!(writer, "  CA data:    {} bytes", index.metadata_offset - prepended)?;
+    writeln!(writer, "  metadata:   {} bytes", size - index.metadata_offset)?;
+    writeln!(writer, "  CAs:        {}", index.entries.len())?;
+    writeln!(writer, "  by kind:    {}", format_counts(
+        by_kind.iter().map(|((_, name), count)| format!("{} {}", count, name)).collect()
+    ))?;
+    writeln!(writer, "  by t:       {}", format_counts(
+        by_strength.iter().map(|(t, count)| format!("{} with t={}", count, t)).collect()
+    ))?;
+    if let Some((_, largest)) = index.entries.iter().max_by_key(|(_, ca_spec)| ca_spec.n) {
+        writeln!(writer, "  largest:    {}", largest)?;
+    }
+    writeln!(writer, "  unused:     {} entries", index.unused.len())?;
+    Ok(())
+}
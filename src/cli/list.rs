@@ -0,0 +1,149 @@
+use std::io::Write;
+use clap::{App, Arg, ArgMatches};
+use serde_json::json;
+use crate::spool::SPOOL_DEFAULT_MEMORY_LIMIT;
+use super::extract::StdinArchive;
+use crate::{CASpec, CAKind, ListedCA, Filter, list_archive, list_index, parse_filters, stdin_path, kind_name, group_vs, Ca2Error, Ca2Result};
+use super::{with_verbosity, output_arg, no_header_arg, archives_arg, find_input_archives, open_output, path_value
+            , DEFAULT_ARCHIVES_HELP};
+
+const COLUMNS : [&str; 10] = ["archive", "index", "offset", "size", "kind", "version", "N", "t", "k", "vs"];
+const OUTPUT_FORMATS : [&str; 3] = ["table", "csv", "json"];
+
+pub fn command() -> App<'static> {
+    with_verbosity(
+        App::new("list")
+        .about("Lists the CAs stored in archives, as read from their indices")
+        .arg(where_arg("Only list CAs matching this condition"))
+        .arg(
+            Arg::new("format")
+                .help("Output format")
+                .short('f')
+                .long("format")
+                .takes_value(true)
+                .possible_values(OUTPUT_FORMATS)
+                .default_value("table"),
+        )
+        .arg(output_arg("Path to the output file. If not given, the list is printed to stdout."))
+        .arg(no_header_arg("Do not print a header line (table and CSV)"))
+        .arg(archives_arg(DEFAULT_ARCHIVES_HELP))
+    )
+}
+
+// Conditions on the entries of archives, see `Filter`
+pub fn where_arg(help: &'static str) -> Arg<'static> {
+    Arg::new("where")
+        .help(help)
+        .long_help(WHERE_HELP)
+        .short('w')
+        .long("where")
+        .takes_value(true)
+        .multiple_occurrences(true)
+}
+
+const WHERE_HELP : &str = "A condition like t=3, k>=20 or v<=4. Fields are n, t, k, v (the largest number of values), version, size, index and offset; operators are =, !=, <, <=, > and >=. All conditions have to hold.";
+
+// The conditions given with `where_arg`
+pub fn where_filters(matches: &ArgMatches) -> Ca2Result<Vec<Filter>> {
+    parse_filters(matches.values_of("where").into_iter().flatten())
+}
+
+pub fn run(matches: &ArgMatches) -> Ca2Result<()> {
+    let filters = where_filters(matches)?;
+    let (input_files, read_stdin) = find_input_archives(matches)?;
+
+    let mut listed = Vec::new();
+    for input_file in input_files.iter() {
+        listed.extend(list_archive(input_file, &filters)?);
+    }
+    if read_stdin {
+        let stdin_archive = StdinArchive::read(SPOOL_DEFAULT_MEMORY_LIMIT)?;
+        listed.extend(list_index(&stdin_path(), &stdin_archive.index, &filters));
+    }
+
+    let mut writer = open_output(path_value(matches, "output").as_deref())?;
+    let header = !matches.is_present("no-header");
+    match matches.value_of("format") {
+        Some("csv") => write_csv(&mut writer, &listed, header)?,
+        Some("json") => write_json(&mut writer, &listed)?,
+        _ => write_table(&mut writer, &listed, header)?
+    }
+    Ok(writer.flush()?)
+}
+
+fn fields(entry: &ListedCA) -> Vec<String> {
+    vec![
+        entry.archive.display().to_string(),
+        entry.index.to_string(),
+        entry.offset.to_string(),
+        entry.size.to_string(),
+        kind_name(entry.ca_spec.kind).to_string(),
+        u16::from(entry.ca_spec.version).to_string(),
+        entry.ca_spec.n.to_string(),
+        entry.ca_spec.t.to_string(),
+        entry.ca_spec.vs.len().to_string(),
+        format_vs(&entry.ca_spec)
+    ]
+}
+
+fn write_table<W: Write>(writer: &mut W, listed: &[ListedCA], header: bool) -> Ca2Result<()> {
+    let mut table : Vec<Vec<String>> = Vec::new();
+    if header {
+        table.push(COLUMNS.iter().map(|c| c.to_string()).collect());
+    }
+    table.extend(listed.iter().map(fields));
+
+    // Align all columns, numbers to the right
+    let widths : Vec<usize> = (0..COLUMNS.len())
+        .map(|c| table.iter().map(|row| row[c].len()).max().unwrap_or(0))
+        .collect();
+    for row in table.iter() {
+        let mut line = String::new();
+        for (c, value) in row.iter().enumerate() {
+            match COLUMNS[c] {
+                "archive" | "kind" => line += &format!("{:<width$}  ", value, width = widths[c]),
+                "vs" => line += value,
+                _ => line += &format!("{:>width$}  ", value, width = widths[c])
+            }
+        }
+        writeln!(writer, "{}", line)?;
+    }
+    Ok(())
+}
+
+fn write_csv<W: Write>(writer: &mut W, listed: &[ListedCA], header: bool) -> Ca2Result<()> {
+    let mut csv_writer = csv::Writer::from_writer(writer);
+    if header {
+        csv_writer.write_record(COLUMNS)?;
+    }
+    for entry in listed {
+        csv_writer.write_record(fields(entry))?;
+    }
+    Ok(csv_writer.flush()?)
+}
+
+fn write_json<W: Write>(writer: &mut W, listed: &[ListedCA]) -> Ca2Result<()> {
+    let entries : Vec<serde_json::Value> = listed.iter().map(|entry| json!({
+        "archive": entry.archive.display().to_string(),
+        "index": entry.index,
+        "offset": entry.offset,
+        "size": entry.size,
+        "kind": kind_name(entry.ca_spec.kind),
+        "version": u16::from(entry.ca_spec.version),
+        "n": entry.ca_spec.n,
+        "t": entry.ca_spec.t,
+        "k": entry.ca_spec.vs.len(),
+        "vs": entry.ca_spec.vs
+    })).collect();
+    serde_json::to_writer_pretty(&mut *writer, &entries)
+        .map_err(|e| Ca2Error::Io(e.into()))?;
+    Ok(writeln!(writer)?)
+}
+
+// The numbers of values in exponential notation, e.g. 3^2 2^2, or the number of events of an SCA
+pub fn format_vs(ca_spec: &CASpec) -> String {
+    match ca_spec.kind {
+        CAKind::Sequence => format!("{} events", ca_spec.vs.len()),
+        _ => group_vs(&ca_spec.vs).iter().map(|(v, count)| format!("{}^{}", v, count)).collect::<Vec<_>>().join(" ")
+    }
+}
@@ -0,0 +1,212 @@
+use std::fs::File;
+use std::io::{Write, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::str::FromStr;
+use clap::{App, Arg, ArgMatches};
+use crate::{find_archives, default_archive_locations, contains_index, stdin_path, Ca2Error, Ca2Result};
+
+// The subcommands of ca2. cca, pca and dca run `compress`, `pack` and `extract` (and `serve`) directly.
+pub mod compress;
+pub mod pack;
+pub mod extract;
+pub mod list;
+pub mod verify;
+pub mod info;
+pub mod serve;
+
+// How much a command reports on stderr. The output itself (CSV, listings) is not affected.
+#[derive(Debug,Clone,Copy,PartialEq,Eq,PartialOrd,Ord)]
+pub enum Verbosity {
+    // Only errors
+    Quiet,
+    // A summary of what was done
+    Normal,
+    // Every step
+    Verbose
+}
+
+impl Verbosity {
+    pub fn from_matches(matches: &ArgMatches) -> Verbosity {
+        if matches.is_present("quiet") {
+            Verbosity::Quiet
+        } else if matches.is_present("verbose") {
+            Verbosity::Verbose
+        } else {
+            Verbosity::Normal
+        }
+    }
+
+    pub fn normal(&self) -> bool {
+        *self >= Verbosity::Normal
+    }
+
+    pub fn verbose(&self) -> bool {
+        *self >= Verbosity::Verbose
+    }
+}
+
+// The ca2 command with all subcommands
+pub fn command() -> App<'static> {
+    App::new("ca2")
+    .about("Compresses, packs, lists, verifies and extracts covering arrays in CA2 archives")
+    .subcommand_required(true)
+    .arg_required_else_help(true)
+    .subcommand(compress::command())
+    .subcommand(pack::command())
+    .subcommand(extract::command())
+    .subcommand(list::command())
+    .subcommand(verify::command())
+    .subcommand(info::command())
+    .subcommand(serve::command())
+}
+
+// Runs the subcommand given to ca2
+pub fn run(matches: &ArgMatches) -> Ca2Result<()> {
+    match matches.subcommand() {
+        Some(("compress", matches)) => compress::run(matches),
+        Some(("pack", matches)) => pack::run(matches),
+        Some(("extract", matches)) => extract::run(matches),
+        Some(("list", matches)) => list::run(matches),
+        Some(("verify", matches)) => verify::run(matches),
+        Some(("info", matches)) => info::run(matches),
+        Some(("serve", matches)) => serve::run(matches),
+        _ => unreachable!("clap requires a subcommand")
+    }
+}
+
+// The exit status of a binary for the result of a command. Errors are printed to stderr.
+pub fn exit_code(result: Ca2Result<()>) -> ExitCode {
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+// Adds the verbosity flags that every command takes
+pub fn with_verbosity(app: App<'static>) -> App<'static> {
+    app
+    .arg(
+        Arg::new("quiet")
+            .help("Only report errors")
+            .short('q')
+            .long("quiet")
+            .takes_value(false),
+    )
+    .arg(
+        Arg::new("verbose")
+            .help("Report every step")
+            .long("verbose")
+            .conflicts_with("quiet")
+            .takes_value(false),
+    )
+}
+
+// The output file of a command
+pub fn output_arg(help: &'static str) -> Arg<'static> {
+    Arg::new("output")
+        .help(help)
+        .short('o')
+        .long("output")
+        .takes_value(true)
+        .allow_hyphen_values(true)
+        .allow_invalid_utf8(true)
+}
+
+// Disables the header line of CSV input or output
+pub fn no_header_arg(help: &'static str) -> Arg<'static> {
+    Arg::new("no-header")
+        .help(help)
+        .short('n')
+        .long("no-header")
+        .takes_value(false)
+}
+
+// Archives, directories containing them or glob patterns
+pub fn archives_arg(help: &'static str) -> Arg<'static> {
+    Arg::new("input_files")
+        .help(help)
+        .takes_value(true)
+        .multiple_values(true)
+        .allow_invalid_utf8(true)
+}
+
+// Help text for `archives_arg` if no archives are required
+pub const DEFAULT_ARCHIVES_HELP : &str = "The input .ca2 archives or directories containing them. If none are given, this executable (if it is a self-extracting archive) and the locations in $CA2_PATH are used.";
+
+pub fn path_value(matches: &ArgMatches, name: &str) -> Option<PathBuf> {
+    matches.value_of_os(name).map(PathBuf::from)
+}
+
+// Parses the value of an argument, if it is present
+pub fn parse_value<T: FromStr>(matches: &ArgMatches, name: &str) -> Ca2Result<Option<T>> {
+    matches.value_of(name)
+        .map(|value| value.parse::<T>().map_err(|_| Ca2Error::InvalidArgument(format!("Invalid {}: {}", name, value))))
+        .transpose()
+}
+
+// Parses all values of an argument
+pub fn parse_values<T: FromStr>(matches: &ArgMatches, name: &str) -> Ca2Result<Vec<T>> {
+    matches.values_of(name)
+        .map(|values| values
+            .map(|value| value.parse::<T>().map_err(|_| Ca2Error::InvalidArgument(format!("Invalid {}: {}", name, value))))
+            .collect())
+        .unwrap_or_else(|| Ok(Vec::new()))
+}
+
+// Opens the output file, or stdout if there is none. Existing files are not overwritten.
+pub fn open_output(output: Option<&Path>) -> Ca2Result<Box<dyn Write>> {
+    match output {
+        Some(path) if path.exists() => Err(Ca2Error::InvalidArgument(format!("Output file {} already exists.", path.display()))),
+        Some(path) => Ok(Box::new(BufWriter::new(File::create(path)?))),
+        None => Ok(Box::new(BufWriter::new(std::io::stdout())))
+    }
+}
+
+// The archive locations given on the command line, or the default ones
+pub fn archive_locations(matches: &ArgMatches) -> Ca2Result<Vec<PathBuf>> {
+    if let Some(values) = matches.values_of_os("input_files") {
+        // We have input files on the command line, use these
+        return Ok(values.map(PathBuf::from).collect());
+    }
+    // Use the current executable as archive (self-extraction), if it is one,
+    // and the default archive locations
+    let current_exe = std::env::current_exe().map_err(|_| Ca2Error::InvalidArgument(String::from(
+        "Could not determine path to self-extracting archive, please add it as an argument"
+    )))?;
+    let mut locations = default_archive_locations();
+    if contains_index(&current_exe) {
+        locations.insert(0, current_exe);
+    }
+    Ok(locations)
+}
+
+// Finds the archives at the locations given on the command line (or the default ones)
+pub fn input_archives(matches: &ArgMatches) -> Ca2Result<Vec<PathBuf>> {
+    let (input_files, read_stdin) = find_input_archives(matches)?;
+    if read_stdin {
+        return Err(Ca2Error::InvalidArgument(String::from(
+            "This command can not read an archive from stdin (-), only extract and list can"
+        )));
+    }
+    Ok(input_files)
+}
+
+// Like `input_archives`, but `-` names an archive read from stdin as well.
+// Returns the other archives and whether stdin is one of the archives.
+pub fn find_input_archives(matches: &ArgMatches) -> Ca2Result<(Vec<PathBuf>, bool)> {
+    let (stdin_files, locations) : (Vec<PathBuf>, Vec<PathBuf>) = archive_locations(matches)?
+        .into_iter()
+        .partition(|f| *f == stdin_path());
+    if stdin_files.len() > 1 {
+        return Err(Ca2Error::InvalidArgument(String::from("stdin can only be read once")));
+    }
+    let input_files = find_archives(&locations)?;
+    if input_files.is_empty() && stdin_files.is_empty() {
+        return Err(Ca2Error::InvalidArgument(String::from("No valid input files.")));
+    }
+    Ok((input_files, !stdin_files.is_empty()))
+}
@@ -0,0 +1,214 @@
+use std::path::PathBuf;
+use clap::{App, Arg, ArgMatches};
+use crate::{ArchiveEdit, Pruning, PackReport, read_input_cas, create_archive, edit_archive, compact_archive, recover_archive, Ca2Error, Ca2Result};
+use super::{Verbosity, with_verbosity, path_value, parse_value, parse_values};
+
+// Compressed CA packager
+//
+// Command line arguments:
+// 1. Output file (required)
+// 2. Prepend file (optional)
+// 3. Input files (all remaining args)
+// 4. Optionally, an operation on an existing output file
+pub fn command() -> App<'static> {
+    with_verbosity(
+        App::new("pack")
+        .about("Packages pairs of .cca and .ccmeta files into a .ca2 archive, or edits an existing archive")
+        .arg(
+            Arg::new("output")
+                .help("The output .ca2 file")
+                .short('o')
+                .long("output")
+                .required(true)
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .allow_invalid_utf8(true),
+        )
+        .arg(
+            Arg::new("prepend_file")
+                .help("An optional file to place at the beginning of the output (e.g. `dca`)")
+                .short('p')
+                .long("pre")
+                .required(false)
+                .takes_value(true)
+                .allow_hyphen_values(true)
+                .allow_invalid_utf8(true),
+        )
+        .arg(
+            Arg::new("input_files")
+                .help("The input .cca and .ccmeta files (in any order)")
+                .required_unless_present_any(["remove", "compact", "prune", "recover"])
+                .takes_value(true)
+                .multiple_values(true)
+                .allow_invalid_utf8(true),
+        )
+        .arg(
+            Arg::new("append")
+                .help("Append the input files to the existing output file")
+                .short('a')
+                .long("append")
+                .conflicts_with_all(&["replace", "remove", "prepend_file"])
+                .takes_value(false),
+        )
+        .arg(
+            Arg::new("replace")
+                .help("Replace the entry with this index in the existing output file by the (single) input CA")
+                .short('r')
+                .long("replace")
+                .conflicts_with_all(&["remove", "prepend_file"])
+                .takes_value(true),
+        )
+        .arg(
+            Arg::new("remove")
+                .help("Remove the entries with these indices from the existing output file")
+                .short('d')
+                .long("remove")
+                .conflicts_with_all(&["input_files", "prepend_file"])
+                .takes_value(true)
+                .multiple_occurrences(true),
+        )
+        .arg(
+            Arg::new("prune")
+                .help("Drop CAs for which a compatible CA with at most as many rows exists. New CAs replace existing entries they improve on.")
+                .long("prune")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::new("keep-dominated")
+                .help("When appending, keep existing entries even if a new CA improves on them (they are replaced by default)")
+                .long("keep-dominated")
+                .conflicts_with("prune")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::new("compact")
+                .help("Reclaim space left behind by replaced or removed entries in the output file")
+                .short('c')
+                .long("compact")
+                .conflicts_with("prepend_file")
+                .takes_value(false),
+        )
+        .arg(
+            Arg::new("recover")
+                .help("Undo an interrupted edit of the existing output file. Edits do this by themselves.")
+                .long("recover")
+                .conflicts_with("prepend_file")
+                .takes_value(false),
+        )
+    )
+}
+
+pub fn run(matches: &ArgMatches) -> Ca2Result<()> {
+    let verbosity = Verbosity::from_matches(matches);
+    let output_file = path_value(matches, "output").unwrap();
+    let prepend_file = path_value(matches, "prepend_file");
+    if prepend_file.as_ref().is_some_and(|f| !f.is_file()) {
+        return Err(Ca2Error::InvalidArgument(String::from("Prepend file does not exist.")));
+    }
+    let input_files : Vec<PathBuf> = matches
+        .values_of_os("input_files")
+        .map(|values| values.map(PathBuf::from).collect())
+        .unwrap_or_default();
+    let prune = matches.is_present("prune");
+    let pruning = match (prune, matches.is_present("keep-dominated")) {
+        (true, _) => Pruning::All,
+        (false, true) => Pruning::Off,
+        (false, false) => Pruning::Replace
+    };
+
+    // Without an operation on an existing archive, a new one is created
+    let edit = if matches.is_present("append") {
+        Some(ArchiveEdit::Append)
+    } else if let Some(i) = parse_value(matches, "replace")? {
+        Some(ArchiveEdit::Replace(i))
+    } else if matches.is_present("remove") {
+        Some(ArchiveEdit::Remove(parse_values(matches, "remove")?))
+    } else if input_files.is_empty() {
+        Some(ArchiveEdit::Keep)
+    } else {
+        None
+    };
+    match edit {
+        None if output_file.is_file() => return Err(Ca2Error::InvalidArgument(format!(
+            "Output file {} already exists", output_file.display()
+        ))),
+        Some(_) if !output_file.is_file() => return Err(Ca2Error::InvalidArgument(format!(
+            "Output file {} does not exist", output_file.display()
+        ))),
+        _ => {}
+    }
+
+    if matches.is_present("recover") {
+        let recovered = recover_archive(&output_file)?;
+        if verbosity.normal() {
+            match recovered {
+                true => eprintln!("Undid an interrupted edit of {}", output_file.display()),
+                false => eprintln!("No interrupted edit of {} to undo", output_file.display())
+            }
+        }
+    }
+
+    if verbosity.verbose() {
+        eprintln!("Parsing CA specifications...");
+    }
+    let cas = read_input_cas(&input_files)?;
+
+    match edit {
+        None => {
+            if cas.is_empty() {
+                return Err(Ca2Error::InvalidArgument(String::from("No valid input files.")));
+            }
+            let report = create_archive(&output_file, prepend_file.as_deref(), cas, prune)?;
+            print_report(&report, None, prune, verbosity);
+            if verbosity.normal() {
+                eprintln!("Finished writing archive {} with {} CAs", output_file.display(), report.added.len());
+            }
+        },
+        Some(ArchiveEdit::Keep) if !prune => {},
+        Some(edit) => {
+            let report = edit_archive(&output_file, &edit, cas, pruning)?;
+            print_report(&report, Some(&edit), prune, verbosity);
+            if verbosity.normal() {
+                eprintln!("Finished updating archive {}", output_file.display());
+            }
+        }
+    }
+
+    if matches.is_present("compact") {
+        if verbosity.verbose() {
+            eprintln!("Compacting archive...");
+        }
+        let reclaimed = compact_archive(&output_file)?;
+        if verbosity.normal() {
+            eprintln!("Reclaimed {} bytes", reclaimed);
+        }
+    }
+
+    Ok(())
+}
+
+fn print_report(report: &PackReport, edit: Option<&ArchiveEdit>, prune: bool, verbosity: Verbosity) {
+    if !verbosity.normal() {
+        return;
+    }
+    for i in report.removed.iter() {
+        match edit {
+            Some(ArchiveEdit::Replace(_)) => eprintln!("Replacing entry {}", i),
+            _ => eprintln!("Removing entry {}", i)
+        }
+    }
+    for replaced in report.replaced.iter() {
+        eprintln!("Replacing {} {} by {} {}", replaced.name, replaced.ca_spec, replaced.dominated_by, replaced.dominating_spec);
+    }
+    for pruned in report.pruned.iter() {
+        eprintln!("Pruning {} {}: dominated by {} {}", pruned.name, pruned.ca_spec, pruned.dominated_by, pruned.dominating_spec);
+    }
+    if prune {
+        eprintln!("Pruned {} of {} CAs", report.pruned.len(), report.considered);
+    }
+    if verbosity.verbose() {
+        for (offset, ca) in report.added.iter() {
+            eprintln!("Wrote compressed CA {} {} at offset {}", ca.cca.display(), ca.ca_spec, offset);
+        }
+    }
+}
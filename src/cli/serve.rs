@@ -0,0 +1,40 @@
+use std::net::TcpListener;
+use clap::{App, Arg, ArgMatches};
+use crate::{Server, Ca2Result};
+use crate::serve::DEFAULT_WORKERS;
+use super::{Verbosity, with_verbosity, archives_arg, input_archives, parse_value, DEFAULT_ARCHIVES_HELP};
+
+pub fn command() -> App<'static> {
+    with_verbosity(
+        App::new("serve")
+        .about("Loads the archives once and serves CAs over HTTP: GET /health, GET /archives, POST /ca?t=2 with a model as body, or GET /ca?t=2&vs=3,2,2. /ca also takes format=csv|json and header=false.")
+        .arg(
+            Arg::new("listen")
+                .help("The address to listen on")
+                .long("listen")
+                .required(false)
+                .takes_value(true)
+                .default_value("127.0.0.1:8320"),
+        )
+        .arg(
+            Arg::new("workers")
+                .help("Number of connections that are handled at the same time, further connections wait. Defaults to 16.")
+                .long("workers")
+                .takes_value(true),
+        )
+        .arg(archives_arg(DEFAULT_ARCHIVES_HELP))
+    )
+}
+
+// Serves CAs from the archives until the process is stopped
+pub fn run(matches: &ArgMatches) -> Ca2Result<()> {
+    let verbosity = Verbosity::from_matches(matches);
+    let workers = parse_value::<usize>(matches, "workers")?.unwrap_or(DEFAULT_WORKERS);
+    let input_files = input_archives(matches)?;
+    let server = Server::new(&input_files)?;
+    let listener = TcpListener::bind(matches.value_of("listen").unwrap())?;
+    if verbosity.normal() {
+        eprintln!("Serving CAs from {} archives on http://{}", input_files.len(), listener.local_addr()?);
+    }
+    server.run(listener, workers)
+}
@@ -0,0 +1,58 @@
+use std::fs::File;
+use std::io::Write;
+use clap::{App, Arg, ArgMatches};
+use crate::{verify_ca, extract_ca_specs, Ca2Error, Ca2Result};
+use super::{Verbosity, with_verbosity, output_arg, archives_arg, input_archives, open_output, path_value
+            , DEFAULT_ARCHIVES_HELP};
+
+pub fn command() -> App<'static> {
+    with_verbosity(
+        App::new("verify")
+        .about("Decodes every CA in the archives and checks it against its metadata")
+        .arg(
+            Arg::new("coverage")
+                .help("Also check that the rows cover all t-way interactions (or t-sequences of events). This takes considerably longer for large CAs.")
+                .long("coverage")
+                .takes_value(false),
+        )
+        .arg(output_arg("Path to the output file for the results. If not given, they are printed to stdout."))
+        .arg(archives_arg(DEFAULT_ARCHIVES_HELP))
+    )
+}
+
+pub fn run(matches: &ArgMatches) -> Ca2Result<()> {
+    let verbosity = Verbosity::from_matches(matches);
+    let check_coverage = matches.is_present("coverage");
+    let input_files = input_archives(matches)?;
+    let mut writer = open_output(path_value(matches, "output").as_deref())?;
+
+    let mut total = 0;
+    let mut failed = 0;
+    for input_file in input_files.iter() {
+        for (i, (offset, ca_spec)) in extract_ca_specs(input_file)?.iter().enumerate() {
+            if verbosity.verbose() {
+                eprintln!("Verifying {} in {}...", ca_spec, input_file.display());
+            }
+            total += 1;
+            let result = verify_ca(File::open(input_file)?, *offset, ca_spec, check_coverage);
+            let status = match result {
+                Ok(verification) if verification.uncovered.is_none_or(|uncovered| uncovered == 0) => String::from("ok"),
+                Ok(verification) => format!("FAILED: {} uncovered interactions", verification.uncovered.unwrap()),
+                Err(e) => format!("FAILED: {}", e)
+            };
+            if status != "ok" {
+                failed += 1;
+            }
+            writeln!(writer, "{} {} {}: {}", input_file.display(), i, ca_spec, status)?;
+        }
+    }
+    writer.flush()?;
+
+    if verbosity.normal() {
+        eprintln!("Verified {} CAs in {} archives, {} failed", total, input_files.len(), failed);
+    }
+    match failed {
+        0 => Ok(()),
+        _ => Err(Ca2Error::Format(format!("{} of {} CAs failed verification", failed, total)))
+    }
+}
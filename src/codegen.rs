@@ -0,0 +1,354 @@
+use std::io::Write;
+use std::sync::Arc;
+use crate::output::{ValueType, value_type};
+use crate::{CAWriter, Ca2Result};
+
+// Test frameworks that test code can be generated for. Every row of the CA becomes one test case,
+// and parameter names are turned into identifiers of the target language.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum TestFramework {
+    // A pytest function with @pytest.mark.parametrize
+    Pytest,
+    // A JUnit 5 @ParameterizedTest with @CsvSource
+    Junit,
+    // A Rust test function with rstest cases
+    Rstest,
+    // A Gherkin Scenario Outline with the rows as Examples
+    Gherkin
+}
+
+// Name of the generated test (or class, or feature)
+const TEST_NAME : &str = "covering_array";
+const JUNIT_CLASS_NAME : &str = "CoveringArrayTest";
+
+impl TestFramework {
+    pub fn writer<'a, W: Write + 'a>(&self, writer: W) -> Box<dyn CAWriter + 'a> {
+        let generator = TestGenerator { writer, framework: *self, names: Vec::new(), types: Vec::new(), rows: 0 };
+        Box::new(generator)
+    }
+}
+
+const PYTHON_KEYWORDS : &[&str] = &[
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+    "elif", "else", "except", "finally", "for", "from", "global", "if", "import", "in", "is", "lambda", "nonlocal",
+    "not", "or", "pass", "raise", "return", "try", "while", "with", "yield", "pytest"
+];
+
+const JAVA_KEYWORDS : &[&str] = &[
+    "abstract", "assert", "boolean", "break", "byte", "case", "catch", "char", "class", "const", "continue",
+    "default", "do", "double", "else", "enum", "extends", "final", "finally", "float", "for", "goto", "if",
+    "implements", "import", "instanceof", "int", "interface", "long", "native", "new", "package", "private",
+    "protected", "public", "return", "short", "static", "strictfp", "super", "switch", "synchronized", "this",
+    "throw", "throws", "transient", "try", "void", "volatile", "while", "true", "false", "null", "var", "record",
+    "yield", "_"
+];
+
+const RUST_KEYWORDS : &[&str] = &[
+    "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum", "extern", "false", "fn",
+    "for", "gen", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut", "pub", "ref", "return", "self",
+    "static", "struct", "super", "trait", "true", "try", "type", "unsafe", "use", "where", "while", "abstract",
+    "become", "box", "do", "final", "macro", "override", "priv", "typeof", "unsized", "virtual", "yield", "_"
+];
+
+// Turns parameter names into distinct identifiers: characters other than ASCII letters, digits and `_`
+// become `_`, identifiers starting with a digit get a leading `_`, and keywords a trailing one.
+pub fn identifiers(names: &[String], keywords: &[&str], lowercase: bool) -> Vec<String> {
+    number_duplicates(names.iter().map(|name| {
+        let mut identifier : String = name.chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        if lowercase {
+            identifier.make_ascii_lowercase();
+        }
+        if identifier.is_empty() || identifier.starts_with(|c: char| c.is_ascii_digit()) {
+            identifier.insert(0, '_');
+        }
+        if keywords.contains(&identifier.as_str()) {
+            identifier.push('_');
+        }
+        identifier
+    }))
+}
+
+// Appends _2, _3, ... to names that occur before
+fn number_duplicates(names: impl Iterator<Item = String>) -> Vec<String> {
+    let mut out : Vec<String> = Vec::new();
+    for name in names {
+        let unique = (1..)
+            .map(|i| match i {
+                1 => name.clone(),
+                _ => format!("{}_{}", name, i)
+            })
+            .find(|candidate| !out.contains(candidate))
+            .unwrap();
+        out.push(unique);
+    }
+    out
+}
+
+// The type of a parameter in generated code. Java int parameters that do not fit into an int are longs.
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+enum ParameterType {
+    Int,
+    Long,
+    Boolean,
+    Text
+}
+
+fn parameter_type(values: &[String]) -> ParameterType {
+    match value_type(values) {
+        ValueType::Int if values.iter().all(|value| value.parse::<i32>().is_ok()) => ParameterType::Int,
+        ValueType::Int => ParameterType::Long,
+        ValueType::Boolean => ParameterType::Boolean,
+        ValueType::Text => ParameterType::Text
+    }
+}
+
+// Integers are written without leading zeros or plus signs, which some languages do not accept
+fn integer_literal(value: &str) -> String {
+    value.parse::<i64>().map(|i| i.to_string()).unwrap_or_else(|_| value.to_string())
+}
+
+fn python_literal(value: &str, parameter_type: ParameterType) -> String {
+    match parameter_type {
+        ParameterType::Int | ParameterType::Long => integer_literal(value),
+        ParameterType::Boolean if value == "true" => String::from("True"),
+        ParameterType::Boolean => String::from("False"),
+        // JSON strings are valid Python strings
+        ParameterType::Text => serde_json::Value::from(value).to_string()
+    }
+}
+
+fn rust_literal(value: &str, parameter_type: ParameterType) -> String {
+    match parameter_type {
+        ParameterType::Int | ParameterType::Long => integer_literal(value),
+        ParameterType::Boolean => value.to_string(),
+        // Debug formatting escapes strings like Rust literals
+        ParameterType::Text => format!("{:?}", value)
+    }
+}
+
+fn java_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            // Java replaces \u escapes before parsing, so octal escapes are used
+            c if c.is_control() => out.push_str(&format!("\\{:03o}", c as u32)),
+            c => out.push(c)
+        }
+    }
+    out.push('"');
+    out
+}
+
+// A value in a @CsvSource line, which uses ' to quote values
+fn csv_source_value(value: &str, parameter_type: ParameterType) -> String {
+    match parameter_type {
+        ParameterType::Text if value.is_empty() || value.contains([',', '\'']) || value.trim() != value =>
+            format!("'{}'", value.replace('\'', "''")),
+        ParameterType::Int | ParameterType::Long => integer_literal(value),
+        _ => value.to_string()
+    }
+}
+
+fn gherkin_cell(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('|', "\\|").replace('\n', "\\n")
+}
+
+struct TestGenerator<W: Write> {
+    writer: W,
+    framework: TestFramework,
+    // Identifiers, or the column names of Gherkin
+    names: Vec<String>,
+    types: Vec<ParameterType>,
+    rows: u64
+}
+
+impl<W: Write> CAWriter for TestGenerator<W> {
+    fn begin(&mut self, names: &[String], values: &[Arc<[String]>]) -> Ca2Result<()> {
+        self.types = values.iter().map(|values| parameter_type(values)).collect();
+        let w = &mut self.writer;
+        match self.framework {
+            TestFramework::Pytest => {
+                self.names = identifiers(names, PYTHON_KEYWORDS, false);
+                writeln!(w, "import pytest\n\n")?;
+                writeln!(w, "@pytest.mark.parametrize(\n    \"{}\",\n    [", self.names.join(", "))?;
+            },
+            TestFramework::Junit => {
+                self.names = identifiers(names, JAVA_KEYWORDS, false);
+                writeln!(w, "import org.junit.jupiter.params.ParameterizedTest;")?;
+                writeln!(w, "import org.junit.jupiter.params.provider.CsvSource;\n")?;
+                writeln!(w, "class {} {{\n\n    @ParameterizedTest\n    @CsvSource({{", JUNIT_CLASS_NAME)?;
+            },
+            TestFramework::Rstest => {
+                self.names = identifiers(names, RUST_KEYWORDS, true);
+                writeln!(w, "use rstest::rstest;\n\n#[rstest]")?;
+            },
+            TestFramework::Gherkin => {
+                // Placeholders end at >, and cells at |
+                self.names = number_duplicates(names.iter().map(|name| name.replace(['<', '>', '|', '\n'], "_")));
+                writeln!(w, "Feature: {}\n\n  Scenario Outline: {}", TEST_NAME, TEST_NAME)?;
+                for (i, name) in self.names.iter().enumerate() {
+                    let keyword = if i == 0 { "Given" } else { "And" };
+                    writeln!(w, "    {} {} is \"<{}>\"", keyword, name, name)?;
+                }
+                writeln!(w, "\n    Examples:")?;
+                let cells : Vec<String> = self.names.iter().map(|name| gherkin_cell(name)).collect();
+                writeln!(w, "      | {} |", cells.join(" | "))?;
+            }
+        }
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &[&str]) -> Ca2Result<()> {
+        let types = self.types.iter();
+        let w = &mut self.writer;
+        match self.framework {
+            TestFramework::Pytest => {
+                let literals : Vec<String> = row.iter().zip(types).map(|(value, t)| python_literal(value, *t)).collect();
+                // One-element tuples need a trailing comma
+                match literals.len() {
+                    1 => writeln!(w, "        ({},),", literals[0])?,
+                    _ => writeln!(w, "        ({}),", literals.join(", "))?
+                }
+            },
+            TestFramework::Junit => {
+                let values : Vec<String> = row.iter().zip(types).map(|(value, t)| csv_source_value(value, *t)).collect();
+                let separator = if self.rows == 0 { "" } else { ",\n" };
+                write!(w, "{}        {}", separator, java_string(&values.join(", ")))?;
+            },
+            TestFramework::Rstest => {
+                let literals : Vec<String> = row.iter().zip(types).map(|(value, t)| rust_literal(value, *t)).collect();
+                writeln!(w, "#[case({})]", literals.join(", "))?;
+            },
+            TestFramework::Gherkin => {
+                let cells : Vec<String> = row.iter().map(|value| gherkin_cell(value)).collect();
+                writeln!(w, "      | {} |", cells.join(" | "))?;
+            }
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Ca2Result<()> {
+        let w = &mut self.writer;
+        match self.framework {
+            TestFramework::Pytest => {
+                writeln!(w, "    ],\n)\ndef test_{}({}):", TEST_NAME, self.names.join(", "))?;
+                writeln!(w, "    # TODO: exercise the system under test with this combination\n    pass")?;
+            },
+            TestFramework::Junit => {
+                let parameters : Vec<String> = self.names.iter().zip(self.types.iter())
+                    .map(|(name, t)| format!("{} {}", match t {
+                        ParameterType::Int => "int",
+                        ParameterType::Long => "long",
+                        ParameterType::Boolean => "boolean",
+                        ParameterType::Text => "String"
+                    }, name))
+                    .collect();
+                writeln!(w, "\n    }})\n    void test({}) {{", parameters.join(", "))?;
+                writeln!(w, "        // TODO: exercise the system under test with this combination\n    }}\n}}")?;
+            },
+            TestFramework::Rstest => {
+                let parameters : Vec<String> = self.names.iter().zip(self.types.iter())
+                    .map(|(name, t)| format!("#[case] {}: {}", name, match t {
+                        ParameterType::Int | ParameterType::Long => "i64",
+                        ParameterType::Boolean => "bool",
+                        ParameterType::Text => "&str"
+                    }))
+                    .collect();
+                writeln!(w, "fn {}({}) {{", TEST_NAME, parameters.join(", "))?;
+                writeln!(w, "    // TODO: exercise the system under test with this combination")?;
+                match self.names.len() {
+                    1 => writeln!(w, "    let _ = {};\n}}", self.names[0])?,
+                    _ => writeln!(w, "    let _ = ({});\n}}", self.names.join(", "))?
+                }
+            },
+            TestFramework::Gherkin => {}
+        }
+        Ok(self.writer.flush()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Generates tests for the rows of a CA whose columns are the given values
+    fn generate(framework: TestFramework, names: &[&str], rows: &[&[&str]]) -> String {
+        let names : Vec<String> = names.iter().map(|name| name.to_string()).collect();
+        let values : Vec<Arc<[String]>> = (0..names.len())
+            .map(|i| rows.iter().map(|row| row[i].to_string()).collect())
+            .collect();
+        let mut out = Vec::new();
+        {
+            let mut writer = framework.writer(&mut out);
+            writer.begin(&names, &values).unwrap();
+            for row in rows.iter() {
+                writer.write_row(row).unwrap();
+            }
+            writer.finish().unwrap();
+        }
+        String::from_utf8(out).unwrap()
+    }
+
+    const NAMES : &[&str] = &["class", "fn", "1st", "a b", "a_b", "Self"];
+    const ROWS : &[&[&str]] = &[
+        &["say \"hi\"", "007", "true", "x", "back\\slash", "it's, here"],
+        &["plain", "-3", "false", "y", "", " padded "]
+    ];
+
+    #[test]
+    fn identifiers_avoid_keywords_and_digits() {
+        let names : Vec<String> = NAMES.iter().map(|name| name.to_string()).collect();
+        assert_eq!(identifiers(&names, PYTHON_KEYWORDS, false), ["class_", "fn", "_1st", "a_b", "a_b_2", "Self"]);
+        assert_eq!(identifiers(&names, JAVA_KEYWORDS, false), ["class_", "fn", "_1st", "a_b", "a_b_2", "Self"]);
+        assert_eq!(identifiers(&names, RUST_KEYWORDS, true), ["class", "fn_", "_1st", "a_b", "a_b_2", "self_"]);
+        let odd : Vec<String> = ["", "_", "pytest", "ä"].iter().map(|name| name.to_string()).collect();
+        assert_eq!(identifiers(&odd, PYTHON_KEYWORDS, false), ["_", "__2", "pytest_", "__3"]);
+        assert_eq!(identifiers(&odd, RUST_KEYWORDS, true), ["__", "___2", "pytest", "___3"]);
+    }
+
+    #[test]
+    fn pytest_literals() {
+        let code = generate(TestFramework::Pytest, NAMES, ROWS);
+        assert!(code.contains("    \"class_, fn, _1st, a_b, a_b_2, Self\",\n"));
+        assert!(code.contains("        (\"say \\\"hi\\\"\", 7, True, \"x\", \"back\\\\slash\", \"it's, here\"),\n"));
+        assert!(code.contains("        (\"plain\", -3, False, \"y\", \"\", \" padded \"),\n"));
+        assert!(code.contains("def test_covering_array(class_, fn, _1st, a_b, a_b_2, Self):"));
+        // Single parameters are one-element tuples
+        assert!(generate(TestFramework::Pytest, &["v"], &[&["1"]]).contains("        (1,),\n"));
+    }
+
+    #[test]
+    fn junit_literals() {
+        let code = generate(TestFramework::Junit, NAMES, ROWS);
+        assert!(code.contains("        \"say \\\"hi\\\", 7, true, x, back\\\\slash, 'it''s, here'\",\n"));
+        assert!(code.contains("        \"plain, -3, false, y, '', ' padded '\"\n    })"));
+        assert!(code.contains("void test(String class_, int fn, boolean _1st, String a_b, String a_b_2, String Self) {"));
+        assert!(generate(TestFramework::Junit, &["big"], &[&["5000000000"], &["1"]]).contains("void test(long big)"));
+        assert!(generate(TestFramework::Junit, &["c"], &[&["bell\u{7}"]]).contains("\"bell\\007\""));
+    }
+
+    #[test]
+    fn rstest_literals() {
+        let code = generate(TestFramework::Rstest, NAMES, ROWS);
+        assert!(code.contains("#[case(\"say \\\"hi\\\"\", 7, true, \"x\", \"back\\\\slash\", \"it's, here\")]\n"));
+        assert!(code.contains("#[case(\"plain\", -3, false, \"y\", \"\", \" padded \")]\n"));
+        assert!(code.contains("fn covering_array(#[case] class: &str, #[case] fn_: i64, #[case] _1st: bool, #[case] a_b: &str, #[case] a_b_2: &str, #[case] self_: &str) {"));
+        assert!(code.contains("    let _ = (class, fn_, _1st, a_b, a_b_2, self_);\n"));
+    }
+
+    #[test]
+    fn gherkin_cells() {
+        let code = generate(TestFramework::Gherkin, &["a|b", "<c>", "class"], &[&["x|y", "say \"hi\"", "line\nbreak"]]);
+        assert!(code.contains("    Given a_b is \"<a_b>\"\n    And _c_ is \"<_c_>\"\n    And class is \"<class>\"\n"));
+        assert!(code.contains("      | a_b | _c_ | class |\n"));
+        assert!(code.contains("      | x\\|y | say \"hi\" | line\\nbreak |\n"));
+    }
+}
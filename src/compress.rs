@@ -0,0 +1,308 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{Read, Write, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use bzip2::write::BzEncoder;
+use bzip2::Compression;
+use crate::{CASpec, CAKind, CA2_DEFAULT_VERSION, MAGIC_BYTES_CCA, Value, InputFormat, CAAnalysis, detect_format, import_ca, analyze_rows, Ca2Error, Ca2Result};
+
+// The number of bytes at the beginning of a CA file used to detect its format
+const DETECT_FORMAT_BYTES : u64 = 64 * 1024;
+
+// Compresses a CA given as CSV into raw CA data (the contents of a .cca file) and returns its specification.
+// The values of each column are numbered in the order they first appear. The columns can be in any order,
+// they are stored sorted descending by their number of values (keeping the order of columns with the same number).
+pub fn compress_csv<R: Read, W: Write>(reader: R, has_header: bool, t: u8, vs: &[u16], writer: W) -> Ca2Result<CASpec> {
+    compress_rows(csv_rows(reader, has_header), t, vs, writer)
+}
+
+// Compresses a CA given as rows of values, see `compress_csv`
+pub fn compress_rows<I, R, W>(rows: I, t: u8, vs: &[u16], writer: W) -> Ca2Result<CASpec>
+where I: IntoIterator<Item = Ca2Result<R>>, R: AsRef<[String]>, W: Write {
+    if t < 2 || vs.len() < 2 || vs.len() < t.into() {
+        return Err(Ca2Error::InvalidArgument(String::from("Invalid strength or parameter value counts.")));
+    }
+
+    // The mapping between input columns and output columns (which must be sorted descending)
+    let mut columns : Vec<usize> = (0..vs.len()).collect();
+    columns.sort_by(|a, b| vs[*b].cmp(&vs[*a]));
+
+    // We need one hashmap for each column, storing associations from input value to abstract (u16) value
+    let mut value_maps : Vec<HashMap<String, Value>> = vec![HashMap::new(); vs.len()];
+    let mut encoder = BzEncoder::new(writer, Compression::fast());
+    let mut n = 0;
+    for result in rows {
+        let record = result?;
+        let record = record.as_ref();
+        if record.len() != vs.len() {
+            return Err(Ca2Error::Parse(format!("Row {} has {} values, expected {}", n + 1, record.len(), vs.len())));
+        }
+
+        // Iterate over the *output* columns (not the ones in the input!)
+        for column in columns.iter() {
+            let next_v = value_maps[*column].len() as Value;
+            let value_out = *value_maps[*column].entry(record[*column].to_string()).or_insert(next_v);
+            if value_out >= vs[*column] {
+                return Err(Ca2Error::Parse(format!(
+                    "Column {} has more than {} distinct values", column + 1, vs[*column]
+                )));
+            }
+            encoder.write_all(&value_out.to_be_bytes())?;
+        }
+        n += 1;
+    }
+    encoder.try_finish()?;
+
+    Ok(CASpec {
+        version: CA2_DEFAULT_VERSION,
+        kind: CAKind::Covering,
+        n,
+        t,
+        vs: columns.iter().map(|c| vs[*c]).collect()
+    })
+}
+
+// Compresses a sequence covering array given as CSV and returns its specification.
+// All columns share the same events, so unlike for ordinary CAs,
+// there is a single mapping from event names to abstract (u16) values.
+pub fn compress_sequence_csv<R: Read, W: Write>(reader: R, has_header: bool, t: u8, writer: W) -> Ca2Result<CASpec> {
+    compress_sequence_rows(csv_rows(reader, has_header), t, writer)
+}
+
+// Compresses a sequence covering array given as rows of events, see `compress_sequence_csv`
+pub fn compress_sequence_rows<I, R, W>(rows: I, t: u8, writer: W) -> Ca2Result<CASpec>
+where I: IntoIterator<Item = Ca2Result<R>>, R: AsRef<[String]>, W: Write {
+    if t < 2 {
+        return Err(Ca2Error::InvalidArgument(String::from("Invalid strength.")));
+    }
+
+    let mut events : HashMap<String, Value> = HashMap::new();
+    let mut num_events = None;
+    let mut encoder = BzEncoder::new(writer, Compression::fast());
+    let mut n = 0;
+    for result in rows {
+        let record = result?;
+        let record = record.as_ref();
+
+        // The first row determines the number of events
+        let k = *num_events.get_or_insert(record.len());
+        if record.len() != k || k < t.into() || k > Value::MAX as usize {
+            return Err(Ca2Error::Parse(format!("Row {} is not a permutation of {} events.", n + 1, k)));
+        }
+
+        // Every event must occur exactly once per row
+        let mut seen = vec![false; k];
+        for value in record.iter() {
+            let next_v = events.len() as Value;
+            let value_out = *events.entry(value.to_string()).or_insert(next_v);
+            if value_out as usize >= k {
+                return Err(Ca2Error::Parse(format!("Row {} contains more than {} distinct events.", n + 1, k)));
+            }
+            if std::mem::replace(&mut seen[value_out as usize], true) {
+                return Err(Ca2Error::Parse(format!("Row {} is not a permutation of {} events.", n + 1, k)));
+            }
+            encoder.write_all(&value_out.to_be_bytes())?;
+        }
+        n += 1;
+    }
+    encoder.try_finish()?;
+
+    Ok(CASpec::new_sequence(n, t, num_events.unwrap_or(0) as u16))
+}
+
+// The rows of a CSV file, read one by one
+fn csv_rows<R: Read>(reader: R, has_header: bool) -> impl Iterator<Item = Ca2Result<Vec<String>>> {
+    csv::ReaderBuilder::new()
+        .has_headers(has_header)
+        .from_reader(reader)
+        .into_records()
+        .map(|record| Ok(record?.iter().map(String::from).collect()))
+}
+
+// How to read a CA file. Strength and numbers of values that are not given are taken from the file,
+// if its format holds them; values that are given must agree with the file.
+#[derive(Debug,Clone,Default)]
+pub struct CompressOptions {
+    // Detected from the contents of the file if not given
+    pub format: Option<InputFormat>,
+    // Whether CSV and PICT files start with a header line
+    pub has_header: bool,
+    pub t: Option<u8>,
+    // Numbers of values in the order of the columns
+    pub vs: Option<Vec<u16>>,
+    // Numbers of values of the columns with these header names, overriding `vs`
+    pub named_vs: Vec<(String, u16)>,
+    // Count the distinct values of columns whose number of values is neither given nor stated by the file
+    pub infer_vs: bool,
+    // The file is a sequence covering array, so there are no numbers of values
+    pub sequence: bool
+}
+
+// The result of compressing a CA file
+#[derive(Debug,Clone)]
+pub struct CompressReport {
+    pub ca_spec: CASpec,
+    // Numbers of values in the order of the columns of the file
+    pub vs: Vec<u16>,
+    pub analysis: CAAnalysis
+}
+
+// The rows of a CA file together with the metadata the file states
+struct CAFile {
+    rows: Box<dyn Iterator<Item = Ca2Result<Vec<String>>>>,
+    names: Option<Vec<String>>,
+    t: Option<u8>,
+    vs: Option<Vec<u16>>
+}
+
+fn open_ca_file(ca_file: &Path, options: &CompressOptions) -> Ca2Result<CAFile> {
+    let format = match options.format {
+        Some(format) => format,
+        None => {
+            let mut beginning = Vec::new();
+            File::open(ca_file)?.take(DETECT_FORMAT_BYTES).read_to_end(&mut beginning)?;
+            detect_format(&String::from_utf8_lossy(&beginning), beginning.len() < DETECT_FORMAT_BYTES as usize)
+        }
+    };
+    match format {
+        // CSV files can be large, so they are not read into memory at once; the analysis only keeps a hash of each row
+        InputFormat::Csv => {
+            let mut reader = csv::ReaderBuilder::new()
+                .has_headers(options.has_header)
+                .from_reader(BufReader::new(File::open(ca_file)?));
+            let names = match options.has_header {
+                true => Some(reader.headers()?.iter().map(String::from).collect()),
+                false => None
+            };
+            let rows = reader.into_records().map(|record| Ok(record?.iter().map(String::from).collect()));
+            Ok(CAFile { rows: Box::new(rows), names, t: None, vs: None })
+        },
+        _ => {
+            let imported = import_ca(&std::fs::read_to_string(ca_file)?, format, options.has_header)?;
+            Ok(CAFile { rows: Box::new(imported.rows.into_iter().map(Ok)), names: imported.names, t: imported.t, vs: imported.vs })
+        }
+    }
+}
+
+// A CA file that was analyzed before compressing it
+#[derive(Debug,Clone)]
+pub struct AnalyzedFile {
+    pub t: u8,
+    // Numbers of values in the order of the columns of the file (empty for sequence covering arrays)
+    pub vs: Vec<u16>,
+    pub analysis: CAAnalysis
+}
+
+// Analyzes a CA file without writing anything: determines the strength and the numbers of values
+// (inferring them if requested), and checks declared ones against the rows, see `analyze_rows`
+pub fn analyze_file(ca_file: &Path, options: &CompressOptions) -> Ca2Result<AnalyzedFile> {
+    let file = open_ca_file(ca_file, options)?;
+    let t = metadata_value(options.t, file.t, "strength")?
+        .ok_or_else(|| Ca2Error::InvalidArgument(String::from("No strength given, and the file does not state any")))?;
+    let analysis = analyze_rows(file.rows, file.names)?;
+    let vs = match options.sequence {
+        true => Vec::new(),
+        false => resolve_vs(options, file.vs, &analysis)?
+    };
+    Ok(AnalyzedFile { t, vs, analysis })
+}
+
+// Compresses a CA file into a .cca and a .ccmeta file. The file is analyzed first (see `analyze_file`),
+// so that numbers of values can be inferred and declared ones are checked before anything is written.
+pub fn compress_file(ca_file: &Path, options: &CompressOptions, cca: &Path, ccmeta: &Path) -> Ca2Result<CompressReport> {
+    compress_analyzed(ca_file, options, analyze_file(ca_file, options)?, cca, ccmeta)
+}
+
+// Compresses a CA file that was analyzed with the same options, e.g. after reporting the analysis.
+// Nothing is left behind if compression fails.
+pub fn compress_analyzed(ca_file: &Path, options: &CompressOptions, analyzed: AnalyzedFile
+                         , cca: &Path, ccmeta: &Path) -> Ca2Result<CompressReport> {
+    let AnalyzedFile { t, vs, analysis } = analyzed;
+    // The analysis consumed the rows, so they are read again
+    let rows = open_ca_file(ca_file, options)?.rows;
+    let writer = BufWriter::new(File::create(cca)?);
+    let result = match options.sequence {
+        true => compress_sequence_rows(rows, t, writer),
+        false => compress_rows(rows, t, &vs, writer)
+    }.and_then(|ca_spec| {
+        write_ccmeta(BufWriter::new(File::create(ccmeta)?), &ca_spec)?;
+        Ok(ca_spec)
+    });
+    match result {
+        Ok(ca_spec) => Ok(CompressReport { vs: if options.sequence { ca_spec.vs.clone() } else { vs }, ca_spec, analysis }),
+        Err(e) => {
+            let _ = std::fs::remove_file(cca);
+            let _ = std::fs::remove_file(ccmeta);
+            Err(e)
+        }
+    }
+}
+
+// Determines the number of values of every column from the options, the file and the analysis,
+// and checks that no column has more distinct values than that
+fn resolve_vs(options: &CompressOptions, stated: Option<Vec<u16>>, analysis: &CAAnalysis) -> Ca2Result<Vec<u16>> {
+    let k = analysis.distinct.len();
+    let mut vs : Vec<Option<u16>> = match metadata_value(options.vs.clone(), stated, "numbers of values")? {
+        Some(vs) if vs.len() != k => return Err(Ca2Error::InvalidArgument(format!(
+            "Got {} numbers of values, but the file has {} columns", vs.len(), k
+        ))),
+        Some(vs) => vs.into_iter().map(Some).collect(),
+        None => vec![None; k]
+    };
+    for (name, v) in options.named_vs.iter() {
+        vs[analysis.find_column(name)?] = Some(*v);
+    }
+    let vs = vs.iter().enumerate()
+        .map(|(c, v)| match (v, options.infer_vs) {
+            (Some(v), _) => Ok(*v),
+            // Too many values are caught by the validation
+            (None, true) => Ok(u16::try_from(analysis.distinct[c]).unwrap_or(u16::MAX)),
+            (None, false) => Err(Ca2Error::InvalidArgument(format!(
+                "No number of values given for {}, and the file does not state it", analysis.column_name(c)
+            )))
+        })
+        .collect::<Ca2Result<Vec<u16>>>()?;
+    analysis.validate_vs(&vs)?;
+    Ok(vs)
+}
+
+// Combines a value given by the caller with the one stated in the file
+fn metadata_value<T: PartialEq + std::fmt::Debug>(given: Option<T>, stated: Option<T>, name: &str) -> Ca2Result<Option<T>> {
+    match (given, stated) {
+        (Some(given), Some(stated)) if given != stated => Err(Ca2Error::InvalidArgument(format!(
+            "Mismatch in {}: given {:?}, but the file states {:?}", name, given, stated
+        ))),
+        (given, stated) => Ok(given.or(stated))
+    }
+}
+
+// The .cca and .ccmeta files that belong to a CA file (or to a given .cca file)
+pub fn compressed_paths(ca_file: &Path) -> (PathBuf, PathBuf) {
+    (ca_file.with_extension("cca"), ca_file.with_extension("ccmeta"))
+}
+
+// Writes the contents of a .ccmeta file
+pub fn write_ccmeta<W: Write>(mut writer: W, ca_spec: &CASpec) -> Ca2Result<()> {
+    writer.write_all(MAGIC_BYTES_CCA.as_bytes())?;
+    writer.write_all(&ca_spec.serialize())?;
+    Ok(writer.flush()?)
+}
+
+// Parses a ccmeta file into a CASpec
+pub fn read_ccmeta(input_file: &Path) -> Ca2Result<CASpec> {
+    let mut f = File::open(input_file)?;
+
+    // Ingest the entire file
+    let mut buf = Vec::new();
+    f.read_to_end(&mut buf)?;
+
+    let buf_noprefix = buf.strip_prefix(MAGIC_BYTES_CCA.as_bytes()).ok_or_else(|| Ca2Error::Format(
+        format!("Not a valid ccmeta file: {}", input_file.display())
+    ))?;
+
+    let (ca_spec, _) = CASpec::unserialize(buf_noprefix).map_err(|e| Ca2Error::Format(
+        format!("Invalid CA specification in ccmeta file {}: {}", input_file.display(), e)
+    ))?;
+
+    Ok(ca_spec) // We disregard the number of read bytes
+}
@@ -0,0 +1,532 @@
+use std::collections::{HashMap, HashSet};
+use std::io::{Read, Write};
+use bzip2::write::BzEncoder;
+use bzip2::Compression;
+use crate::{CASpec, CAKind, CA2_DEFAULT_VERSION, CompressedCA, Value, Ca2Error, Ca2Result};
+
+// Number of rows that are decoded at once when reading a compressed CA
+const DECODE_BATCH_ROWS : usize = 4096;
+
+// An N × k covering array of strength t held in memory.
+// Values are stored row by row, and each value of column i is smaller than vs[i].
+// Unlike stored CAs, the columns do not have to be sorted by their number of values.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CoveringArray {
+    t: u8,
+    vs: Vec<u16>,
+    n: usize,
+    data: Vec<Value>
+}
+
+impl CoveringArray {
+    // Creates an array without rows
+    pub fn new(t: u8, vs: Vec<u16>) -> Self {
+        CoveringArray { t, vs, n: 0, data: Vec::new() }
+    }
+
+    // Creates an array from a list of rows
+    pub fn from_rows(t: u8, vs: Vec<u16>, rows: &[Vec<Value>]) -> Ca2Result<Self> {
+        let mut ca = CoveringArray::new(t, vs);
+        for row in rows.iter() {
+            ca.push_row(row)?;
+        }
+        Ok(ca)
+    }
+
+    pub fn n(&self) -> usize {
+        self.n
+    }
+
+    pub fn k(&self) -> usize {
+        self.vs.len()
+    }
+
+    pub fn t(&self) -> u8 {
+        self.t
+    }
+
+    pub fn vs(&self) -> &[u16] {
+        &self.vs
+    }
+
+    pub fn row(&self, i: usize) -> &[Value] {
+        &self.data[i * self.k()..(i + 1) * self.k()]
+    }
+
+    pub fn rows(&self) -> impl Iterator<Item = &[Value]> {
+        (0..self.n).map(|i| self.row(i))
+    }
+
+    // All values, row by row
+    pub fn as_slice(&self) -> &[Value] {
+        &self.data
+    }
+
+    pub fn push_row(&mut self, row: &[Value]) -> Ca2Result<()> {
+        if row.len() != self.k() {
+            return Err(Ca2Error::InvalidArgument(format!(
+                "Row has {} values, but the array has {} columns", row.len(), self.k()
+            )));
+        }
+        if let Some(i) = (0..row.len()).find(|i| row[*i] >= self.vs[*i]) {
+            return Err(Ca2Error::InvalidArgument(format!(
+                "Value {} in column {} is out of range, the column has {} values", row[i], i, self.vs[i]
+            )));
+        }
+        self.data.extend_from_slice(row);
+        self.n += 1;
+        Ok(())
+    }
+
+    // Removes the rows with the given indices
+    pub fn delete_rows(&mut self, rows: &[usize]) -> Ca2Result<()> {
+        if let Some(i) = rows.iter().find(|i| **i >= self.n) {
+            return Err(Ca2Error::InvalidArgument(format!("The array has no row {}", i)));
+        }
+        let mut delete = vec![false; self.n];
+        for i in rows.iter() {
+            delete[*i] = true;
+        }
+        let k = self.k();
+        let mut i = 0;
+        self.data.retain(|_| {
+            i += 1;
+            !delete[(i - 1) / k]
+        });
+        self.n -= delete.iter().filter(|d| **d).count();
+        Ok(())
+    }
+
+    // Creates an array from the given columns of this one.
+    // The strength is limited by the number of remaining columns.
+    pub fn project(&self, columns: &[usize]) -> Ca2Result<Self> {
+        self.check_columns(columns)?;
+        let mut data = Vec::with_capacity(self.n * columns.len());
+        for row in self.rows() {
+            data.extend(columns.iter().map(|c| row[*c]));
+        }
+        Ok(CoveringArray {
+            t: self.t.min(u8::try_from(columns.len()).unwrap_or(u8::MAX)),
+            vs: columns.iter().map(|c| self.vs[*c]).collect(),
+            n: self.n,
+            data
+        })
+    }
+
+    // Reorders the columns, so that column i afterwards is column permutation[i] before
+    pub fn permute_columns(&mut self, permutation: &[usize]) -> Ca2Result<()> {
+        if permutation.len() != self.k() {
+            return Err(Ca2Error::InvalidArgument(format!(
+                "Permutation of {} columns applied to an array with {} columns", permutation.len(), self.k()
+            )));
+        }
+        *self = self.project(permutation)?;
+        Ok(())
+    }
+
+    // Replaces every value v in `column` by mapping[v].
+    // The mapping may merge values, but has to hit every value up to its maximum,
+    // which then determines the new number of values of the column.
+    pub fn relabel(&mut self, column: usize, mapping: &[Value]) -> Ca2Result<()> {
+        self.check_columns(&[column])?;
+        if mapping.len() != self.vs[column] as usize {
+            return Err(Ca2Error::InvalidArgument(format!(
+                "Mapping of {} values applied to column {} with {} values", mapping.len(), column, self.vs[column]
+            )));
+        }
+        let v = mapping.iter().max().map_or(0, |max| *max as usize + 1);
+        let mut hit = vec![false; v];
+        for m in mapping.iter() {
+            hit[*m as usize] = true;
+        }
+        if hit.contains(&false) {
+            return Err(Ca2Error::InvalidArgument(format!(
+                "Mapping for column {} skips values, so the result would not be a covering array", column
+            )));
+        }
+
+        let k = self.k();
+        for value in self.data.iter_mut().skip(column).step_by(k) {
+            *value = mapping[*value as usize];
+        }
+        self.vs[column] = v as u16;
+        Ok(())
+    }
+
+    // Creates an array that contains the rows of this one, followed by the rows of `other`.
+    // Both arrays need to have the same columns. The strength is the larger of both.
+    pub fn concat(&self, other: &CoveringArray) -> Ca2Result<Self> {
+        if self.vs != other.vs {
+            return Err(Ca2Error::InvalidArgument(String::from(
+                "Only arrays with the same numbers of values can be concatenated"
+            )));
+        }
+        let mut data = Vec::with_capacity(self.data.len() + other.data.len());
+        data.extend_from_slice(&self.data);
+        data.extend_from_slice(&other.data);
+        Ok(CoveringArray { t: self.t.max(other.t), vs: self.vs.clone(), n: self.n + other.n, data })
+    }
+
+    // Reads all rows of a compressed CA with the given specification
+    pub fn from_compressed<R: Read>(ca_spec: &CASpec, mut compressed_ca: CompressedCA<R>) -> Ca2Result<Self> {
+        if ca_spec.kind != CAKind::Covering || compressed_ca.row_len() != ca_spec.vs.len() {
+            return Err(Ca2Error::InvalidArgument(format!("{} is not a covering array of this data", ca_spec)));
+        }
+        let mut ca = CoveringArray::new(ca_spec.t, ca_spec.vs.clone());
+        let k = ca.k();
+        if k == 0 {
+            ca.n = usize::try_from(compressed_ca.rows_remaining())
+                .map_err(|_| Ca2Error::Format(String::from("Too many rows")))?;
+            return Ok(ca);
+        }
+
+        // The data grows with every batch, so a wrong N in the metadata cannot make us allocate too much
+        loop {
+            let start = ca.data.len();
+            ca.data.resize(start + DECODE_BATCH_ROWS * k, 0);
+            let rows = compressed_ca.read_rows(&mut ca.data[start..])?;
+            ca.data.truncate(start + rows * k);
+            if rows == 0 {
+                break;
+            }
+            ca.n += rows;
+        }
+
+        if let Some(i) = (0..ca.data.len()).find(|i| ca.data[*i] >= ca.vs[i % k]) {
+            return Err(Ca2Error::Format(format!(
+                "Value {} in row {}, column {} is out of range", ca.data[i], i / k, i % k
+            )));
+        }
+        Ok(ca)
+    }
+
+    // Compresses the array like cca does and returns the specification of the written data.
+    // Stored CAs have their columns sorted descending by their number of values,
+    // so the columns are reordered accordingly (keeping the order of columns with the same number of values).
+    pub fn write_compressed<W: Write>(&self, writer: W) -> Ca2Result<CASpec> {
+        let mut columns : Vec<usize> = (0..self.k()).collect();
+        columns.sort_by(|a, b| self.vs[*b].cmp(&self.vs[*a]));
+
+        let mut encoder = BzEncoder::new(writer, Compression::fast());
+        for row in self.rows() {
+            for c in columns.iter() {
+                encoder.write_all(&row[*c].to_be_bytes())?;
+            }
+        }
+        encoder.try_finish()?;
+
+        Ok(CASpec {
+            version: CA2_DEFAULT_VERSION,
+            kind: CAKind::Covering,
+            n: self.n as u64,
+            t: self.t,
+            vs: columns.iter().map(|c| self.vs[*c]).collect()
+        })
+    }
+
+    // Reads an array from a CSV file. Values are arbitrary strings; the values of each column
+    // are numbered in the order they first appear, just like cca does.
+    // Returns the array and, for each column, the strings of its values.
+    pub fn from_csv<R: Read>(reader: R, t: u8, vs: Vec<u16>, has_header: bool) -> Ca2Result<(Self, Vec<Vec<String>>)> {
+        let mut reader = csv::ReaderBuilder::new()
+            .has_headers(has_header)
+            .from_reader(reader);
+        let mut ca = CoveringArray::new(t, vs);
+        let mut labels : Vec<Vec<String>> = vec![Vec::new(); ca.k()];
+        let mut value_maps : Vec<HashMap<String, Value>> = vec![HashMap::new(); ca.k()];
+
+        for record in reader.records() {
+            let record = record?;
+            if record.len() != ca.k() {
+                return Err(Ca2Error::Parse(format!(
+                    "Row {} has {} values, expected {}", ca.n + 1, record.len(), ca.k()
+                )));
+            }
+            for (i, value) in record.iter().enumerate() {
+                let next = labels[i].len() as Value;
+                let symbol = *value_maps[i].entry(value.to_string()).or_insert(next);
+                if symbol == next {
+                    if next >= ca.vs[i] {
+                        return Err(Ca2Error::Parse(format!(
+                            "Column {} has more than {} distinct values", i + 1, ca.vs[i]
+                        )));
+                    }
+                    labels[i].push(value.to_string());
+                }
+                ca.data.push(symbol);
+            }
+            ca.n += 1;
+        }
+        Ok((ca, labels))
+    }
+
+    // Writes the array as CSV with an optional header.
+    // Values are written as numbers, or as labels[column][value] if labels are given.
+    pub fn write_csv<W: Write>(&self, writer: W, header: Option<&[String]>, labels: Option<&[Vec<String>]>) -> Ca2Result<()> {
+        if header.is_some_and(|header| header.len() != self.k()) {
+            return Err(Ca2Error::InvalidArgument(format!("The header must have {} columns", self.k())));
+        }
+        if labels.is_some_and(|labels| labels.len() != self.k()
+                              || (0..self.k()).any(|i| labels[i].len() < self.vs[i] as usize)) {
+            return Err(Ca2Error::InvalidArgument(String::from("There must be a label for every value")));
+        }
+
+        let mut writer = csv::Writer::from_writer(writer);
+        if let Some(header) = header {
+            writer.write_record(header)?;
+        }
+        let mut record = Vec::with_capacity(self.k());
+        for row in self.rows() {
+            record.clear();
+            match labels {
+                Some(labels) => record.extend(row.iter().enumerate().map(|(i, v)| labels[i][*v as usize].clone())),
+                None => record.extend(row.iter().map(|v| v.to_string()))
+            }
+            writer.write_record(&record)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+
+    // Counts the t-way interactions (combinations of t columns with one value each) that no row covers.
+    // Zero means that the array really is a covering array of strength t.
+    pub fn count_uncovered(&self) -> Ca2Result<u64> {
+        let t = self.t as usize;
+        if t > self.k() {
+            return Err(Ca2Error::InvalidArgument(format!("Strength {} exceeds the number of columns {}", t, self.k())));
+        }
+        let mut uncovered : u64 = 0;
+        let mut columns : Vec<usize> = (0..t).collect();
+        loop {
+            let interactions = columns.iter()
+                .try_fold(1u64, |product, c| product.checked_mul(self.vs[*c] as u64))
+                .ok_or_else(|| Ca2Error::InvalidArgument(String::from("Too many interactions")))?;
+
+            // Each interaction is numbered like a mixed radix number. Small sets of interactions are
+            // tracked in a bitmap, larger ones (which the rows cannot cover anyway) in a hash set.
+            let index = |row: &[Value]| columns.iter().fold(0u64, |i, c| i * self.vs[*c] as u64 + row[*c] as u64);
+            let covered = if interactions <= (self.n as u64).max(1 << 16) {
+                let mut seen = vec![false; interactions as usize];
+                for row in self.rows() {
+                    seen[index(row) as usize] = true;
+                }
+                seen.into_iter().filter(|s| *s).count() as u64
+            } else {
+                self.rows().map(index).collect::<HashSet<u64>>().len() as u64
+            };
+            uncovered += interactions - covered;
+
+            // Advance to the next combination of columns
+            let Some(i) = (0..t).rev().find(|i| columns[*i] < self.k() - t + i) else {
+                return Ok(uncovered);
+            };
+            columns[i] += 1;
+            for j in i + 1..t {
+                columns[j] = columns[j - 1] + 1;
+            }
+        }
+    }
+
+    fn check_columns(&self, columns: &[usize]) -> Ca2Result<()> {
+        if let Some(c) = columns.iter().find(|c| **c >= self.k()) {
+            return Err(Ca2Error::InvalidArgument(format!("The array has no column {}", c)));
+        }
+        let mut seen = vec![false; self.k()];
+        for c in columns.iter() {
+            if seen[*c] {
+                return Err(Ca2Error::InvalidArgument(format!("Column {} is used more than once", c)));
+            }
+            seen[*c] = true;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use crate::open_ca;
+
+    // CA(4; 2, 2^3), every pair of columns sees each of the 4 pairs of values exactly once
+    fn complete_ca() -> CoveringArray {
+        CoveringArray::from_rows(2, vec![2, 2, 2], &[vec![0, 0, 0], vec![0, 1, 1], vec![1, 0, 1], vec![1, 1, 0]]).unwrap()
+    }
+
+    // CA(9; 2, 3^4), built from the two orthogonal latin squares of order 3
+    fn mixed_ca() -> CoveringArray {
+        let rows : Vec<Vec<Value>> = (0..9)
+            .map(|i| (i / 3, i % 3))
+            .map(|(a, b)| vec![a, b, (a + b) % 3, (a + 2 * b) % 3])
+            .collect();
+        CoveringArray::from_rows(2, vec![3, 3, 3, 3], &rows).unwrap()
+    }
+
+    #[test]
+    fn complete_cas_have_no_uncovered_interactions() {
+        assert_eq!(complete_ca().count_uncovered().unwrap(), 0);
+        assert_eq!(mixed_ca().count_uncovered().unwrap(), 0);
+        // Strength 3 needs all 8 rows of 2^3
+        let mut ca = complete_ca();
+        ca.t = 3;
+        assert_eq!(ca.count_uncovered().unwrap(), 4);
+    }
+
+    #[test]
+    fn deleted_rows_lose_their_interactions() {
+        // Row 000 is the only one covering the value pair 00 in each of the 3 pairs of columns
+        let mut ca = complete_ca();
+        ca.delete_rows(&[0]).unwrap();
+        assert_eq!(ca.n(), 3);
+        assert_eq!(ca.row(0), &[0, 1, 1]);
+        assert_eq!(ca.count_uncovered().unwrap(), 3);
+
+        // Each row of the mixed CA covers 6 pairs that no other row covers
+        let mut ca = mixed_ca();
+        ca.delete_rows(&[2, 5]).unwrap();
+        assert_eq!(ca.n(), 7);
+        assert_eq!(ca.count_uncovered().unwrap(), 12);
+
+        assert!(ca.delete_rows(&[7]).is_err());
+    }
+
+    #[test]
+    fn relabel_and_permute_round_trip() {
+        let original = mixed_ca();
+        let mut ca = original.clone();
+        ca.relabel(1, &[2, 0, 1]).unwrap();
+        assert_ne!(ca, original);
+        assert_eq!(ca.count_uncovered().unwrap(), 0);
+        ca.relabel(1, &[1, 2, 0]).unwrap();
+        assert_eq!(ca, original);
+
+        ca.permute_columns(&[2, 0, 3, 1]).unwrap();
+        assert_eq!(ca.row(1), &[1, 0, 2, 1]);
+        assert_eq!(ca.count_uncovered().unwrap(), 0);
+        ca.permute_columns(&[1, 3, 0, 2]).unwrap();
+        assert_eq!(ca, original);
+    }
+
+    #[test]
+    fn relabel_merges_values() {
+        let mut ca = mixed_ca();
+        ca.relabel(0, &[0, 1, 1]).unwrap();
+        assert_eq!(ca.vs(), &[2, 3, 3, 3]);
+        assert_eq!(ca.count_uncovered().unwrap(), 0);
+        // Skipping value 1 would leave interactions that no row can cover
+        assert!(ca.relabel(1, &[0, 2, 2]).is_err());
+        assert!(ca.relabel(1, &[0, 1]).is_err());
+    }
+
+    #[test]
+    fn project_keeps_coverage_of_the_remaining_columns() {
+        let ca = mixed_ca().project(&[3, 1]).unwrap();
+        assert_eq!((ca.n(), ca.k(), ca.t()), (9, 2, 2));
+        assert_eq!(ca.row(5), &[2, 2]);
+        assert_eq!(ca.count_uncovered().unwrap(), 0);
+
+        let ca = complete_ca().project(&[2]).unwrap();
+        assert_eq!(ca.t(), 1);
+        assert_eq!(ca.count_uncovered().unwrap(), 0);
+
+        assert!(complete_ca().project(&[0, 0]).is_err());
+        assert!(complete_ca().project(&[3]).is_err());
+    }
+
+    #[test]
+    fn concat_restores_deleted_rows() {
+        let mut first = complete_ca();
+        first.delete_rows(&[0]).unwrap();
+        let row = CoveringArray::from_rows(1, vec![2, 2, 2], &[vec![0, 0, 0]]).unwrap();
+        let ca = first.concat(&row).unwrap();
+        assert_eq!((ca.n(), ca.t()), (4, 2));
+        assert_eq!(ca.row(3), &[0, 0, 0]);
+        assert_eq!(ca.count_uncovered().unwrap(), 0);
+
+        assert!(complete_ca().concat(&mixed_ca()).is_err());
+    }
+
+    // CA(6; 2, 2 3 2) with the column of 3 values in the middle, so that storing it reorders the columns
+    fn unsorted_ca() -> CoveringArray {
+        let rows : Vec<Vec<Value>> = (0..6).map(|i| vec![i / 3, i % 3, (i / 3 + i % 3) % 2]).collect();
+        CoveringArray::from_rows(2, vec![2, 3, 2], &rows).unwrap()
+    }
+
+    fn compressed(ca: &CoveringArray) -> (Vec<u8>, CASpec) {
+        let mut data = Vec::new();
+        let ca_spec = ca.write_compressed(&mut data).unwrap();
+        (data, ca_spec)
+    }
+
+    #[test]
+    fn compressed_round_trip_sorts_columns() {
+        let ca = unsorted_ca();
+        let (data, ca_spec) = compressed(&ca);
+        assert_eq!((ca_spec.kind, ca_spec.n, ca_spec.t), (CAKind::Covering, 6, 2));
+        assert_eq!(ca_spec.vs, [3, 2, 2]);
+
+        let decoded = CoveringArray::from_compressed(&ca_spec, open_ca(Cursor::new(data), 0, &ca_spec).unwrap()).unwrap();
+        assert_eq!(decoded.vs(), [3, 2, 2]);
+        // Columns with the same number of values keep their order
+        assert_eq!(decoded, ca.project(&[1, 0, 2]).unwrap());
+        assert_eq!(decoded.count_uncovered().unwrap(), 0);
+
+        let (data, ca_spec) = compressed(&mixed_ca());
+        let decoded = CoveringArray::from_compressed(&ca_spec, open_ca(Cursor::new(data), 0, &ca_spec).unwrap()).unwrap();
+        assert_eq!(decoded, mixed_ca());
+    }
+
+    #[test]
+    fn compressed_values_out_of_range() {
+        let (data, mut ca_spec) = compressed(&mixed_ca());
+        ca_spec.vs = vec![3, 3, 3, 2];
+        let compressed_ca = open_ca(Cursor::new(data.clone()), 0, &ca_spec).unwrap();
+        assert!(matches!(CoveringArray::from_compressed(&ca_spec, compressed_ca), Err(Ca2Error::Format(_))));
+
+        // Sequence covering arrays are no covering arrays of their data
+        ca_spec.vs = vec![3, 3, 3, 3];
+        ca_spec.kind = CAKind::Sequence;
+        let compressed_ca = open_ca(Cursor::new(data), 0, &ca_spec).unwrap();
+        assert!(CoveringArray::from_compressed(&ca_spec, compressed_ca).is_err());
+    }
+
+    #[test]
+    fn csv_round_trip_with_labels() {
+        let csv = "os,browser,js\nlinux,firefox,on\nwindows,firefox,off\nlinux,\"edge, new\",off\nwindows,\"edge, new\",on\n";
+        let (ca, labels) = CoveringArray::from_csv(csv.as_bytes(), 2, vec![2, 2, 2], true).unwrap();
+        assert_eq!((ca.n(), ca.k()), (4, 3));
+        assert_eq!(ca.row(2), [0, 1, 1]);
+        assert_eq!(labels, [vec!["linux", "windows"], vec!["firefox", "edge, new"], vec!["on", "off"]]);
+        assert_eq!(ca.count_uncovered().unwrap(), 0);
+
+        let header : Vec<String> = ["os", "browser", "js"].iter().map(|s| s.to_string()).collect();
+        let mut out = Vec::new();
+        ca.write_csv(&mut out, Some(&header), Some(&labels)).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), csv);
+    }
+
+    #[test]
+    fn csv_round_trip_without_header() {
+        let csv = "0,0,0\n0,1,1\n1,0,1\n1,1,0\n";
+        let (ca, labels) = CoveringArray::from_csv(csv.as_bytes(), 2, vec![2, 2, 2], false).unwrap();
+        assert_eq!(ca, complete_ca());
+        assert_eq!(labels[0], ["0", "1"]);
+
+        let mut out = Vec::new();
+        ca.write_csv(&mut out, None, None).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), csv);
+    }
+
+    #[test]
+    fn invalid_csv() {
+        // A third distinct value in a column with two values
+        assert!(matches!(CoveringArray::from_csv("a,b\nc,b\nd,b\n".as_bytes(), 1, vec![2, 2], false), Err(Ca2Error::Parse(_))));
+        assert!(CoveringArray::from_csv("a,b\nc\n".as_bytes(), 1, vec![2, 2], false).is_err());
+
+        let ca = complete_ca();
+        let short_header = vec![String::from("a")];
+        assert!(ca.write_csv(Vec::new(), Some(&short_header), None).is_err());
+        let missing_labels = vec![vec![String::from("x")]; 3];
+        assert!(ca.write_csv(Vec::new(), None, Some(&missing_labels)).is_err());
+    }
+}
@@ -0,0 +1,255 @@
+use std::path::PathBuf;
+use crate::{CASpec, CAKind};
+
+// Number of near misses that are kept for every reason
+pub const NEAREST_CANDIDATES : usize = 3;
+
+// A reason why a stored CA is not compatible with a request
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Mismatch {
+    Strength { stored: u8, requested: u8 },
+    // Too few parameters (or events of an SCA)
+    Columns { stored: usize, requested: usize },
+    // The first position of the sorted vs where the stored CA has too few values
+    Values { position: usize, stored: u16, requested: u16 }
+}
+
+impl Mismatch {
+    // How far the stored CA is off, in the unit of the reason
+    pub fn deficit(&self) -> u64 {
+        match *self {
+            Mismatch::Strength { stored, requested } => (requested - stored).into(),
+            Mismatch::Columns { stored, requested } => (requested - stored) as u64,
+            Mismatch::Values { stored, requested, .. } => (requested - stored).into()
+        }
+    }
+}
+
+// A stored CA of the requested kind that is not compatible with the request
+#[derive(Debug,Clone)]
+pub struct Candidate {
+    pub archive: PathBuf,
+    pub offset: u64,
+    pub ca_spec: CASpec,
+    pub mismatches: Vec<Mismatch>
+}
+
+// The smallest stored CA that would be compatible with a slightly relaxed request
+#[derive(Debug,Clone)]
+pub struct Relaxation {
+    pub requested: CASpec,
+    pub best: Option<(PathBuf, u64, CASpec)>
+}
+
+// Why none of the stored CAs is compatible with a request
+#[derive(Debug,Clone)]
+pub struct Explanation {
+    // Number of stored CAs that were examined, and how many of them are of a different kind
+    pub examined: usize,
+    pub other_kinds: usize,
+    // The nearest candidates for every reason, fewest mismatches first
+    pub too_low_strength: Vec<Candidate>,
+    pub too_few_columns: Vec<Candidate>,
+    pub too_few_values: Vec<Candidate>,
+    // Requests with the strength, the number of parameters or the values of one parameter lowered by one
+    pub relaxations: Vec<Relaxation>
+}
+
+// All reasons why `stored` is not compatible with `requested`, assuming both are of the same kind
+pub fn find_mismatches(requested: &CASpec, stored: &CASpec) -> Vec<Mismatch> {
+    let mut mismatches = Vec::new();
+    if stored.t < requested.t {
+        mismatches.push(Mismatch::Strength { stored: stored.t, requested: requested.t });
+    }
+    if stored.vs.len() < requested.vs.len() {
+        mismatches.push(Mismatch::Columns { stored: stored.vs.len(), requested: requested.vs.len() });
+    }
+    // The number of events of an SCA is already covered by its columns
+    if requested.kind != CAKind::Sequence {
+        let position = requested.vs.iter().zip(stored.vs.iter()).position(|(r, s)| s < r);
+        if let Some(position) = position {
+            mismatches.push(Mismatch::Values {
+                position,
+                stored: stored.vs[position],
+                requested: requested.vs[position]
+            });
+        }
+    }
+    mismatches
+}
+
+// Explains why none of the stored CAs (archive, offset, specification) is compatible with `requested`.
+// `requested` needs its vs sorted descending, like the request of a `RequestedCA`.
+pub fn explain_request(requested: &CASpec, stored: &[(PathBuf, u64, CASpec)]) -> Explanation {
+    let (mut examined, mut other_kinds) = (0, 0);
+    // Nearest first: fewest mismatches, then the smallest deficit for the reason, then the fewest rows
+    let mut near_misses : [Vec<(usize, u64, u64, Candidate)>; 3] = Default::default();
+    for (archive, offset, ca_spec) in stored.iter().filter(|(_, _, ca_spec)| ca_spec.kind != CAKind::Unused) {
+        examined += 1;
+        if ca_spec.kind != requested.kind {
+            other_kinds += 1;
+            continue;
+        }
+        let mismatches = find_mismatches(requested, ca_spec);
+        for mismatch in mismatches.iter() {
+            let reason = match mismatch {
+                Mismatch::Strength { .. } => 0,
+                Mismatch::Columns { .. } => 1,
+                Mismatch::Values { .. } => 2
+            };
+            let candidate = Candidate { archive: archive.clone(), offset: *offset, ca_spec: ca_spec.clone(), mismatches: mismatches.clone() };
+            near_misses[reason].push((mismatches.len(), mismatch.deficit(), ca_spec.n, candidate));
+        }
+    }
+    let [too_low_strength, too_few_columns, too_few_values] = near_misses.map(|mut candidates| {
+        candidates.sort_by_key(|(mismatches, deficit, n, _)| (*mismatches, *deficit, *n));
+        candidates.into_iter().take(NEAREST_CANDIDATES).map(|(_, _, _, candidate)| candidate).collect()
+    });
+
+    let relaxations = relax(requested).into_iter()
+        .map(|relaxed| {
+            let best = stored.iter()
+                .filter(|(_, _, ca_spec)| relaxed.is_compatible(ca_spec))
+                .min_by_key(|(_, _, ca_spec)| ca_spec.n)
+                .cloned();
+            Relaxation { requested: relaxed, best }
+        })
+        .collect();
+    Explanation { examined, other_kinds, too_low_strength, too_few_columns, too_few_values, relaxations }
+}
+
+// The requests that differ from `requested` by one strength, one parameter (or event),
+// or one value of a parameter with more than two values. Lowering the values of the last parameter
+// with v values keeps vs sorted.
+fn relax(requested: &CASpec) -> Vec<CASpec> {
+    let mut relaxed = Vec::new();
+    if requested.t > 1 {
+        relaxed.push(CASpec { t: requested.t - 1, ..requested.clone() });
+    }
+    let k = requested.vs.len();
+    if k > 1 && k > requested.t.into() {
+        match requested.kind {
+            CAKind::Sequence => relaxed.push(CASpec::new_sequence(requested.n, requested.t, (k - 1) as u16)),
+            _ => relaxed.push(CASpec { vs: requested.vs[..k - 1].to_vec(), ..requested.clone() })
+        }
+    }
+    if requested.kind != CAKind::Sequence {
+        for (i, v) in requested.vs.iter().enumerate() {
+            let last_of_group = requested.vs.get(i + 1) != Some(v);
+            if last_of_group && *v > 2 {
+                let mut vs = requested.vs.clone();
+                vs[i] -= 1;
+                relaxed.push(CASpec { vs, ..requested.clone() });
+            }
+        }
+    }
+    relaxed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CA2Version;
+
+    fn ca(n: u64, t: u8, vs: &[u16]) -> CASpec {
+        CASpec { version: CA2Version::Bzip2, kind: CAKind::Covering, n, t, vs: vs.to_vec() }
+    }
+
+    fn stored(specs: &[CASpec]) -> Vec<(PathBuf, u64, CASpec)> {
+        specs.iter().enumerate().map(|(i, spec)| (PathBuf::from("cas.ca2"), 100 * i as u64, spec.clone())).collect()
+    }
+
+    // The requests of the relaxations as (kind, t, vs), since CASpec is not comparable
+    fn relaxed(requested: &CASpec) -> Vec<(CAKind, u8, Vec<u16>)> {
+        relax(requested).into_iter().map(|spec| (spec.kind, spec.t, spec.vs)).collect()
+    }
+
+    #[test]
+    fn mismatches_name_every_reason() {
+        let requested = ca(0, 3, &[4, 3, 3, 2]);
+        assert_eq!(find_mismatches(&requested, &ca(50, 3, &[4, 4, 3, 2, 2])), []);
+        assert_eq!(find_mismatches(&requested, &ca(50, 2, &[4, 3, 3, 2])), [Mismatch::Strength { stored: 2, requested: 3 }]);
+        assert_eq!(find_mismatches(&requested, &ca(50, 3, &[4, 3, 3])), [Mismatch::Columns { stored: 3, requested: 4 }]);
+        // Only the first position with too few values is reported
+        assert_eq!(
+            find_mismatches(&requested, &ca(50, 3, &[4, 2, 2, 2])),
+            [Mismatch::Values { position: 1, stored: 2, requested: 3 }]
+        );
+        let mismatches = find_mismatches(&requested, &ca(50, 1, &[3, 3]));
+        assert_eq!(mismatches, [
+            Mismatch::Strength { stored: 1, requested: 3 },
+            Mismatch::Columns { stored: 2, requested: 4 },
+            Mismatch::Values { position: 0, stored: 3, requested: 4 }
+        ]);
+        assert_eq!(mismatches.iter().map(Mismatch::deficit).collect::<Vec<_>>(), [2, 2, 1]);
+
+        // The events of an SCA are only reported as columns
+        let requested = CASpec::new_sequence(0, 3, 6);
+        assert_eq!(find_mismatches(&requested, &CASpec::new_sequence(20, 3, 5)), [Mismatch::Columns { stored: 5, requested: 6 }]);
+        assert_eq!(find_mismatches(&requested, &CASpec::new_sequence(20, 3, 7)), []);
+    }
+
+    #[test]
+    fn explanations_keep_the_nearest_candidates() {
+        let requested = ca(0, 3, &[3, 3, 3, 3]);
+        let stored = stored(&[
+            ca(10, 2, &[3, 3, 3, 3]),
+            ca(20, 1, &[3, 3, 3, 3]),
+            ca(30, 2, &[3, 3, 3]),
+            ca(40, 3, &[3, 3, 3]),
+            ca(50, 3, &[3, 3, 3, 2, 2]),
+            ca(35, 3, &[2, 2, 2, 2]),
+            ca(60, 3, &[3, 3, 2, 2]),
+            ca(5, 2, &[3, 3, 3, 3]),
+            CASpec::new_sequence(10, 3, 4),
+            CASpec::new_unused()
+        ]);
+        let explanation = explain_request(&requested, &stored);
+        assert_eq!(explanation.examined, 9);
+        assert_eq!(explanation.other_kinds, 1);
+
+        // Fewest mismatches first, then the smallest deficit, then the fewest rows
+        let offsets = |candidates: &[Candidate]| candidates.iter().map(|c| c.offset).collect::<Vec<_>>();
+        assert_eq!(offsets(&explanation.too_low_strength), [700, 0, 100]);
+        assert_eq!(offsets(&explanation.too_few_columns), [300, 200]);
+        assert_eq!(offsets(&explanation.too_few_values), [500, 400, 600]);
+        assert_eq!(explanation.too_few_columns[1].mismatches.len(), 2);
+
+        // Lowering the strength finds the smallest CA of strength 2
+        let relaxation = &explanation.relaxations[0];
+        assert_eq!(relaxation.requested.t, 2);
+        assert_eq!(relaxation.best.as_ref().map(|(_, offset, _)| *offset), Some(700));
+        // Dropping a parameter
+        assert_eq!(explanation.relaxations[1].requested.vs, [3, 3, 3]);
+        assert_eq!(explanation.relaxations[1].best.as_ref().map(|(_, offset, _)| *offset), Some(300));
+        assert_eq!(explanation.relaxations[2].requested.vs, [3, 3, 3, 2]);
+        assert_eq!(explanation.relaxations[2].best.as_ref().map(|(_, offset, _)| *offset), Some(400));
+        assert_eq!(explanation.relaxations.len(), 3);
+    }
+
+    #[test]
+    fn relaxations_lower_one_thing_at_a_time() {
+        // The values of the last parameter of each group are lowered, keeping vs sorted
+        assert_eq!(relaxed(&ca(0, 3, &[4, 4, 3, 2])), [
+            (CAKind::Covering, 2, vec![4, 4, 3, 2]),
+            (CAKind::Covering, 3, vec![4, 4, 3]),
+            (CAKind::Covering, 3, vec![4, 3, 3, 2]),
+            (CAKind::Covering, 3, vec![4, 4, 2, 2])
+        ]);
+        // Strength 1 can not be lowered, neither can parameters be dropped below the strength,
+        // nor binary parameters lose values
+        assert_eq!(relaxed(&ca(0, 1, &[2])), []);
+        assert_eq!(relaxed(&ca(0, 2, &[2, 2])), [(CAKind::Covering, 1, vec![2, 2])]);
+
+        // SCAs lose an event, which lowers the number of events stored in every entry of vs
+        assert_eq!(relaxed(&CASpec::new_sequence(0, 3, 5)), [
+            (CAKind::Sequence, 2, vec![5; 5]),
+            (CAKind::Sequence, 3, vec![4; 4])
+        ]);
+        let relaxed_sca = &relax(&CASpec::new_sequence(0, 3, 5))[1];
+        assert_eq!(relaxed_sca.events(), Some(4));
+        assert!(relaxed_sca.is_compatible(&CASpec::new_sequence(10, 3, 4)));
+        assert!(!relaxed_sca.is_compatible(&CASpec::new_sequence(10, 3, 3)));
+        assert_eq!(relaxed(&CASpec::new_sequence(0, 3, 3)), [(CAKind::Sequence, 2, vec![3; 3])]);
+    }
+}
@@ -0,0 +1,148 @@
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crate::archive::stored_length;
+use crate::{CASpec, ArchiveIndex, RequestedCA, Value, CAWriter, OutputFormat, open_ca, generate_reorder_map, read_archive_index, write_ccmeta
+            , ca_name, Ca2Error, Ca2Result};
+
+// Number of rows that are decoded at once
+const DECODE_BATCH_ROWS : usize = 4096;
+
+// Decodes the CA stored at `offset` of an archive, translates its rows to the parameters and values
+// of the request and writes them as CSV, optionally with the parameter names as header.
+// Returns the number of written rows.
+pub fn extract_csv<R: 'static + Read + Seek, W: Write>(archive: R, offset: u64, ca_spec: &CASpec
+                   , requested_ca: &RequestedCA, writer: W, header: bool) -> Ca2Result<u64> {
+    extract_ca(archive, offset, ca_spec, requested_ca, OutputFormat::Csv.writer(writer, header).as_mut())
+}
+
+// Like `extract_csv`, but writes the rows with any `CAWriter`
+pub fn extract_ca<R: 'static + Read + Seek>(archive: R, offset: u64, ca_spec: &CASpec
+                  , requested_ca: &RequestedCA, writer: &mut dyn CAWriter) -> Ca2Result<u64> {
+    // Create the mapping between stored and requested parameters
+    let reorder_map = generate_reorder_map(&requested_ca.parameter_sizes, ca_spec)?;
+
+    writer.begin(&requested_ca.parameter_names, &requested_ca.parameter_values)?;
+    let mut cells = Vec::with_capacity(requested_ca.parameter_names.len());
+    let rows = write_rows(archive, offset, ca_spec, |row| {
+        cells.clear();
+        cells.extend(requested_ca.translate_indices(row, &reorder_map).map(|(i, v)| requested_ca.parameter_values[i][v].as_str()));
+        writer.write_row(&cells)
+    })?;
+    writer.finish()?;
+    Ok(rows)
+}
+
+// Decodes the CA stored at `offset` of an archive and writes it as CSV exactly as stored:
+// Columns sorted descending by their number of values, and values from 0 to v - 1.
+// The optional header names the columns p1 to pk. Returns the number of written rows.
+pub fn extract_raw_csv<R: 'static + Read + Seek, W: Write>(archive: R, offset: u64, ca_spec: &CASpec
+                       , writer: W, header: bool) -> Ca2Result<u64> {
+    extract_raw(archive, offset, ca_spec, OutputFormat::Csv.writer(writer, header).as_mut())
+}
+
+// Like `extract_raw_csv`, but writes the rows with any `CAWriter`
+pub fn extract_raw<R: 'static + Read + Seek>(archive: R, offset: u64, ca_spec: &CASpec
+                   , writer: &mut dyn CAWriter) -> Ca2Result<u64> {
+    let names : Vec<String> = (1..=ca_spec.vs.len()).map(|i| format!("p{}", i)).collect();
+    let values : Vec<Arc<[String]>> = ca_spec.vs.iter().map(|v| (0..*v).map(|value| value.to_string()).collect()).collect();
+
+    writer.begin(&names, &values)?;
+    let mut cells = Vec::with_capacity(names.len());
+    let rows = write_rows(archive, offset, ca_spec, |row| {
+        cells.clear();
+        for (i, value) in row.iter().enumerate() {
+            let column = values.get(i).ok_or_else(|| Ca2Error::Format(String::from("Row longer than the CA specification")))?;
+            let value = column.get(*value as usize).ok_or_else(|| Ca2Error::Format(format!("Value {} out of range in column {}", value, i + 1)))?;
+            cells.push(value.as_str());
+        }
+        writer.write_row(&cells)
+    })?;
+    writer.finish()?;
+    Ok(rows)
+}
+
+// Decodes all rows and passes each of them to `write_row`. Returns the number of rows.
+fn write_rows<R, F>(archive: R, offset: u64, ca_spec: &CASpec, mut write_row: F) -> Ca2Result<u64>
+where R: 'static + Read + Seek, F: FnMut(&[Value]) -> Ca2Result<()> {
+    // Rows are decoded in batches into a flat buffer
+    let mut compressed_ca = open_ca(archive, offset, ca_spec)?;
+    let row_len = compressed_ca.row_len().max(1);
+    let mut buf = vec![0; DECODE_BATCH_ROWS * row_len];
+
+    let mut n = 0;
+    loop {
+        let rows = compressed_ca.read_rows(&mut buf)?;
+        if rows == 0 {
+            break;
+        }
+        for row in buf[..rows * compressed_ca.row_len()].chunks(row_len) {
+            write_row(row)?;
+        }
+        n += rows as u64;
+    }
+    Ok(n)
+}
+
+// A CA written by `unpack_archive`
+pub struct UnpackedCA {
+    // Index of the entry in the archive
+    pub index: usize,
+    pub ca_spec: CASpec,
+    // The CA as raw CSV with header
+    pub csv: PathBuf,
+    // The stored data and metadata, ready to be packed again
+    pub cca: PathBuf,
+    pub ccmeta: PathBuf
+}
+
+// Writes the entries of an archive (all, or the ones with the given indices) into a directory.
+// Every CA is written as raw CSV (see `extract_raw_csv`) together with its stored data as .cca and .ccmeta file,
+// named by `ca_name`. Existing files are never overwritten; a suffix like _1 is added to the name instead.
+pub fn unpack_archive(archive: &Path, dir: &Path, indices: Option<&[usize]>) -> Ca2Result<Vec<UnpackedCA>> {
+    let index = read_archive_index(archive)?;
+    unpack_index(|| Ok(File::open(archive)?), archive, &index, dir, indices)
+}
+
+// Like `unpack_archive`, for an archive whose index has already been read, e.g. from stdin.
+// `open` returns a new reader of the archive for every CA.
+pub fn unpack_index<R, F>(open: F, archive: &Path, index: &ArchiveIndex, dir: &Path, indices: Option<&[usize]>) -> Ca2Result<Vec<UnpackedCA>>
+where R: 'static + Read + Seek, F: Fn() -> Ca2Result<R> {
+    let mut f = open()?;
+    if let Some(i) = indices.unwrap_or_default().iter().find(|i| **i >= index.entries.len()) {
+        return Err(Ca2Error::InvalidArgument(format!("Entry {} does not exist in {}", i, archive.display())));
+    }
+    std::fs::create_dir_all(dir)?;
+
+    let mut unpacked = Vec::new();
+    for (i, (offset, ca_spec)) in index.entries.iter().enumerate() {
+        if indices.is_some_and(|indices| !indices.contains(&i)) {
+            continue;
+        }
+        let name = ca_name(ca_spec);
+        let (csv, cca, ccmeta) = (0..)
+            .map(|suffix| match suffix {
+                0 => name.clone(),
+                _ => format!("{}_{}", name, suffix)
+            })
+            .map(|name| (dir.join(format!("{}.csv", name)), dir.join(format!("{}.cca", name)), dir.join(format!("{}.ccmeta", name))))
+            .find(|(csv, cca, ccmeta)| !csv.exists() && !cca.exists() && !ccmeta.exists())
+            .unwrap();
+
+        extract_raw_csv(open()?, *offset, ca_spec, BufWriter::new(File::create(&csv)?), true)?;
+
+        // The stored data is copied as is
+        let len = stored_length(&mut f, *offset, ca_spec)?;
+        f.seek(SeekFrom::Start(*offset))?;
+        let mut writer = BufWriter::new(File::create(&cca)?);
+        if std::io::copy(&mut (&mut f).take(len), &mut writer)? != len {
+            return Err(Ca2Error::Format(String::from("Truncated CA data in archive")));
+        }
+        writer.flush()?;
+        write_ccmeta(BufWriter::new(File::create(&ccmeta)?), ca_spec)?;
+
+        unpacked.push(UnpackedCA { index: i, ca_spec: ca_spec.clone(), csv, cca, ccmeta });
+    }
+    Ok(unpacked)
+}
@@ -0,0 +1,381 @@
+use std::str::FromStr;
+use crate::{try_parse_acts, Ca2Error, Ca2Result};
+
+// Formats of CA files that can be compressed
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum InputFormat {
+    // Comma-separated values, optionally with a header line of parameter names
+    Csv,
+    // Whitespace-separated values, optionally preceded by a line `N k v t` (or a prefix of it)
+    Whitespace,
+    // CASA output: A line with N, followed by whitespace-separated rows
+    Casa,
+    // ACTS output: A `[Test Set]` section after the model, or a CSV export with `#` comments
+    Acts,
+    // PICT output: Tab-separated values, optionally with a header line of parameter names
+    Pict,
+    // One line per parameter with its value in every row, separated by commas, tabs or spaces.
+    // A line may start with the name of its parameter followed by a colon.
+    Transposed
+}
+
+// The names of all formats, as accepted by `InputFormat::from_str`
+pub const INPUT_FORMATS : [&str; 6] = ["csv", "whitespace", "casa", "acts", "pict", "transposed"];
+
+impl FromStr for InputFormat {
+    type Err = Ca2Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(InputFormat::Csv),
+            "whitespace" => Ok(InputFormat::Whitespace),
+            "casa" => Ok(InputFormat::Casa),
+            "acts" => Ok(InputFormat::Acts),
+            "pict" => Ok(InputFormat::Pict),
+            "transposed" => Ok(InputFormat::Transposed),
+            _ => Err(Ca2Error::InvalidArgument(format!("Unknown input format {}, expected one of {}", s, INPUT_FORMATS.join(", "))))
+        }
+    }
+}
+
+// A CA read from a file, together with the metadata the file holds
+#[derive(Debug,Clone,Default)]
+pub struct ImportedCA {
+    // Parameter names from a header line
+    pub names: Option<Vec<String>>,
+    pub rows: Vec<Vec<String>>,
+    pub n: Option<u64>,
+    pub t: Option<u8>,
+    // Numbers of values in the order of the columns, e.g. from the parameters of an ACTS file
+    pub vs: Option<Vec<u16>>
+}
+
+// ACTS comments holding metadata
+const ACTS_STRENGTH : &str = "Degree of interaction coverage:";
+const ACTS_ROWS : &str = "Number of configurations:";
+const ACTS_TEST_SET : &str = "[Test Set]";
+// ACTS marks values that can be chosen freely
+const ACTS_DONT_CARE : &str = "*";
+
+// Guesses the format of a CA file from its contents, or only the beginning of them if `complete` is false.
+// Transposed files look like any of the other formats, so they are never detected.
+pub fn detect_format(contents: &str, complete: bool) -> InputFormat {
+    let mut lines = contents.lines().map(str::trim).filter(|line| !line.is_empty());
+    if contents.lines().any(|line| line.trim().eq_ignore_ascii_case(ACTS_TEST_SET))
+        || (contents.starts_with('#') && contents.contains(ACTS_STRENGTH)) {
+        return InputFormat::Acts;
+    }
+    let first = lines.next().unwrap_or("");
+    if first.contains('\t') {
+        InputFormat::Pict
+    } else if first.contains(',') {
+        InputFormat::Csv
+    } else if is_casa(contents, complete) {
+        InputFormat::Casa
+    } else if first.split_whitespace().count() > 1 {
+        InputFormat::Whitespace
+    } else {
+        InputFormat::Csv
+    }
+}
+
+// CASA files start with a line holding N and k, optionally followed by v, which have to agree with the rows.
+// A single number is not enough, since it is as well the first line of a CSV file with one numeric column.
+fn is_casa(contents: &str, complete: bool) -> bool {
+    let mut lines : Vec<&str> = contents.lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+    // The last line of an incomplete file may be cut off
+    if !complete {
+        lines.pop();
+    }
+    let Some(header) = lines.first()
+        .and_then(|first| first.split_whitespace().map(|value| value.parse::<u64>().ok()).collect::<Option<Vec<_>>>())
+        .filter(|header| header.len() == 2 || header.len() == 3) else {
+        return false;
+    };
+    let rows = lines.len() as u64 - 1;
+    let rows_agree = match complete {
+        true => rows == header[0],
+        false => rows <= header[0]
+    };
+    rows_agree && lines[1..].iter().all(|line| line.split_whitespace().count() as u64 == header[1])
+}
+
+// Reads a CA in the given format. `has_header` only applies to formats whose header line is optional,
+// i.e. CSV and PICT. Metadata that the format does not hold is left empty.
+pub fn import_ca(contents: &str, format: InputFormat, has_header: bool) -> Ca2Result<ImportedCA> {
+    let imported = match format {
+        InputFormat::Csv => import_delimited(contents, b',', has_header)?,
+        InputFormat::Pict => import_delimited(contents, b'\t', has_header)?,
+        InputFormat::Whitespace | InputFormat::Casa => import_whitespace(contents, format == InputFormat::Casa)?,
+        InputFormat::Acts => import_acts(contents)?,
+        InputFormat::Transposed => import_transposed(contents)?
+    };
+    if let Some(n) = imported.n.filter(|n| *n != imported.rows.len() as u64) {
+        return Err(Ca2Error::Format(format!("The file states {} rows, but contains {}", n, imported.rows.len())));
+    }
+    Ok(imported)
+}
+
+fn import_delimited(contents: &str, delimiter: u8, has_header: bool) -> Ca2Result<ImportedCA> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(has_header)
+        .quoting(delimiter == b',')
+        .from_reader(contents.as_bytes());
+    let names = match has_header {
+        true => Some(reader.headers()?.iter().map(String::from).collect()),
+        false => None
+    };
+    let rows = reader.records()
+        .map(|record| Ok(record?.iter().map(String::from).collect()))
+        .collect::<Ca2Result<_>>()?;
+    Ok(ImportedCA { names, rows, ..Default::default() })
+}
+
+// Whitespace-separated rows, optionally preceded by a header line with N, k, v and t (in this order,
+// later ones may be missing). A line of numbers is only taken as header if N matches the number of rows
+// and k the length of the first one.
+fn import_whitespace(contents: &str, require_header: bool) -> Ca2Result<ImportedCA> {
+    let mut rows : Vec<Vec<String>> = contents.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| line.split_whitespace().map(String::from).collect())
+        .collect();
+    let header : Option<Vec<u64>> = rows.first()
+        .filter(|first| first.len() <= 4)
+        .and_then(|first| first.iter().map(|value| value.parse::<u64>().ok()).collect::<Option<Vec<_>>>())
+        .filter(|header| header[0] == rows.len() as u64 - 1)
+        .filter(|header| header.len() < 2 || rows.get(1).is_some_and(|row| header[1] == row.len() as u64));
+    let Some(header) = header else {
+        if require_header {
+            return Err(Ca2Error::Parse(String::from("CASA files have to start with the number of rows")));
+        }
+        return Ok(ImportedCA { rows, ..Default::default() });
+    };
+    rows.remove(0);
+
+    let k : Option<usize> = header_number(&header, 1, "number of parameters")?;
+    let v : Option<u16> = header_number(&header, 2, "number of values")?;
+    Ok(ImportedCA {
+        names: None,
+        rows,
+        n: Some(header[0]),
+        t: header_number(&header, 3, "strength")?,
+        vs: k.zip(v).map(|(k, v)| vec![v; k])
+    })
+}
+
+fn header_number<T: TryFrom<u64>>(header: &[u64], i: usize, what: &str) -> Ca2Result<Option<T>> {
+    header.get(i)
+        .map(|value| T::try_from(*value).map_err(|_| Ca2Error::Parse(format!("Invalid {} {} in header line", what, value))))
+        .transpose()
+}
+
+// ACTS writes test sets after the model of the system, or as CSV with comments holding the metadata.
+// Parameters of the model determine the numbers of values, and don't care values (`*`) are replaced
+// by the first value of their parameter.
+fn import_acts(contents: &str) -> Ca2Result<ImportedCA> {
+    let comment_value = |key: &str| contents.lines()
+        .filter_map(|line| line.trim_start_matches('#').trim().strip_prefix(key))
+        .map(|value| value.trim().to_string())
+        .next();
+    let t = comment_value(ACTS_STRENGTH)
+        .map(|t| t.parse::<u8>().map_err(|_| Ca2Error::Parse(format!("Invalid strength {}", t))))
+        .transpose()?;
+    let n = comment_value(ACTS_ROWS)
+        .map(|n| n.parse::<u64>().map_err(|_| Ca2Error::Parse(format!("Invalid number of configurations {}", n))))
+        .transpose()?;
+
+    // The model before the test set, if there is one. A model that can not be parsed is an error,
+    // since the numbers of values of the test set would silently be lost.
+    let lines : Vec<&str> = contents.lines().collect();
+    let test_set = lines.iter().position(|line| line.trim().eq_ignore_ascii_case(ACTS_TEST_SET));
+    let (model, test_set) = match test_set {
+        Some(i) => {
+            let model : Vec<&str> = lines[..i].iter().copied().filter(|line| !line.trim_start().starts_with('#')).collect();
+            let model = match model.iter().all(|line| line.trim().is_empty()) {
+                true => None,
+                false => Some(try_parse_acts(&model.join("\n"), t.unwrap_or(2)).map_err(|e| Ca2Error::Format(format!(
+                    "Invalid model before the test set: {}", e
+                )))?)
+            };
+            (model, &lines[i + 1..])
+        },
+        None => (None, &lines[..])
+    };
+
+    // The test set ends with the next section
+    let mut test_set = test_set.iter()
+        .map(|line| line.trim())
+        .take_while(|line| !line.starts_with('['))
+        .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with("--"))
+        .map(|line| line.split(',').map(|value| value.trim().to_string()).collect::<Vec<String>>());
+    let names = test_set.next().ok_or_else(|| Ca2Error::Parse(String::from("The ACTS file contains no test set")))?;
+    let mut rows : Vec<Vec<String>> = test_set.collect();
+
+    let vs = match model {
+        Some(model) => {
+            // The columns of the test set can be in any order
+            let parameters = names.iter()
+                .map(|name| model.parameter_names.iter().position(|p| p == name)
+                     .ok_or_else(|| Ca2Error::Parse(format!("Parameter {} of the test set is not part of the model", name))))
+                .collect::<Ca2Result<Vec<usize>>>()?;
+            for row in rows.iter_mut() {
+                for (value, p) in row.iter_mut().zip(parameters.iter()) {
+                    if value == ACTS_DONT_CARE {
+                        value.clone_from(&model.parameter_values[*p][0]);
+                    }
+                }
+            }
+            Some(parameters.iter().map(|p| model.parameter_sizes[*p]).collect())
+        },
+        None => None
+    };
+    Ok(ImportedCA { names: Some(names), rows, n, t, vs })
+}
+
+fn import_transposed(contents: &str) -> Ca2Result<ImportedCA> {
+    let mut names = Vec::new();
+    let mut columns : Vec<Vec<String>> = Vec::new();
+    for line in contents.lines().map(str::trim).filter(|line| !line.is_empty()) {
+        let (name, values) = match line.split_once(':') {
+            Some((name, values)) => (Some(name.trim().to_string()), values),
+            None => (None, line)
+        };
+        let values : Vec<String> = if values.contains(',') {
+            values.split(',').map(|value| value.trim().to_string()).collect()
+        } else if values.contains('\t') {
+            values.split('\t').map(|value| value.trim().to_string()).collect()
+        } else {
+            values.split_whitespace().map(String::from).collect()
+        };
+        if columns.first().is_some_and(|first| first.len() != values.len()) {
+            return Err(Ca2Error::Parse(format!(
+                "Parameter {} has {} values, expected {}", columns.len() + 1, values.len(), columns[0].len()
+            )));
+        }
+        names.push(name);
+        columns.push(values);
+    }
+
+    let n = columns.first().map_or(0, |first| first.len());
+    let rows = (0..n).map(|i| columns.iter().map(|column| column[i].clone()).collect()).collect();
+    let names = match names.iter().all(Option::is_some) && !names.is_empty() {
+        true => Some(names.into_iter().map(Option::unwrap).collect()),
+        false => None
+    };
+    Ok(ImportedCA { names, rows, ..Default::default() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(rows: &[&[&str]]) -> Vec<Vec<String>> {
+        rows.iter().map(|row| row.iter().map(|value| value.to_string()).collect()).collect()
+    }
+
+    const ACTS_TEST_SET_FILE : &str = "\
+[System]
+Name: s
+
+[Parameter]
+p1 (enum): a,b,c
+p2 (boolean): true,false
+
+[Test Set]
+p2,p1
+true,a
+false,*
+*,c
+";
+
+    #[test]
+    fn formats_are_detected() {
+        assert_eq!(detect_format("a,b,c\n0,1,2\n", true), InputFormat::Csv);
+        assert_eq!(detect_format("a\tb\n0\t1\n", true), InputFormat::Pict);
+        assert_eq!(detect_format("3 2\n0 1\n1 0\n1 1\n", true), InputFormat::Casa);
+        assert_eq!(detect_format("3 2 2\n0 1\n1 0\n1 1\n", true), InputFormat::Casa);
+        assert_eq!(detect_format("0 1 2\n1 0 2\n", true), InputFormat::Whitespace);
+        assert_eq!(detect_format("# Degree of interaction coverage: 2\np1,p2\na,true\n", true), InputFormat::Acts);
+        assert_eq!(detect_format(ACTS_TEST_SET_FILE, true), InputFormat::Acts);
+        // A single column is read as CSV
+        assert_eq!(detect_format("a\nb\n", true), InputFormat::Csv);
+    }
+
+    #[test]
+    fn casa_is_only_detected_when_the_header_agrees_with_the_rows() {
+        // A CSV file with one numeric column
+        assert_eq!(detect_format("2\n0\n1\n", true), InputFormat::Csv);
+        assert_eq!(detect_format("1\n0\n1\n0\n", true), InputFormat::Csv);
+        // Wrong number of rows or columns
+        assert_eq!(detect_format("4 2\n0 1\n1 0\n1 1\n", true), InputFormat::Whitespace);
+        assert_eq!(detect_format("3 3\n0 1\n1 0\n1 1\n", true), InputFormat::Whitespace);
+        // The beginning of a file may hold fewer rows, and end with a cut off one
+        assert_eq!(detect_format("4 2\n0 1\n1 0\n1", false), InputFormat::Casa);
+        assert_eq!(detect_format("2 2\n0 1\n1 0\n1 1\n0", false), InputFormat::Whitespace);
+    }
+
+    #[test]
+    fn whitespace_header_is_taken_when_n_matches() {
+        let imported = import_whitespace("2 3 2 2\n0 1 1\n1 0 1\n", false).unwrap();
+        assert_eq!(imported.rows, rows(&[&["0", "1", "1"], &["1", "0", "1"]]));
+        assert_eq!(imported.n, Some(2));
+        assert_eq!(imported.t, Some(2));
+        assert_eq!(imported.vs, Some(vec![2, 2, 2]));
+
+        // Only N and k
+        let imported = import_whitespace("1 2\n0 1\n", false).unwrap();
+        assert_eq!((imported.n, imported.t, imported.vs), (Some(1), None, None));
+    }
+
+    #[test]
+    fn whitespace_rows_that_look_like_a_header_are_kept() {
+        // N does not match the number of rows
+        let imported = import_whitespace("3 3 2 2\n0 1 1\n1 0 1\n", false).unwrap();
+        assert_eq!(imported.rows.len(), 3);
+        assert_eq!(imported.n, None);
+        // k does not match the length of the rows
+        let imported = import_whitespace("2 2\n0 1 1\n1 0 1\n", false).unwrap();
+        assert_eq!(imported.rows.len(), 3);
+        assert_eq!(imported.n, None);
+    }
+
+    #[test]
+    fn casa_files_require_the_header() {
+        assert_eq!(import_whitespace("2\n0 1\n1 0\n", true).unwrap().n, Some(2));
+        assert!(matches!(import_whitespace("3\n0 1\n1 0\n", true), Err(Ca2Error::Parse(_))));
+    }
+
+    #[test]
+    fn acts_dont_care_values_are_replaced() {
+        let imported = import_acts(ACTS_TEST_SET_FILE).unwrap();
+        assert_eq!(imported.names, Some(vec![String::from("p2"), String::from("p1")]));
+        // The first value of the parameter, in the order of the test set's columns
+        assert_eq!(imported.rows, rows(&[&["true", "a"], &["false", "a"], &["true", "c"]]));
+        assert_eq!(imported.vs, Some(vec![2, 3]));
+    }
+
+    #[test]
+    fn acts_csv_exports_keep_their_metadata() {
+        let contents = "# Degree of interaction coverage: 2\n# Number of configurations: 2\np1,p2\na,*\nb,true\n";
+        let imported = import_acts(contents).unwrap();
+        assert_eq!((imported.n, imported.t, imported.vs), (Some(2), Some(2), None));
+        // Without a model, don't care values cannot be replaced
+        assert_eq!(imported.rows, rows(&[&["a", "*"], &["b", "true"]]));
+    }
+
+    #[test]
+    fn acts_models_have_to_be_valid() {
+        let contents = ACTS_TEST_SET_FILE.replace("p2 (boolean)", "p2 (float)");
+        assert!(matches!(import_acts(&contents), Err(Ca2Error::Format(_))));
+        // Without a model, the test set is read as it is
+        let contents = "# Degree of interaction coverage: 2\n\n[Test Set]\np1,p2\na,*\n";
+        assert_eq!(import_acts(contents).unwrap().vs, None);
+    }
+
+    #[test]
+    fn acts_test_sets_only_hold_parameters_of_the_model() {
+        let contents = ACTS_TEST_SET_FILE.replace("p2,p1", "p3,p1");
+        assert!(matches!(import_acts(&contents), Err(Ca2Error::Parse(_))));
+    }
+}
@@ -0,0 +1,226 @@
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use crate::{CASpec, CAKind, ArchiveIndex, read_archive_index, Ca2Error, Ca2Result};
+
+// An entry of an archive with the size of its compressed data
+#[derive(Debug,Clone)]
+pub struct ListedCA {
+    pub archive: PathBuf,
+    pub index: usize,
+    pub offset: u64,
+    pub size: u64,
+    pub ca_spec: CASpec
+}
+
+// The entries of an archive that match all filters
+pub fn list_archive(archive: &Path, filters: &[Filter]) -> Ca2Result<Vec<ListedCA>> {
+    Ok(list_index(archive, &read_archive_index(archive)?, filters))
+}
+
+// The entries of an index that has already been read, e.g. from an archive on stdin, that match all filters
+pub fn list_index(archive: &Path, index: &ArchiveIndex, filters: &[Filter]) -> Vec<ListedCA> {
+    index.entries.iter()
+        .enumerate()
+        .map(|(i, (offset, ca_spec))| ListedCA {
+            archive: archive.to_path_buf(),
+            index: i,
+            offset: *offset,
+            size: index.data_size(*offset),
+            ca_spec: ca_spec.clone()
+        })
+        .filter(|entry| filters.iter().all(|filter| filter.matches(entry)))
+        .collect()
+}
+
+// The name of a kind of CA in listings and JSON output
+pub fn kind_name(kind: CAKind) -> &'static str {
+    match kind {
+        CAKind::Covering => "covering",
+        CAKind::Sequence => "sequence",
+        CAKind::Unused => "unused"
+    }
+}
+
+// Conditions like t=3 or k>=20, see `Filter`
+pub fn parse_filters<I, S>(conditions: I) -> Ca2Result<Vec<Filter>>
+where I: IntoIterator<Item = S>, S: AsRef<str> {
+    conditions.into_iter().map(|condition| condition.as_ref().parse()).collect()
+}
+
+// A condition on the listed entries, e.g. k>=20, see `Field` and `Op`
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub struct Filter {
+    field: Field,
+    op: Op,
+    value: u64
+}
+
+// A field of the listed entries that conditions compare
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Field {
+    N,
+    T,
+    K,
+    // The largest number of values
+    V,
+    Version,
+    Size,
+    Index,
+    Offset
+}
+
+pub const FILTER_FIELDS : [&str; 8] = ["n", "t", "k", "v", "version", "size", "index", "offset"];
+
+impl FromStr for Field {
+    type Err = Ca2Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "n" => Ok(Field::N),
+            "t" => Ok(Field::T),
+            "k" => Ok(Field::K),
+            "v" => Ok(Field::V),
+            "version" => Ok(Field::Version),
+            "size" => Ok(Field::Size),
+            "index" => Ok(Field::Index),
+            "offset" => Ok(Field::Offset),
+            _ => Err(Ca2Error::InvalidArgument(format!("Unknown field {}, expected one of {}", s, FILTER_FIELDS.join(", "))))
+        }
+    }
+}
+
+impl Field {
+    pub fn value(self, entry: &ListedCA) -> u64 {
+        match self {
+            Field::N => entry.ca_spec.n,
+            Field::T => entry.ca_spec.t.into(),
+            Field::K => entry.ca_spec.vs.len() as u64,
+            // Stored vs are sorted descending
+            Field::V => entry.ca_spec.vs.first().copied().unwrap_or(0).into(),
+            Field::Version => u16::from(entry.ca_spec.version).into(),
+            Field::Size => entry.size,
+            Field::Index => entry.index as u64,
+            Field::Offset => entry.offset
+        }
+    }
+}
+
+// The comparison of a condition
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Op {
+    Lt,
+    Le,
+    Eq,
+    Ne,
+    Gt,
+    Ge
+}
+
+// Longer operators first, so that <= is not read as <
+const OPERATORS : [(&str, Op); 7] = [("<=", Op::Le), (">=", Op::Ge), ("!=", Op::Ne), ("==", Op::Eq), ("=", Op::Eq), ("<", Op::Lt), (">", Op::Gt)];
+
+impl Op {
+    pub fn compare(self, actual: u64, value: u64) -> bool {
+        match self {
+            Op::Lt => actual < value,
+            Op::Le => actual <= value,
+            Op::Eq => actual == value,
+            Op::Ne => actual != value,
+            Op::Gt => actual > value,
+            Op::Ge => actual >= value
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = Ca2Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || Ca2Error::InvalidArgument(format!("Invalid condition: {}", s));
+        let start = s.find(['<', '>', '=', '!']).ok_or_else(invalid)?;
+        let (operator, op) = OPERATORS.iter().find(|(operator, _)| s[start..].starts_with(operator)).ok_or_else(invalid)?;
+        let field = s[..start].trim();
+        let field = field.parse().map_err(|_| Ca2Error::InvalidArgument(format!(
+            "Unknown field {} in condition {}, expected one of {}", field, s, FILTER_FIELDS.join(", ")
+        )))?;
+        let value = s[start + operator.len()..].trim().parse().map_err(|_| invalid())?;
+        Ok(Filter { field, op: *op, value })
+    }
+}
+
+impl Filter {
+    pub fn matches(&self, entry: &ListedCA) -> bool {
+        self.op.compare(self.field.value(entry), self.value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CA2Version;
+
+    // An entry with a distinct value for every field
+    fn entry() -> ListedCA {
+        ListedCA {
+            archive: PathBuf::from("cas.ca2"),
+            index: 4,
+            offset: 1000,
+            size: 250,
+            ca_spec: CASpec { version: CA2Version::Bzip2, kind: CAKind::Covering, n: 30, t: 3, vs: vec![5, 3, 2, 2] }
+        }
+    }
+
+    fn matches(condition: &str) -> bool {
+        condition.parse::<Filter>().unwrap().matches(&entry())
+    }
+
+    #[test]
+    fn filters_compare_every_field() {
+        let fields = [("n", 30), ("t", 3), ("k", 4), ("v", 5), ("version", 2), ("size", 250), ("index", 4), ("offset", 1000)];
+        for (field, value) in fields {
+            assert!(matches(&format!("{}={}", field, value)), "{}", field);
+            assert!(!matches(&format!("{}={}", field, value + 1)), "{}", field);
+        }
+        // Field names are case insensitive, and spaces around them are ignored
+        assert!(matches(" K = 4 "));
+        assert!(matches("VERSION>=2"));
+        assert_eq!(" K = 4 ".parse::<Filter>().unwrap(), Filter { field: Field::K, op: Op::Eq, value: 4 });
+    }
+
+    #[test]
+    fn filters_apply_every_operator() {
+        let operators = [
+            ("=", [false, true, false]),
+            ("==", [false, true, false]),
+            ("!=", [true, false, true]),
+            ("<", [false, false, true]),
+            ("<=", [false, true, true]),
+            (">", [true, false, false]),
+            (">=", [true, true, false])
+        ];
+        for (operator, expected) in operators {
+            for (value, expected) in [29, 30, 31].into_iter().zip(expected) {
+                assert_eq!(matches(&format!("n{}{}", operator, value)), expected, "n{}{}", operator, value);
+            }
+        }
+    }
+
+    #[test]
+    fn invalid_conditions_are_rejected() {
+        for condition in ["n>>3", "n=<3", "n", "n=", "n>=x", "n=-1", "t!3"] {
+            match condition.parse::<Filter>() {
+                Err(Ca2Error::InvalidArgument(msg)) => assert_eq!(msg, format!("Invalid condition: {}", condition)),
+                other => panic!("{} parsed as {:?}", condition, other.map_err(|e| e.to_string()))
+            }
+        }
+        for condition in ["x=3", "rows>3", "=3"] {
+            let Err(Ca2Error::InvalidArgument(msg)) = condition.parse::<Filter>() else {
+                panic!("{} was accepted", condition);
+            };
+            assert!(msg.starts_with("Unknown field"), "{}", msg);
+        }
+        assert!(parse_filters(["t=3", "k>>3"]).is_err());
+        assert!("x".parse::<Field>().is_err());
+        assert_eq!(parse_filters(["t=3", "k>=4"]).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,402 @@
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::Arc;
+use crate::{TestFramework, Ca2Error, Ca2Result};
+
+// Formats that decoded CAs can be written in
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum OutputFormat {
+    Csv,
+    // Tab-separated; tabs, newlines and backslashes in values are escaped as \t, \n, \r and \\
+    Tsv,
+    // An array of objects that map parameter names to values
+    Json,
+    // One object per line
+    Ndjson,
+    // An ACTS model with the rows as [Test Set], which ACTS can import again
+    Acts,
+    Markdown,
+    Asciidoc,
+    // Test code with one test case per row
+    Tests(TestFramework)
+}
+
+pub const OUTPUT_FORMATS : [&str; 11] = ["csv", "tsv", "json", "ndjson", "acts", "markdown", "asciidoc"
+                                         , "pytest", "junit", "rstest", "gherkin"];
+
+impl FromStr for OutputFormat {
+    type Err = Ca2Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "csv" => Ok(OutputFormat::Csv),
+            "tsv" => Ok(OutputFormat::Tsv),
+            "json" => Ok(OutputFormat::Json),
+            "ndjson" | "jsonl" => Ok(OutputFormat::Ndjson),
+            "acts" => Ok(OutputFormat::Acts),
+            "markdown" | "md" => Ok(OutputFormat::Markdown),
+            "asciidoc" | "adoc" => Ok(OutputFormat::Asciidoc),
+            "pytest" => Ok(OutputFormat::Tests(TestFramework::Pytest)),
+            "junit" => Ok(OutputFormat::Tests(TestFramework::Junit)),
+            "rstest" => Ok(OutputFormat::Tests(TestFramework::Rstest)),
+            "gherkin" => Ok(OutputFormat::Tests(TestFramework::Gherkin)),
+            _ => Err(Ca2Error::InvalidArgument(format!("Unknown output format {}, expected one of {}", s, OUTPUT_FORMATS.join(", "))))
+        }
+    }
+}
+
+impl OutputFormat {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            OutputFormat::Csv => "text/csv",
+            OutputFormat::Tsv => "text/tab-separated-values",
+            OutputFormat::Json => "application/json",
+            OutputFormat::Ndjson => "application/x-ndjson",
+            OutputFormat::Acts | OutputFormat::Asciidoc => "text/plain",
+            OutputFormat::Markdown => "text/markdown",
+            OutputFormat::Tests(TestFramework::Pytest) => "text/x-python",
+            OutputFormat::Tests(TestFramework::Junit) => "text/x-java",
+            OutputFormat::Tests(TestFramework::Rstest) => "text/x-rust",
+            OutputFormat::Tests(TestFramework::Gherkin) => "text/plain"
+        }
+    }
+
+    // Creates a writer for this format. Without `header`, CSV, TSV and AsciiDoc are written without
+    // the parameter names; the other formats always contain them.
+    pub fn writer<'a, W: Write + 'a>(&self, writer: W, header: bool) -> Box<dyn CAWriter + 'a> {
+        match self {
+            OutputFormat::Csv => Box::new(CsvWriter { writer: csv::Writer::from_writer(writer), header }),
+            OutputFormat::Tsv => Box::new(TsvWriter { writer, header }),
+            OutputFormat::Json => Box::new(JsonWriter { writer, names: Vec::new(), lines: false, rows: 0 }),
+            OutputFormat::Ndjson => Box::new(JsonWriter { writer, names: Vec::new(), lines: true, rows: 0 }),
+            OutputFormat::Acts => Box::new(ActsWriter { writer }),
+            OutputFormat::Markdown => Box::new(MarkdownWriter { writer }),
+            OutputFormat::Asciidoc => Box::new(AsciidocWriter { writer, header }),
+            OutputFormat::Tests(framework) => framework.writer(writer)
+        }
+    }
+}
+
+// Writes the rows of a CA. `begin` is called once with the names and the values of all parameters,
+// then `write_row` for every row, and `finish` at the end.
+pub trait CAWriter {
+    fn begin(&mut self, names: &[String], values: &[Arc<[String]>]) -> Ca2Result<()>;
+    fn write_row(&mut self, row: &[&str]) -> Ca2Result<()>;
+    fn finish(&mut self) -> Ca2Result<()>;
+}
+
+struct CsvWriter<W: Write> {
+    writer: csv::Writer<W>,
+    header: bool
+}
+
+impl<W: Write> CAWriter for CsvWriter<W> {
+    fn begin(&mut self, names: &[String], _: &[Arc<[String]>]) -> Ca2Result<()> {
+        if self.header {
+            self.writer.write_record(names)?;
+        }
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &[&str]) -> Ca2Result<()> {
+        Ok(self.writer.write_record(row)?)
+    }
+
+    fn finish(&mut self) -> Ca2Result<()> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+struct TsvWriter<W: Write> {
+    writer: W,
+    header: bool
+}
+
+impl<W: Write> TsvWriter<W> {
+    fn write_line<S: AsRef<str>>(&mut self, values: &[S]) -> Ca2Result<()> {
+        let line : Vec<String> = values.iter()
+            .map(|value| value.as_ref().replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n").replace('\r', "\\r"))
+            .collect();
+        Ok(writeln!(self.writer, "{}", line.join("\t"))?)
+    }
+}
+
+impl<W: Write> CAWriter for TsvWriter<W> {
+    fn begin(&mut self, names: &[String], _: &[Arc<[String]>]) -> Ca2Result<()> {
+        match self.header {
+            true => self.write_line(names),
+            false => Ok(())
+        }
+    }
+
+    fn write_row(&mut self, row: &[&str]) -> Ca2Result<()> {
+        self.write_line(row)
+    }
+
+    fn finish(&mut self) -> Ca2Result<()> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+// Objects are written by hand to keep the parameters in order
+struct JsonWriter<W: Write> {
+    writer: W,
+    // The parameter names as JSON strings
+    names: Vec<String>,
+    lines: bool,
+    rows: u64
+}
+
+fn json_string(s: &str) -> String {
+    serde_json::Value::from(s).to_string()
+}
+
+impl<W: Write> CAWriter for JsonWriter<W> {
+    fn begin(&mut self, names: &[String], _: &[Arc<[String]>]) -> Ca2Result<()> {
+        self.names = names.iter().map(|name| json_string(name)).collect();
+        if !self.lines {
+            self.writer.write_all(b"[")?;
+        }
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &[&str]) -> Ca2Result<()> {
+        let fields : Vec<String> = self.names.iter().zip(row.iter())
+            .map(|(name, value)| format!("{}: {}", name, json_string(value)))
+            .collect();
+        match (self.lines, self.rows) {
+            (true, _) => writeln!(self.writer, "{{{}}}", fields.join(", "))?,
+            (false, 0) => write!(self.writer, "\n  {{{}}}", fields.join(", "))?,
+            (false, _) => write!(self.writer, ",\n  {{{}}}", fields.join(", "))?
+        }
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Ca2Result<()> {
+        if !self.lines {
+            self.writer.write_all(if self.rows == 0 { b"]\n" } else { b"\n]\n" })?;
+        }
+        Ok(self.writer.flush()?)
+    }
+}
+
+// The system name of written ACTS models
+const ACTS_SYSTEM_NAME : &str = "CA";
+
+struct ActsWriter<W: Write> {
+    writer: W
+}
+
+// Names and values that the ACTS format can hold
+fn is_acts_value(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c == '_' || c == '-' || c.is_ascii_alphanumeric())
+}
+
+fn is_acts_name(s: &str) -> bool {
+    is_acts_value(s) && s.starts_with(|c: char| c == '_' || c.is_ascii_alphabetic())
+}
+
+// The type of a parameter in ACTS models and generated test code
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub(crate) enum ValueType {
+    Int,
+    Boolean,
+    Text
+}
+
+// Parameters whose values are all integers are int parameters, true and false make a boolean one
+pub(crate) fn value_type(values: &[String]) -> ValueType {
+    let mut sorted : Vec<&str> = values.iter().map(String::as_str).collect();
+    sorted.sort_unstable();
+    if sorted == ["false", "true"] {
+        ValueType::Boolean
+    } else if values.iter().all(|value| value.parse::<i64>().is_ok()) {
+        ValueType::Int
+    } else {
+        ValueType::Text
+    }
+}
+
+fn acts_type(values: &[String]) -> &'static str {
+    match value_type(values) {
+        ValueType::Int => "int",
+        ValueType::Boolean => "boolean",
+        ValueType::Text => "enum"
+    }
+}
+
+impl<W: Write> CAWriter for ActsWriter<W> {
+    fn begin(&mut self, names: &[String], values: &[Arc<[String]>]) -> Ca2Result<()> {
+        writeln!(self.writer, "[System]\nName: {}\n\n[Parameter]", ACTS_SYSTEM_NAME)?;
+        for (name, values) in names.iter().zip(values.iter()) {
+            if !is_acts_name(name) {
+                return Err(Ca2Error::InvalidArgument(format!("Parameter name {} can not be written to an ACTS file", name)));
+            }
+            if let Some(value) = values.iter().find(|value| !is_acts_value(value)) {
+                return Err(Ca2Error::InvalidArgument(format!("Value {} of parameter {} can not be written to an ACTS file", value, name)));
+            }
+            writeln!(self.writer, "{} ({}): {}", name, acts_type(values), values.join(", "))?;
+        }
+        Ok(writeln!(self.writer, "\n[Test Set]\n{}", names.join(","))?)
+    }
+
+    fn write_row(&mut self, row: &[&str]) -> Ca2Result<()> {
+        Ok(writeln!(self.writer, "{}", row.join(","))?)
+    }
+
+    fn finish(&mut self) -> Ca2Result<()> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+struct MarkdownWriter<W: Write> {
+    writer: W
+}
+
+impl<W: Write> MarkdownWriter<W> {
+    fn write_line<S: AsRef<str>>(&mut self, values: &[S]) -> Ca2Result<()> {
+        let cells : Vec<String> = values.iter()
+            .map(|value| value.as_ref().replace('\\', "\\\\").replace('|', "\\|").replace('\n', " "))
+            .collect();
+        Ok(writeln!(self.writer, "| {} |", cells.join(" | "))?)
+    }
+}
+
+// Markdown tables need a header, so it is always written
+impl<W: Write> CAWriter for MarkdownWriter<W> {
+    fn begin(&mut self, names: &[String], _: &[Arc<[String]>]) -> Ca2Result<()> {
+        self.write_line(names)?;
+        Ok(writeln!(self.writer, "|{}", " --- |".repeat(names.len()))?)
+    }
+
+    fn write_row(&mut self, row: &[&str]) -> Ca2Result<()> {
+        self.write_line(row)
+    }
+
+    fn finish(&mut self) -> Ca2Result<()> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+struct AsciidocWriter<W: Write> {
+    writer: W,
+    header: bool
+}
+
+impl<W: Write> AsciidocWriter<W> {
+    fn write_line<S: AsRef<str>>(&mut self, values: &[S]) -> Ca2Result<()> {
+        let cells : Vec<String> = values.iter().map(|value| value.as_ref().replace('|', "\\|")).collect();
+        Ok(writeln!(self.writer, "|{}", cells.join(" |"))?)
+    }
+}
+
+impl<W: Write> CAWriter for AsciidocWriter<W> {
+    fn begin(&mut self, names: &[String], _: &[Arc<[String]>]) -> Ca2Result<()> {
+        if self.header {
+            writeln!(self.writer, "[options=\"header\"]")?;
+        }
+        writeln!(self.writer, "|===")?;
+        match self.header {
+            true => self.write_line(names),
+            false => Ok(())
+        }
+    }
+
+    fn write_row(&mut self, row: &[&str]) -> Ca2Result<()> {
+        self.write_line(row)
+    }
+
+    fn finish(&mut self) -> Ca2Result<()> {
+        writeln!(self.writer, "|===")?;
+        Ok(self.writer.flush()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn values(values: &[&str]) -> Arc<[String]> {
+        values.iter().map(|value| value.to_string()).collect()
+    }
+
+    // Writes a CA with two parameters in `format`
+    fn write(format: OutputFormat, header: bool, names: [&str; 2], rows: &[[&str; 2]]) -> Ca2Result<String> {
+        let names : Vec<String> = names.iter().map(|name| name.to_string()).collect();
+        let columns : Vec<Arc<[String]>> = (0..2).map(|i| values(&rows.iter().map(|row| row[i]).collect::<Vec<_>>())).collect();
+        let mut out = Vec::new();
+        {
+            let mut writer = format.writer(&mut out, header);
+            writer.begin(&names, &columns)?;
+            for row in rows.iter() {
+                writer.write_row(row)?;
+            }
+            writer.finish()?;
+        }
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn tsv_escapes_separators() {
+        assert_eq!(write(OutputFormat::Tsv, true, ["a b", "c\td"], &[["x\ty", "1\n2"], ["back\\slash", "cr\r"]]).unwrap(),
+                   "a b\tc\\td\nx\\ty\t1\\n2\nback\\\\slash\tcr\\r\n");
+        assert_eq!(write(OutputFormat::Tsv, false, ["a", "b"], &[["1", "2"]]).unwrap(), "1\t2\n");
+    }
+
+    #[test]
+    fn json_escapes_strings() {
+        let rows = [["say \"hi\"", "C:\\dir"], ["tab\there", "bell\u{7}"]];
+        let json = write(OutputFormat::Json, true, ["quote\"d", "p"], &rows).unwrap();
+        assert_eq!(json, "[\n  {\"quote\\\"d\": \"say \\\"hi\\\"\", \"p\": \"C:\\\\dir\"},\n  {\"quote\\\"d\": \"tab\\there\", \"p\": \"bell\\u0007\"}\n]\n");
+        // The output is valid JSON with the original strings, and the parameters in their order
+        let parsed : serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed[1]["p"], "bell\u{7}");
+        assert_eq!(parsed[0]["quote\"d"], "say \"hi\"");
+
+        let ndjson = write(OutputFormat::Ndjson, false, ["b", "a"], &rows).unwrap();
+        let lines : Vec<serde_json::Value> = ndjson.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["a"], "C:\\dir");
+        assert!(ndjson.starts_with("{\"b\": "));
+        assert_eq!(write(OutputFormat::Json, true, ["a", "b"], &[]).unwrap(), "[]\n");
+    }
+
+    #[test]
+    fn table_cells_escape_pipes() {
+        assert_eq!(write(OutputFormat::Markdown, false, ["a|b", "c"], &[["x|y", "multi\nline"], ["back\\", "z"]]).unwrap(),
+                   "| a\\|b | c |\n| --- | --- |\n| x\\|y | multi line |\n| back\\\\ | z |\n");
+        assert_eq!(write(OutputFormat::Asciidoc, true, ["a|b", "c"], &[["x|y", "z"]]).unwrap(),
+                   "[options=\"header\"]\n|===\n|a\\|b |c\n|x\\|y |z\n|===\n");
+        assert_eq!(write(OutputFormat::Asciidoc, false, ["a", "b"], &[["1", "2"]]).unwrap(), "|===\n|1 |2\n|===\n");
+    }
+
+    #[test]
+    fn acts_models_are_typed() {
+        assert_eq!(write(OutputFormat::Acts, true, ["size", "flag"], &[["1", "true"], ["-20", "false"]]).unwrap(),
+                   "[System]\nName: CA\n\n[Parameter]\nsize (int): 1, -20\nflag (boolean): true, false\n\n[Test Set]\nsize,flag\n1,true\n-20,false\n");
+        assert!(write(OutputFormat::Acts, true, ["1st", "b"], &[["1", "2"]]).is_err());
+        assert!(write(OutputFormat::Acts, true, ["a", "b"], &[["x y", "2"]]).is_err());
+    }
+
+    #[test]
+    fn numbers_booleans_and_strings() {
+        assert_eq!(value_type(&values(&["0", "-1", "42"])), ValueType::Int);
+        assert_eq!(value_type(&values(&["true", "false"])), ValueType::Boolean);
+        assert_eq!(value_type(&values(&["false", "true"])), ValueType::Boolean);
+        // A single boolean value, numbers that are no integers and mixed values are strings
+        assert_eq!(value_type(&values(&["true"])), ValueType::Text);
+        assert_eq!(value_type(&values(&["1.5", "2"])), ValueType::Text);
+        assert_eq!(value_type(&values(&["1", "x"])), ValueType::Text);
+        assert_eq!(value_type(&values(&["", "1"])), ValueType::Text);
+        assert_eq!(value_type(&values(&["99999999999999999999"])), ValueType::Text);
+    }
+
+    #[test]
+    fn formats_are_parsed_case_insensitively() {
+        assert_eq!("NDJSON".parse::<OutputFormat>().unwrap(), OutputFormat::Ndjson);
+        assert_eq!("md".parse::<OutputFormat>().unwrap(), OutputFormat::Markdown);
+        assert_eq!("pytest".parse::<OutputFormat>().unwrap(), OutputFormat::Tests(TestFramework::Pytest));
+        assert!("xml".parse::<OutputFormat>().is_err());
+    }
+}
@@ -0,0 +1,413 @@
+use std::fs::File;
+use std::io::{Write, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use crate::{CASpec, read_ccmeta, read_archive_index, write_index, write_edited_index, edit_archive_tail, recover_archive, find_dominated, Ca2Error, Ca2Result};
+
+// Changes to an existing archive
+pub enum ArchiveEdit {
+    // Append CAs to the archive
+    Append,
+    // Replace the entry at this index by the (single) new CA
+    Replace(usize),
+    // Remove the entries at these indices
+    Remove(Vec<usize>),
+    // Leave the entries as they are (only useful for pruning)
+    Keep
+}
+
+// Which dominated CAs (see `CASpec::dominates`) an edit drops
+#[derive(Debug,Clone,Copy,PartialEq,Eq)]
+pub enum Pruning {
+    // Keep every CA
+    Off,
+    // Appended CAs replace the existing entries they improve on
+    Replace,
+    // Drop every dominated CA, both from the archive and from the new ones
+    All
+}
+
+// A compressed CA that is about to be added to an archive
+pub struct InputCA {
+    pub ca_spec: CASpec,
+    // The .cca file with the raw CA data
+    pub cca: PathBuf
+}
+
+// A CA that was dropped because another one dominates it.
+// CAs are named by their .cca file, or by their index in the original archive.
+pub struct Pruned {
+    pub name: String,
+    pub ca_spec: CASpec,
+    pub dominated_by: String,
+    pub dominating_spec: CASpec
+}
+
+// What creating or editing an archive did
+#[derive(Default)]
+pub struct PackReport {
+    // The CAs that were written, with their offsets
+    pub added: Vec<(u64, InputCA)>,
+    // Indices of replaced or removed entries in the original archive
+    pub removed: Vec<usize>,
+    pub pruned: Vec<Pruned>,
+    // Existing entries that an appended CA improves on, see `Pruning::Replace`
+    pub replaced: Vec<Pruned>,
+    // Number of CAs that were considered for pruning
+    pub considered: usize
+}
+
+// Pairs .ccmeta files with the .cca files of the same name and reads their specifications.
+// Files with other extensions and unpaired files are ignored.
+pub fn read_input_cas(paths: &[PathBuf]) -> Ca2Result<Vec<InputCA>> {
+    let mut cas = Vec::new();
+    for ccmeta in paths.iter().filter(|p| p.extension().is_some_and(|ext| ext == "ccmeta")) {
+        let cca = paths.iter().find(|p| {
+            p.extension().is_some_and(|ext| ext == "cca") && p.file_stem() == ccmeta.file_stem()
+        });
+        if let Some(cca) = cca {
+            if !cca.is_file() {
+                return Err(Ca2Error::InvalidArgument(format!("Compressed CA file {} does not exist", cca.display())));
+            }
+            cas.push(InputCA { ca_spec: read_ccmeta(ccmeta)?, cca: cca.clone() });
+        }
+    }
+    Ok(cas)
+}
+
+// Creates a new archive from compressed CAs, optionally starting with the contents of `prepend_file`
+// (e.g. a self-extracting executable). The CAs are sorted by their number of rows.
+pub fn create_archive(output_file: &Path, prepend_file: Option<&Path>, cas: Vec<InputCA>
+                      , prune_dominated: bool) -> Ca2Result<PackReport> {
+    let mut report = PackReport { considered: cas.len(), ..Default::default() };
+    let mut writer = BufWriter::new(File::create(output_file)?);
+
+    // Offset in the output file
+    let mut offset : u64 = 0;
+
+    // Copy the prepend file into the output
+    if let Some(prepend_file) = prepend_file {
+        offset += copy_file(&mut writer, prepend_file)?;
+    }
+
+    // Drop dominated CAs
+    let mut cas = cas;
+    if prune_dominated {
+        let names : Vec<String> = cas.iter().map(|ca| ca.cca.display().to_string()).collect();
+        let dominated = prune(&cas.iter().map(|ca| &ca.ca_spec).collect::<Vec<_>>(), &names, &mut report);
+        cas = cas.into_iter()
+            .enumerate()
+            .filter(|(i, _)| !dominated.contains(i))
+            .map(|(_, ca)| ca)
+            .collect();
+    }
+
+    // Reorder CAs
+    cas.sort_by_key(|ca| ca.ca_spec.n);
+
+    // Write the compressed CA files to the output
+    let mut entries = Vec::with_capacity(cas.len());
+    for ca in cas.into_iter() {
+        entries.push((offset, ca.ca_spec.clone()));
+        let length = copy_file(&mut writer, &ca.cca)?;
+        report.added.push((offset, ca));
+        offset += length;
+    }
+
+    write_index(&mut writer, &entries)?;
+    writer.flush()?;
+    Ok(report)
+}
+
+// Appends, replaces, removes or prunes entries of an existing archive.
+// The other entries keep their order: Appended CAs are added at the end of the index, a replacing CA takes
+// the place of the replaced entry, and removed entries are left out.
+// The archive is edited in place: The new CA data overwrites the old metadata block, and a new metadata
+// block is written after it, so only the index is rewritten. The old metadata block is kept in a journal
+// until the edit is complete, see `edit_archive_tail`. Data of replaced or removed entries stays in the archive
+// until it is compacted.
+pub fn edit_archive(archive: &Path, edit: &ArchiveEdit, cas: Vec<InputCA>, pruning: Pruning) -> Ca2Result<PackReport> {
+    recover_archive(archive)?;
+    let index = read_archive_index(archive)?;
+    let mut entries = index.entries;
+    let mut unused = index.unused;
+    let mut report = PackReport::default();
+    // Entries are reported by their index in the original archive
+    let mut names : Vec<String> = (0..entries.len()).map(|i| format!("entry {}", i)).collect();
+
+    // Check the operation before we touch anything
+    let mut indices = match edit {
+        ArchiveEdit::Replace(i) => vec![*i],
+        ArchiveEdit::Remove(is) => is.clone(),
+        _ => Vec::new()
+    };
+    if let Some(i) = indices.iter().find(|i| **i >= entries.len()) {
+        return Err(Ca2Error::InvalidArgument(format!("Archive {} has no entry with index {}", archive.display(), i)));
+    }
+    match edit {
+        ArchiveEdit::Append if cas.is_empty() => return Err(Ca2Error::InvalidArgument(String::from("No CAs to append."))),
+        ArchiveEdit::Replace(_) if cas.len() != 1 => return Err(Ca2Error::InvalidArgument(String::from(
+            "Replacing an entry requires exactly one pair of input files."
+        ))),
+        ArchiveEdit::Remove(_) | ArchiveEdit::Keep if !cas.is_empty() => return Err(Ca2Error::InvalidArgument(String::from(
+            "This operation does not take input files."
+        ))),
+        _ => {}
+    }
+
+    // The position of the replacing CA in the index
+    let mut replace_at = match edit {
+        ArchiveEdit::Replace(i) => Some(*i),
+        _ => None
+    };
+
+    // Replaced and removed entries become unused space
+    indices.sort_unstable_by(|a, b| b.cmp(a));
+    indices.dedup();
+    for i in indices {
+        unused.push(entries.remove(i).0);
+        names.remove(i);
+        report.removed.push(i);
+    }
+    report.removed.reverse();
+
+    // Drop dominated CAs, both from the archive and from the new ones.
+    // This replaces existing entries by better new CAs.
+    let mut cas = cas;
+    if pruning == Pruning::All {
+        report.considered = entries.len() + cas.len();
+        names.extend(cas.iter().map(|ca| ca.cca.display().to_string()));
+        let dominated = prune(&entries.iter().map(|(_, ca_spec)| ca_spec)
+                              .chain(cas.iter().map(|ca| &ca.ca_spec))
+                              .collect::<Vec<_>>(), &names, &mut report);
+        let num_entries = entries.len();
+        // Pruning the replacing CA would turn the replacement into a removal
+        let replacing = dominated.iter().position(|i| *i == num_entries).map(|p| &report.pruned[p]);
+        if let (ArchiveEdit::Replace(i), Some(pruned)) = (edit, replacing) {
+            return Err(Ca2Error::InvalidArgument(format!(
+                "Entry {} is not replaced, since the replacing CA {} is dominated by {} {}",
+                i, pruned.ca_spec, pruned.dominated_by, pruned.dominating_spec
+            )));
+        }
+        let (pruned, kept) : (Vec<_>, Vec<_>) = entries.into_iter()
+            .enumerate()
+            .partition(|(i, _)| dominated.contains(i));
+        // Pruned entries before the replaced one move it to the front
+        replace_at = replace_at.map(|at| at - pruned.iter().filter(|(i, _)| *i < at).count());
+        unused.extend(pruned.into_iter().map(|(_, (offset, _))| offset));
+        entries = kept.into_iter().map(|(_, entry)| entry).collect();
+        cas = cas.into_iter()
+            .enumerate()
+            .filter(|(i, _)| !dominated.contains(&(num_entries + i)))
+            .map(|(_, ca)| ca)
+            .collect();
+    } else if pruning == Pruning::Replace && matches!(edit, ArchiveEdit::Append) {
+        // Every existing entry that a new CA strictly improves on gives way to the smallest such CA
+        let mut i = 0;
+        while i < entries.len() {
+            let ca_spec = &entries[i].1;
+            let better = cas.iter()
+                .filter(|ca| ca.ca_spec.dominates(ca_spec) && !ca_spec.dominates(&ca.ca_spec))
+                .min_by_key(|ca| ca.ca_spec.n);
+            match better {
+                Some(ca) => {
+                    report.replaced.push(Pruned {
+                        name: names.remove(i),
+                        ca_spec: ca_spec.clone(),
+                        dominated_by: ca.cca.display().to_string(),
+                        dominating_spec: ca.ca_spec.clone()
+                    });
+                    unused.push(entries.remove(i).0);
+                },
+                None => i += 1
+            }
+        }
+    }
+
+    // Write the new compressed CA files where the metadata used to be
+    edit_archive_tail(archive, index.metadata_offset, |writer| {
+        let mut offset = index.metadata_offset;
+        for ca in cas.into_iter() {
+            match replace_at {
+                Some(at) => entries.insert(at, (offset, ca.ca_spec.clone())),
+                None => entries.push((offset, ca.ca_spec.clone()))
+            }
+            let length = copy_file(writer, &ca.cca)?;
+            report.added.push((offset, ca));
+            offset += length;
+        }
+        write_edited_index(writer, &entries, &unused)
+    })?;
+    Ok(report)
+}
+
+// Finds the CAs that are dominated by another one, records them in the report and returns their indices
+fn prune(ca_specs: &[&CASpec], names: &[String], report: &mut PackReport) -> Vec<usize> {
+    let dominated = find_dominated(ca_specs);
+    for (i, j) in dominated.iter() {
+        report.pruned.push(Pruned {
+            name: names[*i].clone(),
+            ca_spec: ca_specs[*i].clone(),
+            dominated_by: names[*j].clone(),
+            dominating_spec: ca_specs[*j].clone()
+        });
+    }
+    dominated.into_iter().map(|(i, _)| i).collect()
+}
+
+// Simply copies the contents of `input_file` into `writer`
+fn copy_file<W: Write>(writer: &mut W, input_file: &Path) -> std::io::Result<u64> {
+    let f = File::open(input_file)?;
+    let mut reader = BufReader::new(f);
+    std::io::copy(&mut reader, writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ca2_core::testing::TestDir;
+    use crate::{CoveringArray, Value, compact_archive, open_ca, read_index, write_ccmeta};
+
+    // Test CAs with their columns sorted descending by v, so that they are stored as they are
+    fn test_cas() -> Vec<CoveringArray> {
+        let latin_squares : Vec<Vec<Value>> = (0..9)
+            .map(|i| (i / 3, i % 3))
+            .map(|(a, b)| vec![a, b, (a + b) % 3, (a + 2 * b) % 3])
+            .collect();
+        vec![
+            CoveringArray::from_rows(2, vec![2, 2, 2], &[vec![0, 0, 0], vec![0, 1, 1], vec![1, 0, 1], vec![1, 1, 0]]).unwrap(),
+            CoveringArray::from_rows(2, vec![3, 3, 3, 3], &latin_squares).unwrap(),
+            CoveringArray::from_rows(1, vec![3, 2], &[vec![0, 0], vec![1, 1], vec![2, 0]]).unwrap(),
+            CoveringArray::from_rows(1, vec![5], &[vec![0], vec![1], vec![2], vec![3], vec![4]]).unwrap()
+        ]
+    }
+
+    // Writes the .cca and .ccmeta files of a CA
+    fn write_input(dir: &Path, name: &str, ca: &CoveringArray) -> InputCA {
+        let cca = dir.join(format!("{}.cca", name));
+        let ca_spec = ca.write_compressed(File::create(&cca).unwrap()).unwrap();
+        write_ccmeta(File::create(dir.join(format!("{}.ccmeta", name))).unwrap(), &ca_spec).unwrap();
+        InputCA { ca_spec, cca }
+    }
+
+    // Decodes all entries of an archive in the order of its index
+    fn decode_archive(archive: &Path) -> Vec<CoveringArray> {
+        read_index(&mut File::open(archive).unwrap()).unwrap().entries.iter()
+            .map(|(offset, ca_spec)| {
+                let compressed_ca = open_ca(File::open(archive).unwrap(), *offset, ca_spec).unwrap();
+                CoveringArray::from_compressed(ca_spec, compressed_ca).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn edits_keep_the_rows_of_the_other_entries() {
+        let dir = TestDir::new("pack-edits");
+        let archive = dir.join("archive.ca2");
+        let cas = test_cas();
+        let inputs : Vec<InputCA> = cas.iter().enumerate()
+            .map(|(i, ca)| write_input(&dir, &format!("ca{}", i), ca))
+            .collect();
+        let mut inputs = inputs.into_iter();
+        let (first, second, third, fourth) = (inputs.next().unwrap(), inputs.next().unwrap(), inputs.next().unwrap(), inputs.next().unwrap());
+
+        // New archives are sorted by N
+        create_archive(&archive, None, vec![second, first], false).unwrap();
+        assert_eq!(decode_archive(&archive), [cas[0].clone(), cas[1].clone()]);
+
+        let report = edit_archive(&archive, &ArchiveEdit::Append, vec![third], Pruning::Off).unwrap();
+        assert_eq!(report.added.len(), 1);
+        assert_eq!(decode_archive(&archive), [cas[0].clone(), cas[1].clone(), cas[2].clone()]);
+
+        // The new CA takes the place of the replaced one
+        let report = edit_archive(&archive, &ArchiveEdit::Replace(0), vec![fourth], Pruning::Off).unwrap();
+        assert_eq!(report.removed, [0]);
+        assert_eq!(decode_archive(&archive), [cas[3].clone(), cas[1].clone(), cas[2].clone()]);
+
+        let report = edit_archive(&archive, &ArchiveEdit::Remove(vec![1]), Vec::new(), Pruning::Off).unwrap();
+        assert_eq!(report.removed, [1]);
+        assert_eq!(decode_archive(&archive), [cas[3].clone(), cas[2].clone()]);
+        assert_eq!(read_index(&mut File::open(&archive).unwrap()).unwrap().unused.len(), 2);
+
+        let size = std::fs::metadata(&archive).unwrap().len();
+        let reclaimed = compact_archive(&archive).unwrap();
+        assert!(reclaimed > 0);
+        assert_eq!(std::fs::metadata(&archive).unwrap().len(), size - reclaimed);
+        assert!(read_index(&mut File::open(&archive).unwrap()).unwrap().unused.is_empty());
+        assert_eq!(decode_archive(&archive), [cas[3].clone(), cas[2].clone()]);
+
+    }
+
+    // A CA that the first test CA improves on, since it has an additional row
+    fn worse_ca() -> CoveringArray {
+        CoveringArray::from_rows(2, vec![2, 2, 2], &[vec![0, 0, 0], vec![0, 1, 1], vec![1, 0, 1], vec![1, 1, 0], vec![1, 1, 1]]).unwrap()
+    }
+
+    #[test]
+    fn appended_cas_replace_the_entries_they_improve_on() {
+        let dir = TestDir::new("pack-improve");
+        let archive = dir.join("archive.ca2");
+        let cas = test_cas();
+        let worse = worse_ca();
+        create_archive(&archive, None, vec![write_input(&dir, "worse", &worse), write_input(&dir, "ca2", &cas[2])], false).unwrap();
+        assert_eq!(decode_archive(&archive), [cas[2].clone(), worse.clone()]);
+
+        // Without replacing, both CAs are kept
+        let contents = std::fs::read(&archive).unwrap();
+        edit_archive(&archive, &ArchiveEdit::Append, vec![write_input(&dir, "ca0", &cas[0])], Pruning::Off).unwrap();
+        assert_eq!(decode_archive(&archive), [cas[2].clone(), worse.clone(), cas[0].clone()]);
+        std::fs::write(&archive, &contents).unwrap();
+
+        // A CA that is neither better nor worse is simply appended
+        let report = edit_archive(&archive, &ArchiveEdit::Append, vec![write_input(&dir, "ca3", &cas[3])], Pruning::Replace).unwrap();
+        assert!(report.replaced.is_empty());
+        let report = edit_archive(&archive, &ArchiveEdit::Append, vec![write_input(&dir, "ca0", &cas[0])], Pruning::Replace).unwrap();
+        assert_eq!(report.replaced.len(), 1);
+        assert_eq!(report.replaced[0].name, "entry 1");
+        assert_eq!(report.replaced[0].dominated_by, dir.join("ca0.cca").display().to_string());
+        assert_eq!(decode_archive(&archive), [cas[2].clone(), cas[3].clone(), cas[0].clone()]);
+        assert_eq!(read_index(&mut File::open(&archive).unwrap()).unwrap().unused.len(), 1);
+
+        // Appending the same CA again does not replace it
+        let report = edit_archive(&archive, &ArchiveEdit::Append, vec![write_input(&dir, "ca0", &cas[0])], Pruning::Replace).unwrap();
+        assert!(report.replaced.is_empty());
+
+    }
+
+    #[test]
+    fn dominated_cas_do_not_replace_entries_when_pruning() {
+        let dir = TestDir::new("pack-replace-dominated");
+        let archive = dir.join("archive.ca2");
+        let cas = test_cas();
+        create_archive(&archive, None, vec![write_input(&dir, "ca0", &cas[0]), write_input(&dir, "ca1", &cas[1])], false).unwrap();
+        let contents = std::fs::read(&archive).unwrap();
+
+        // Entry 0 dominates the replacing CA, which would be pruned right away
+        let worse = write_input(&dir, "worse", &worse_ca());
+        let e = edit_archive(&archive, &ArchiveEdit::Replace(1), vec![worse], Pruning::All).err().unwrap();
+        assert!(e.to_string().contains("Entry 1 is not replaced"), "{}", e);
+        assert_eq!(std::fs::read(&archive).unwrap(), contents);
+
+        // A replacing CA that is not dominated is kept
+        let report = edit_archive(&archive, &ArchiveEdit::Replace(1), vec![write_input(&dir, "ca2", &cas[2])], Pruning::All).unwrap();
+        assert_eq!(report.removed, [1]);
+        assert!(report.pruned.is_empty());
+        assert_eq!(decode_archive(&archive), [cas[0].clone(), cas[2].clone()]);
+
+    }
+
+    #[test]
+    fn invalid_edits_leave_the_archive_alone() {
+        let dir = TestDir::new("pack-invalid");
+        let archive = dir.join("archive.ca2");
+        let cas = test_cas();
+        create_archive(&archive, None, vec![write_input(&dir, "ca0", &cas[0])], false).unwrap();
+        let contents = std::fs::read(&archive).unwrap();
+
+        assert!(edit_archive(&archive, &ArchiveEdit::Remove(vec![1]), Vec::new(), Pruning::Off).is_err());
+        assert!(edit_archive(&archive, &ArchiveEdit::Append, Vec::new(), Pruning::Off).is_err());
+        let inputs = vec![write_input(&dir, "ca1", &cas[1]), write_input(&dir, "ca2", &cas[2])];
+        assert!(edit_archive(&archive, &ArchiveEdit::Replace(0), inputs, Pruning::Off).is_err());
+        assert_eq!(std::fs::read(&archive).unwrap(), contents);
+
+    }
+}
@@ -1,3 +0,0 @@
-
-pub mod acts;
-pub mod ctwedge;
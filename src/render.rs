@@ -0,0 +1,262 @@
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use crate::{CAWriter, Ca2Error, Ca2Result};
+
+// Name of the manifest that `TemplateRenderer` writes next to the rendered files
+pub const MANIFEST_FILE_NAME : &str = "manifest.csv";
+
+// Placeholder for the number of the row, counted from 1
+pub const ROW_PLACEHOLDER : &str = "row";
+
+#[derive(Debug,Clone,PartialEq,Eq)]
+enum Part {
+    Text(String),
+    Parameter(usize),
+    Row
+}
+
+// A text with placeholders like {{name}}, where name is a parameter name or `row`.
+// Whitespace around the name is ignored. `row` always stands for the row number, even if a parameter
+// has that name, so that file names stay distinct.
+#[derive(Debug,Clone)]
+pub struct Template {
+    parts: Vec<Part>
+}
+
+impl Template {
+    // Parses a template for the parameters `names`. Unknown placeholders are an error,
+    // so that typos do not go unnoticed.
+    pub fn parse(template: &str, names: &[String]) -> Ca2Result<Template> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find("{{") {
+            let end = rest[start..].find("}}")
+                .ok_or_else(|| Ca2Error::Parse(format!("Unclosed placeholder in template: {}", &rest[start..])))?;
+            if start > 0 {
+                parts.push(Part::Text(rest[..start].to_string()));
+            }
+            let name = rest[start + 2..start + end].trim();
+            match names.iter().position(|n| n == name) {
+                _ if name == ROW_PLACEHOLDER => parts.push(Part::Row),
+                Some(i) => parts.push(Part::Parameter(i)),
+                None => return Err(Ca2Error::Parse(format!(
+                    "Unknown placeholder {{{{{}}}}} in template, expected {} or one of the parameters {}", name, ROW_PLACEHOLDER, names.join(", ")
+                )))
+            }
+            rest = &rest[start + end + 2..];
+        }
+        if !rest.is_empty() {
+            parts.push(Part::Text(rest.to_string()));
+        }
+        Ok(Template { parts })
+    }
+
+    // Whether the template contains the row placeholder
+    pub fn uses_row(&self) -> bool {
+        self.parts.contains(&Part::Row)
+    }
+
+    // Fills in the values of a row, passed through `escape`, and its number `row`
+    pub fn render<F: Fn(&str) -> String>(&self, values: &[&str], row: &str, escape: F) -> String {
+        let mut out = String::new();
+        for part in self.parts.iter() {
+            match part {
+                Part::Text(text) => out.push_str(text),
+                Part::Parameter(i) => out.push_str(&escape(values[*i])),
+                Part::Row => out.push_str(row)
+            }
+        }
+        out
+    }
+}
+
+// Values in file names must not create directories or invalid paths
+fn file_name_value(value: &str) -> String {
+    match value {
+        "" | "." | ".." => String::from("_"),
+        _ => value.chars().map(|c| if c == '/' || c == '\\' || c.is_control() { '_' } else { c }).collect()
+    }
+}
+
+// The default pattern for the names of rendered files: the row number before the extension
+// of the template, e.g. config_{{row}}.yaml
+pub fn default_file_name(template_path: &Path) -> String {
+    let name = template_path.file_name().map(|name| name.to_string_lossy().into_owned()).unwrap_or_default();
+    match name.rsplit_once('.') {
+        Some((stem, extension)) if !stem.is_empty() => format!("{}_{{{{{}}}}}.{}", stem, ROW_PLACEHOLDER, extension),
+        _ => format!("{}_{{{{{}}}}}", name, ROW_PLACEHOLDER)
+    }
+}
+
+// Renders a template once per row into a directory, and lists the files in a manifest
+// (MANIFEST_FILE_NAME) with the row number, the file and the values of the row.
+// Existing files are never overwritten.
+pub struct TemplateRenderer {
+    template: String,
+    file_name: String,
+    dir: PathBuf,
+    // Row numbers in file names are padded to the width of this number
+    rows: u64,
+    parsed: Option<(Template, Template)>,
+    manifest: Option<csv::Writer<BufWriter<File>>>,
+    rendered: u64
+}
+
+impl TemplateRenderer {
+    // `file_name` is a template for the names of the rendered files, see `default_file_name`.
+    // `rows` is the (expected) number of rows of the CA.
+    pub fn new(template: String, file_name: String, dir: &Path, rows: u64) -> TemplateRenderer {
+        TemplateRenderer { template, file_name, dir: dir.to_path_buf(), rows, parsed: None, manifest: None, rendered: 0 }
+    }
+
+    // Number of files rendered so far
+    pub fn rendered(&self) -> u64 {
+        self.rendered
+    }
+}
+
+fn create_new(path: &Path) -> Ca2Result<BufWriter<File>> {
+    match File::options().write(true).create_new(true).open(path) {
+        Ok(f) => Ok(BufWriter::new(f)),
+        Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists =>
+            Err(Ca2Error::InvalidArgument(format!("Output file {} already exists.", path.display()))),
+        Err(e) => Err(e.into())
+    }
+}
+
+impl CAWriter for TemplateRenderer {
+    fn begin(&mut self, names: &[String], _: &[Arc<[String]>]) -> Ca2Result<()> {
+        let template = Template::parse(&self.template, names)?;
+        let file_name = Template::parse(&self.file_name, names)?;
+        if !file_name.uses_row() && self.rows > 1 {
+            return Err(Ca2Error::InvalidArgument(format!("The file names need a {{{{{}}}}} placeholder to be distinct", ROW_PLACEHOLDER)));
+        }
+        std::fs::create_dir_all(&self.dir)?;
+        let mut manifest = csv::Writer::from_writer(create_new(&self.dir.join(MANIFEST_FILE_NAME))?);
+        manifest.write_record(["row", "file"].into_iter().chain(names.iter().map(String::as_str)))?;
+        self.parsed = Some((template, file_name));
+        self.manifest = Some(manifest);
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &[&str]) -> Ca2Result<()> {
+        let (Some((template, file_name)), Some(manifest)) = (&self.parsed, &mut self.manifest) else {
+            return Err(Ca2Error::InvalidArgument(String::from("Rows written before the parameters")));
+        };
+        self.rendered += 1;
+        let number = self.rendered.to_string();
+        let padded = format!("{:0width$}", self.rendered, width = self.rows.max(1).to_string().len());
+        let name = file_name.render(row, &padded, file_name_value);
+        let mut writer = create_new(&self.dir.join(&name))?;
+        writer.write_all(template.render(row, &number, str::to_string).as_bytes())?;
+        writer.flush()?;
+        manifest.write_record([number.as_str(), name.as_str()].into_iter().chain(row.iter().copied()))?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Ca2Result<()> {
+        match self.manifest.as_mut() {
+            Some(manifest) => Ok(manifest.flush()?),
+            None => Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ca2_core::testing::TestDir;
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|name| name.to_string()).collect()
+    }
+
+    // Renders the rows with a renderer, which creates `dir`
+    fn render_rows(template: &str, file_name: &str, dir: &Path, names: &[String], rows: &[&[&str]]) -> Ca2Result<u64> {
+        let mut renderer = TemplateRenderer::new(template.to_string(), file_name.to_string(), dir, rows.len() as u64);
+        renderer.begin(names, &[])?;
+        for row in rows.iter() {
+            renderer.write_row(row)?;
+        }
+        renderer.finish()?;
+        Ok(renderer.rendered())
+    }
+
+    #[test]
+    fn placeholders_are_filled_in() {
+        let template = Template::parse("os={{ os }}, browser={{browser}} #{{row}}{{os}}", &names(&["os", "browser"])).unwrap();
+        assert!(template.uses_row());
+        assert_eq!(template.render(&["linux", "firefox"], "3", str::to_string), "os=linux, browser=firefox #3linux");
+        assert_eq!(template.render(&["a/b", "c"], "1", file_name_value), "os=a_b, browser=c #1a_b");
+
+        let template = Template::parse("no placeholders {", &[]).unwrap();
+        assert!(!template.uses_row());
+        assert_eq!(template.render(&[], "1", str::to_string), "no placeholders {");
+    }
+
+    #[test]
+    fn invalid_placeholders_are_rejected() {
+        assert!(Template::parse("{{os}", &names(&["os"])).is_err());
+        assert!(Template::parse("{{oss}}", &names(&["os"])).is_err());
+        assert!(Template::parse("{{}}", &names(&["os"])).is_err());
+    }
+
+    #[test]
+    fn row_is_the_row_number_even_for_a_parameter_named_row() {
+        let template = Template::parse("{{row}}: {{other}}", &names(&["row", "other"])).unwrap();
+        assert!(template.uses_row());
+        assert_eq!(template.render(&["value", "x"], "7", str::to_string), "7: x");
+
+        let test_dir = TestDir::new("render-row-parameter");
+        let dir = test_dir.join("out");
+        let rendered = render_rows("{{other}}", &default_file_name(Path::new("t.txt")), &dir, &names(&["row", "other"]), &[&["a", "x"], &["b", "y"]]).unwrap();
+        assert_eq!(rendered, 2);
+        assert_eq!(std::fs::read_to_string(dir.join("t_2.txt")).unwrap(), "y");
+    }
+
+    #[test]
+    fn file_names_stay_in_the_directory() {
+        assert_eq!(file_name_value("../etc/passwd"), ".._etc_passwd");
+        assert_eq!(file_name_value("a\\b\nc"), "a_b_c");
+        assert_eq!(file_name_value(".."), "_");
+        assert_eq!(file_name_value(""), "_");
+        assert_eq!(default_file_name(Path::new("/templates/config.yaml")), "config_{{row}}.yaml");
+        assert_eq!(default_file_name(Path::new("archive.tar.gz")), "archive.tar_{{row}}.gz");
+        assert_eq!(default_file_name(Path::new(".env")), ".env_{{row}}");
+        assert_eq!(default_file_name(Path::new("Makefile")), "Makefile_{{row}}");
+    }
+
+    #[test]
+    fn renders_files_and_manifest() {
+        let test_dir = TestDir::new("render-manifest");
+        let dir = test_dir.join("out");
+        let parameters = names(&["os", "path"]);
+        let rows : Vec<&[&str]> = (0..10).map(|i| if i % 2 == 0 { &["linux", "/usr"][..] } else { &["win, 10", "C:\\"][..] }).collect();
+        assert_eq!(render_rows("{{os}} {{path}}\n", "{{row}}-{{path}}.txt", &dir, &parameters, &rows).unwrap(), 10);
+
+        // Row numbers are padded to the width of the number of rows
+        assert_eq!(std::fs::read_to_string(dir.join("01-_usr.txt")).unwrap(), "linux /usr\n");
+        assert_eq!(std::fs::read_to_string(dir.join("10-C:_.txt")).unwrap(), "win, 10 C:\\\n");
+        let manifest = std::fs::read_to_string(dir.join(MANIFEST_FILE_NAME)).unwrap();
+        let lines : Vec<&str> = manifest.lines().collect();
+        assert_eq!(lines.len(), 11);
+        assert_eq!(lines[0], "row,file,os,path");
+        assert_eq!(lines[1], "1,01-_usr.txt,linux,/usr");
+        assert_eq!(lines[10], "10,10-C:_.txt,\"win, 10\",C:\\");
+
+        // Nothing is overwritten
+        assert!(render_rows("{{os}}", "{{row}}.txt", &dir, &parameters, &rows).is_err());
+    }
+
+    #[test]
+    fn file_names_need_the_row_number() {
+        let test_dir = TestDir::new("render-no-row");
+        let dir = test_dir.join("out");
+        assert!(render_rows("{{os}}", "{{os}}.txt", &dir, &names(&["os"]), &[&["a"], &["b"]]).is_err());
+        assert!(!dir.exists());
+        assert_eq!(render_rows("{{os}}", "{{os}}.txt", &dir, &names(&["os"]), &[&["a"]]).unwrap(), 1);
+        assert_eq!(std::fs::read_to_string(dir.join("a.txt")).unwrap(), "a");
+    }
+}
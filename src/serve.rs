@@ -0,0 +1,445 @@
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::sync_channel;
+use std::time::Duration;
+use serde_json::json;
+use crate::{CASpec, CAKind, CompressedCA, IndexCache, RequestedCA, OutputFormat, CAWriter, parse_model, open_ca
+            , generate_reorder_map, kind_name, Ca2Error, Ca2Result};
+
+// Limits for requests, so that clients cannot make the server allocate arbitrary amounts of memory
+// (or threads): Connections are handled by a fixed number of workers, and responses are not buffered.
+const MAX_LINE_LENGTH : u64 = 8 * 1024;
+const MAX_HEADERS : usize = 100;
+const MAX_BODY_LENGTH : usize = 16 * 1024 * 1024;
+// Connections that are handled at the same time, unless configured otherwise
+pub const DEFAULT_WORKERS : usize = 16;
+// Accepted connections that wait for a free worker. Further connections wait in the backlog of the listener.
+const MAX_PENDING_CONNECTIONS : usize = 64;
+// Connections that do not send anything for this long are closed
+const READ_TIMEOUT : Duration = Duration::from_secs(30);
+// Clients that do not read the response for this long are disconnected
+const WRITE_TIMEOUT : Duration = Duration::from_secs(30);
+// Number of rows that are decoded at once
+const DECODE_BATCH_ROWS : usize = 4096;
+
+// A small HTTP/JSON service that answers requests for CAs from archives that are loaded once.
+//
+// GET  /health            Status and number of loaded archives and CAs
+// GET  /archives          The CAs stored in every archive
+// POST /ca?t=2            Translates the best CA for the model in the body (ACTS, CTWedge or an event list)
+// GET  /ca?t=2&vs=3,2,2   Returns the best CA for parameters with these numbers of values
+//
+// /ca takes the optional query parameters format (default csv) and header=false. format=json returns t, N,
+// the parameters and the rows in one object; all other formats of `OutputFormat` are written like dca writes them.
+// CAs are sent while they are decoded, without a Content-Length; the end of the body is marked by closing the connection.
+pub struct Server {
+    cache: IndexCache
+}
+
+// A parsed HTTP request
+struct Request {
+    method: String,
+    path: String,
+    query: Vec<(String, String)>,
+    body: Vec<u8>
+}
+
+impl Request {
+    fn query_value(&self, name: &str) -> Option<&str> {
+        self.query.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str())
+    }
+}
+
+// A response that is ready to be sent
+struct Response {
+    status: u16,
+    content_type: &'static str,
+    body: Vec<u8>
+}
+
+impl Response {
+    fn json(status: u16, value: serde_json::Value) -> Response {
+        Response { status, content_type: "application/json", body: value.to_string().into_bytes() }
+    }
+
+    fn error(status: u16, message: &str) -> Response {
+        Response::json(status, json!({ "error": message }))
+    }
+}
+
+// What a request is answered with
+enum Reply {
+    Response(Response),
+    Ca(Box<CAReply>)
+}
+
+// A CA that is decoded and translated while it is sent
+struct CAReply {
+    requested_ca: RequestedCA,
+    compressed_ca: CompressedCA<Box<dyn Read>>,
+    reorder_map: Vec<usize>,
+    // None for the JSON object with t and N
+    format: Option<OutputFormat>,
+    header: bool,
+    t: u8,
+    n: u64
+}
+
+impl From<Ca2Error> for Response {
+    fn from(e: Ca2Error) -> Response {
+        match e {
+            Ca2Error::Parse(_) | Ca2Error::InvalidArgument(_) => Response::error(400, &e.to_string()),
+            _ => Response::error(500, &e.to_string())
+        }
+    }
+}
+
+impl Server {
+    // Reads the indices of all archives
+    pub fn new(archive_files: &[PathBuf]) -> Ca2Result<Server> {
+        let mut cache = IndexCache::default();
+        cache.update(archive_files)?;
+        Ok(Server { cache })
+    }
+
+    // Answers requests until the listener fails. Connections are handled by `workers` threads;
+    // while all of them are busy, new connections wait.
+    pub fn run(self, listener: TcpListener, workers: usize) -> Ca2Result<()> {
+        if workers == 0 {
+            return Err(Ca2Error::InvalidArgument(String::from("The server needs at least one worker")));
+        }
+        let server = Arc::new(self);
+        let (sender, receiver) = sync_channel::<TcpStream>(MAX_PENDING_CONNECTIONS);
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..workers {
+            let (server, receiver) = (Arc::clone(&server), Arc::clone(&receiver));
+            std::thread::spawn(move || loop {
+                // The lock is only held while waiting for the next connection
+                let stream = match receiver.lock().map(|receiver| receiver.recv()) {
+                    Ok(Ok(stream)) => stream,
+                    _ => return
+                };
+                // Errors while talking to a client only affect that client. So do panics, which would
+                // otherwise end the worker, until no worker is left to answer requests.
+                let _ = catch_unwind(AssertUnwindSafe(|| server.handle_connection(stream)));
+            });
+        }
+
+        for stream in listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                // The client might have given up already
+                Err(_) => continue
+            };
+            if sender.send(stream).is_err() {
+                return Err(Ca2Error::Io(std::io::Error::other("All workers of the server failed")));
+            }
+        }
+        Ok(())
+    }
+
+    fn handle_connection(&self, stream: TcpStream) -> Ca2Result<()> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        stream.set_write_timeout(Some(WRITE_TIMEOUT))?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let reply = match read_request(&mut reader) {
+            Ok(request) => catch_unwind(AssertUnwindSafe(|| self.handle(&request)))
+                .unwrap_or_else(|_| Reply::Response(Response::error(500, "Internal error"))),
+            Err(response) => Reply::Response(response)
+        };
+        let writer = BufWriter::new(stream);
+        match reply {
+            Reply::Response(response) => Ok(write_response(writer, &response)?),
+            Reply::Ca(ca) => ca.send(writer)
+        }
+    }
+
+    fn handle(&self, request: &Request) -> Reply {
+        match (request.method.as_str(), request.path.as_str()) {
+            ("GET", "/health") => Reply::Response(self.health()),
+            ("GET", "/archives") => Reply::Response(self.archives()),
+            ("GET" | "POST", "/ca") => self.ca(request).unwrap_or_else(|e| Reply::Response(e.into())),
+            (_, "/health" | "/archives" | "/ca") => Reply::Response(Response::error(405, "Method not allowed")),
+            _ => Reply::Response(Response::error(404, "Not found"))
+        }
+    }
+
+    fn health(&self) -> Response {
+        let archives = self.cache.archives();
+        Response::json(200, json!({
+            "status": "ok",
+            "archives": archives.len(),
+            "cas": archives.iter()
+                .map(|archive| archive.entries.iter().filter(|(_, ca_spec)| ca_spec.kind != CAKind::Unused).count())
+                .sum::<usize>()
+        }))
+    }
+
+    fn archives(&self) -> Response {
+        let archives : Vec<serde_json::Value> = self.cache.archives().iter().map(|archive| json!({
+            "path": archive.path.display().to_string(),
+            "cas": archive.entries.iter()
+                .filter(|(_, ca_spec)| ca_spec.kind != CAKind::Unused)
+                .map(|(offset, ca_spec)| spec_json(*offset, ca_spec))
+                .collect::<Vec<_>>()
+        })).collect();
+        Response::json(200, json!(archives))
+    }
+
+    fn ca(&self, request: &Request) -> Ca2Result<Reply> {
+        let strength = request.query_value("t")
+            .ok_or_else(|| Ca2Error::InvalidArgument(String::from("Missing query parameter t")))?
+            .parse::<u8>()
+            .map_err(|_| Ca2Error::InvalidArgument(String::from("Invalid strength")))?;
+        let requested_ca = match request.query_value("vs") {
+            Some(vs) => {
+                let vs = vs.split(',')
+                    .map(|v| v.trim().parse::<u16>())
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| Ca2Error::InvalidArgument(format!("Invalid numbers of values: {}", vs)))?;
+                RequestedCA::from_sizes(strength, &vs)?
+            },
+            None => {
+                let model = std::str::from_utf8(&request.body)
+                    .map_err(|_| Ca2Error::Parse(String::from("The model is not valid UTF-8")))?;
+                parse_model(model, strength)?
+            }
+        };
+        // The JSON response also states t and N, the other formats are written like dca writes them
+        let format = match request.query_value("format") {
+            Some("json") => None,
+            Some(format) => Some(format.parse::<OutputFormat>()?),
+            None => Some(OutputFormat::Csv)
+        };
+        let header = request.query_value("header") != Some("false");
+
+        let (file, offset, ca_spec) = match self.cache.find_best(&requested_ca.ca_spec, None) {
+            Some(best) => best,
+            None => return Ok(Reply::Response(Response::error(404, "No compatible CA found in archives")))
+        };
+        // Everything that can fail happens before the response is started
+        let reorder_map = generate_reorder_map(&requested_ca.parameter_sizes, ca_spec)?;
+        let compressed_ca = open_ca(File::open(file)?, offset, ca_spec)?;
+        Ok(Reply::Ca(Box::new(CAReply { requested_ca, compressed_ca, reorder_map, format, header, t: ca_spec.t, n: ca_spec.n })))
+    }
+}
+
+impl CAReply {
+    // Sends the rows while they are decoded, just like dca writes them.
+    // Errors after the status line can only be reported by closing the connection early.
+    fn send<W: Write>(mut self, mut writer: W) -> Ca2Result<()> {
+        let content_type = self.format.map_or("application/json", |format| format.content_type());
+        write_head(&mut writer, 200, content_type, None)?;
+        let mut ca_writer = match self.format {
+            Some(format) => format.writer(&mut writer, self.header),
+            None => Box::new(JsonObjectWriter { writer: &mut writer, t: self.t, n: self.n, rows: 0 })
+        };
+        ca_writer.begin(&self.requested_ca.parameter_names, &self.requested_ca.parameter_values)?;
+
+        let row_len = self.compressed_ca.row_len().max(1);
+        let mut buf = vec![0; DECODE_BATCH_ROWS * row_len];
+        let mut values = Vec::with_capacity(self.requested_ca.parameter_names.len());
+        loop {
+            let n = self.compressed_ca.read_rows(&mut buf)?;
+            if n == 0 {
+                return ca_writer.finish();
+            }
+            for row in buf[..n * self.compressed_ca.row_len()].chunks(row_len) {
+                values.clear();
+                values.extend(self.requested_ca.translate_indices(row, &self.reorder_map)
+                    .map(|(i, v)| self.requested_ca.parameter_values[i][v].as_str()));
+                ca_writer.write_row(&values)?;
+            }
+        }
+    }
+}
+
+// The JSON object of format=json. Its keys are sorted (n, parameters, rows, t) like the keys of the other
+// JSON responses, which serde_json writes sorted. Clients that read the response while it is streamed
+// rely on getting n and the parameters before the rows.
+struct JsonObjectWriter<W: Write> {
+    writer: W,
+    t: u8,
+    n: u64,
+    rows: u64
+}
+
+impl<W: Write> CAWriter for JsonObjectWriter<W> {
+    fn begin(&mut self, names: &[String], _: &[Arc<[String]>]) -> Ca2Result<()> {
+        write!(self.writer, "{{\"n\":{},\"parameters\":{},\"rows\":[", self.n, json!(names))?;
+        Ok(())
+    }
+
+    fn write_row(&mut self, row: &[&str]) -> Ca2Result<()> {
+        if self.rows > 0 {
+            self.writer.write_all(b",")?;
+        }
+        write!(self.writer, "{}", json!(row))?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Ca2Result<()> {
+        write!(self.writer, "],\"t\":{}}}", self.t)?;
+        Ok(self.writer.flush()?)
+    }
+}
+
+fn spec_json(offset: u64, ca_spec: &CASpec) -> serde_json::Value {
+    json!({
+        "offset": offset,
+        "version": u16::from(ca_spec.version),
+        "kind": kind_name(ca_spec.kind),
+        "n": ca_spec.n,
+        "t": ca_spec.t,
+        "vs": ca_spec.vs
+    })
+}
+
+// Reads a request. Returns the response to send instead, if the request is invalid.
+fn read_request<R: BufRead>(reader: &mut R) -> Result<Request, Response> {
+    let bad_request = || Response::error(400, "Bad request");
+    let request_line = read_line(reader)?.ok_or_else(bad_request)?;
+    let mut parts = request_line.split(' ');
+    let (method, target) = match (parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(target), Some(version)) if version.starts_with("HTTP/1.") => (method, target),
+        _ => return Err(bad_request())
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (percent_decode(name), percent_decode(value))
+        })
+        .collect();
+
+    let mut content_length = 0;
+    for i in 0.. {
+        let line = read_line(reader)?.ok_or_else(bad_request)?;
+        if line.is_empty() {
+            break;
+        }
+        if i == MAX_HEADERS {
+            return Err(Response::error(431, "Too many headers"));
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            if name.eq_ignore_ascii_case("content-length") {
+                content_length = value.trim().parse::<usize>().map_err(|_| bad_request())?;
+            } else if name.eq_ignore_ascii_case("transfer-encoding") {
+                return Err(Response::error(501, "Chunked requests are not supported"));
+            }
+        }
+    }
+    if content_length > MAX_BODY_LENGTH {
+        return Err(Response::error(413, "Request body too large"));
+    }
+    // The body grows as it arrives, so that a large Content-Length alone does not allocate anything
+    let mut body = Vec::new();
+    reader.take(content_length as u64).read_to_end(&mut body).map_err(|_| bad_request())?;
+    if body.len() != content_length {
+        return Err(bad_request());
+    }
+
+    Ok(Request { method: method.to_string(), path: path.to_string(), query, body })
+}
+
+// Reads a line without its line break. Returns None at the end of the stream.
+fn read_line<R: BufRead>(reader: &mut R) -> Result<Option<String>, Response> {
+    let mut line = Vec::new();
+    reader.take(MAX_LINE_LENGTH).read_until(b'\n', &mut line).map_err(|_| Response::error(400, "Bad request"))?;
+    if line.is_empty() {
+        return Ok(None);
+    }
+    if line.pop() != Some(b'\n') {
+        return Err(Response::error(431, "Line too long"));
+    }
+    if line.last() == Some(&b'\r') {
+        line.pop();
+    }
+    String::from_utf8(line).map(Some).map_err(|_| Response::error(400, "Bad request"))
+}
+
+fn percent_decode(s: &str) -> String {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = bytes.get(i + 1..i + 3)
+            .and_then(|h| std::str::from_utf8(h).ok())
+            .and_then(|h| u8::from_str_radix(h, 16).ok());
+        match (bytes[i], hex) {
+            (b'%', Some(b)) => {
+                out.push(b);
+                i += 3;
+            },
+            (b'+', _) => {
+                out.push(b' ');
+                i += 1;
+            },
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+fn write_response<W: Write>(mut writer: W, response: &Response) -> std::io::Result<()> {
+    write_head(&mut writer, response.status, response.content_type, Some(response.body.len()))?;
+    writer.write_all(&response.body)?;
+    writer.flush()
+}
+
+// Writes the status line and the headers. Without a length, the body ends when the connection is closed.
+fn write_head<W: Write>(writer: &mut W, status: u16, content_type: &str, content_length: Option<usize>) -> std::io::Result<()> {
+    let reason = match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        413 => "Payload Too Large",
+        431 => "Request Header Fields Too Large",
+        501 => "Not Implemented",
+        _ => "Internal Server Error"
+    };
+    write!(writer, "HTTP/1.1 {} {}\r\nContent-Type: {}\r\n", status, reason, content_type)?;
+    if let Some(content_length) = content_length {
+        write!(writer, "Content-Length: {}\r\n", content_length)?;
+    }
+    writer.write_all(b"Connection: close\r\n\r\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(request: &str) -> Result<Request, Response> {
+        read_request(&mut request.as_bytes())
+    }
+
+    #[test]
+    fn bodies_are_read_up_to_the_content_length() {
+        let request = parse("POST /ca?t=2&format=json HTTP/1.1\r\nContent-Length: 5\r\n\r\nmodelrest").ok().unwrap();
+        assert_eq!((request.method.as_str(), request.path.as_str()), ("POST", "/ca"));
+        assert_eq!(request.query_value("t"), Some("2"));
+        assert_eq!(request.body, b"model");
+        assert!(parse("GET /health HTTP/1.1\r\n\r\n").ok().unwrap().body.is_empty());
+    }
+
+    #[test]
+    fn invalid_bodies_are_rejected() {
+        assert_eq!(parse("POST /ca HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort").err().unwrap().status, 400);
+        let too_large = format!("POST /ca HTTP/1.1\r\nContent-Length: {}\r\n\r\n", MAX_BODY_LENGTH + 1);
+        assert_eq!(parse(&too_large).err().unwrap().status, 413);
+        // A large announced body does not need to arrive in full to be rejected as incomplete
+        let announced = format!("POST /ca HTTP/1.1\r\nContent-Length: {}\r\n\r\nx", MAX_BODY_LENGTH);
+        assert_eq!(parse(&announced).err().unwrap().status, 400);
+        assert_eq!(parse("POST /ca HTTP/1.1\r\nTransfer-Encoding: chunked\r\n\r\n").err().unwrap().status, 501);
+    }
+}
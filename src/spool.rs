@@ -0,0 +1,154 @@
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Write, Seek, SeekFrom, Cursor};
+use std::path::PathBuf;
+use std::sync::Arc;
+use crate::Ca2Result;
+
+// Default number of bytes a spool keeps in memory before it moves its data to a temporary file
+pub const SPOOL_DEFAULT_MEMORY_LIMIT : usize = 64 * 1024 * 1024;
+
+// Archives have to be read from the end and decoded from arbitrary offsets,
+// which requires seeking. A spool holds the complete contents of a non-seekable
+// source (like stdin) so that it can be used like an archive file.
+pub enum Spool {
+    // Small inputs are kept in memory...
+    Memory(Cursor<Arc<[u8]>>),
+    // ...larger ones in an anonymous temporary file
+    File(File)
+}
+
+impl Spool {
+    // Reads `reader` to the end. At most `memory_limit` bytes are kept in memory.
+    pub fn new<R: Read>(mut reader: R, memory_limit: usize) -> Ca2Result<Spool> {
+        let mut buf = Vec::new();
+        let read = (&mut reader).take(memory_limit as u64 + 1).read_to_end(&mut buf)?;
+        if read <= memory_limit {
+            return Ok(Spool::Memory(Cursor::new(buf.into())));
+        }
+
+        // Too large, continue in a temporary file
+        let mut f = temporary_file()?;
+        f.write_all(&buf)?;
+        drop(buf);
+        std::io::copy(&mut reader, &mut f)?;
+        f.seek(SeekFrom::Start(0))?;
+        Ok(Spool::File(f))
+    }
+
+    // Another handle to the same contents, e.g. for decoding several of the CAs they contain.
+    // Handles to a temporary file share their position, so they have to seek before reading.
+    pub fn try_clone(&self) -> Ca2Result<Spool> {
+        Ok(match self {
+            Spool::Memory(c) => Spool::Memory(Cursor::new(c.get_ref().clone())),
+            Spool::File(f) => Spool::File(f.try_clone()?)
+        })
+    }
+}
+
+impl Read for Spool {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self {
+            Spool::Memory(c) => c.read(buf),
+            Spool::File(f) => f.read(buf)
+        }
+    }
+}
+
+impl Seek for Spool {
+    fn seek(&mut self, pos: SeekFrom) -> std::io::Result<u64> {
+        match self {
+            Spool::Memory(c) => c.seek(pos),
+            Spool::File(f) => f.seek(pos)
+        }
+    }
+}
+
+// Creates a temporary file that is removed as soon as possible.
+// On Windows, an open file can not be removed, so the file is opened to be deleted when it is closed;
+// elsewhere, it is unlinked right away and disappears when closed.
+fn temporary_file() -> std::io::Result<File> {
+    let mut path = std::env::temp_dir();
+    let mut i = 0;
+    loop {
+        path.push(format!("ca2-spool-{}-{}", std::process::id(), i));
+        match temporary_options().open(&path) {
+            Ok(f) => {
+                #[cfg(not(windows))]
+                let _ = std::fs::remove_file(&path);
+                return Ok(f);
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                path.pop();
+                i += 1;
+            },
+            Err(e) => return Err(e)
+        }
+    }
+}
+
+#[cfg(windows)]
+fn temporary_options() -> OpenOptions {
+    use std::os::windows::fs::OpenOptionsExt;
+    const FILE_FLAG_DELETE_ON_CLOSE : u32 = 0x0400_0000;
+    let mut options = OpenOptions::new();
+    options.read(true).write(true).create_new(true).custom_flags(FILE_FLAG_DELETE_ON_CLOSE);
+    options
+}
+
+#[cfg(not(windows))]
+fn temporary_options() -> OpenOptions {
+    let mut options = OpenOptions::new();
+    options.read(true).write(true).create_new(true);
+    options
+}
+
+// The path that names stdin as an archive on the command line
+pub fn stdin_path() -> PathBuf {
+    PathBuf::from("-")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The temporary files of spools of this process that still exist
+    fn spool_files() -> Vec<PathBuf> {
+        let prefix = format!("ca2-spool-{}-", std::process::id());
+        std::fs::read_dir(std::env::temp_dir()).unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| path.file_name().and_then(|name| name.to_str()).is_some_and(|name| name.starts_with(&prefix)))
+            .collect()
+    }
+
+    #[test]
+    fn large_inputs_are_spooled_to_removed_files() {
+        let data : Vec<u8> = (0..10_000u32).map(|i| (i % 251) as u8).collect();
+        let mut small = Spool::new(data.as_slice(), data.len()).unwrap();
+        assert!(matches!(small, Spool::Memory(_)));
+        let mut large = Spool::new(data.as_slice(), 100).unwrap();
+        assert!(matches!(large, Spool::File(_)));
+
+        for spool in [&mut small, &mut large] {
+            let mut read = Vec::new();
+            spool.read_to_end(&mut read).unwrap();
+            assert_eq!(read, data);
+            spool.seek(SeekFrom::End(-3)).unwrap();
+            let mut tail = [0; 3];
+            spool.read_exact(&mut tail).unwrap();
+            assert_eq!(tail, data[data.len() - 3..]);
+        }
+
+        // Clones read the same contents
+        for spool in [&small, &large] {
+            let mut clone = spool.try_clone().unwrap();
+            clone.seek(SeekFrom::Start(0)).unwrap();
+            let mut read = Vec::new();
+            clone.read_to_end(&mut read).unwrap();
+            assert_eq!(read, data);
+        }
+
+        // The file is gone at the latest when the spool is dropped
+        drop(large);
+        assert_eq!(spool_files(), Vec::<PathBuf>::new());
+    }
+}
@@ -0,0 +1,105 @@
+use std::io::{Read, Seek};
+use crate::{CASpec, CAKind, CompressedCA, CoveringArray, Value, open_ca, Ca2Error, Ca2Result};
+
+// Number of rows that are decoded at once
+const DECODE_BATCH_ROWS : usize = 4096;
+
+// The result of checking a stored CA
+pub struct Verification {
+    pub rows: u64,
+    // Number of t-way interactions (or t-sequences of events) that no row covers, if coverage was checked
+    pub uncovered: Option<u64>
+}
+
+// Decodes the CA stored at `offset` of an archive and checks it against its specification:
+// The data has to hold N rows with values in range, and rows of sequence covering arrays
+// have to be permutations of the events. With `check_coverage`, the interactions that the rows
+// do not cover are counted as well, which takes considerably longer for large CAs.
+pub fn verify_ca<R: 'static + Read + Seek>(archive: R, offset: u64, ca_spec: &CASpec
+                                           , check_coverage: bool) -> Ca2Result<Verification> {
+    if ca_spec.t as usize > ca_spec.vs.len() {
+        return Err(Ca2Error::Format(format!("{}: strength exceeds the number of parameters", ca_spec)));
+    }
+    let compressed_ca = open_ca(archive, offset, ca_spec)?;
+    let (rows, uncovered) = match ca_spec.kind {
+        CAKind::Covering => {
+            let ca = CoveringArray::from_compressed(ca_spec, compressed_ca)?;
+            let uncovered = match check_coverage {
+                true => Some(ca.count_uncovered()?),
+                false => None
+            };
+            (ca.n() as u64, uncovered)
+        },
+        CAKind::Sequence => verify_sequence(ca_spec, compressed_ca, check_coverage)?,
+        CAKind::Unused => return Err(Ca2Error::InvalidArgument(String::from("Unused space holds no CA")))
+    };
+    if rows != ca_spec.n {
+        return Err(Ca2Error::Format(format!("{}: found {} rows instead of {}", ca_spec, rows, ca_spec.n)));
+    }
+    Ok(Verification { rows, uncovered })
+}
+
+// Checks that every row is a permutation of the events. Coverage is checked by recording,
+// for every row, the position of each event, and looking for a row with increasing positions
+// for every t-sequence of distinct events.
+fn verify_sequence<R: Read>(ca_spec: &CASpec, mut compressed_ca: CompressedCA<R>
+                            , check_coverage: bool) -> Ca2Result<(u64, Option<u64>)> {
+    let k = ca_spec.vs.len();
+    let mut buf = vec![0; DECODE_BATCH_ROWS * k.max(1)];
+    let mut positions : Vec<Vec<usize>> = Vec::new();
+    let mut rows = 0;
+    loop {
+        let n = compressed_ca.read_rows(&mut buf)?;
+        if n == 0 || k == 0 {
+            break;
+        }
+        for row in buf[..n * k].chunks(k) {
+            let mut position = vec![usize::MAX; k];
+            for (i, event) in row.iter().enumerate() {
+                match position.get_mut(*event as usize) {
+                    Some(p) if *p == usize::MAX => *p = i,
+                    _ => return Err(Ca2Error::Format(format!(
+                        "Row {} is not a permutation of {} events", rows, k
+                    )))
+                }
+            }
+            if check_coverage {
+                positions.push(position);
+            }
+            rows += 1;
+        }
+    }
+    if !check_coverage {
+        return Ok((rows, None));
+    }
+
+    // Enumerate all t-sequences of distinct events
+    let t = ca_spec.t as usize;
+    if t == 0 {
+        return Ok((rows, Some(0)));
+    }
+    let mut uncovered = 0;
+    let mut sequence : Vec<Value> = Vec::with_capacity(t);
+    let mut next : Value = 0;
+    loop {
+        // Extend the sequence with the smallest unused event from `next` on
+        if let Some(e) = (next..k as Value).find(|e| !sequence.contains(e)) {
+            sequence.push(e);
+            if sequence.len() < t {
+                next = 0;
+                continue;
+            }
+            let covered = positions.iter().any(|position| {
+                sequence.windows(2).all(|w| position[w[0] as usize] < position[w[1] as usize])
+            });
+            if !covered {
+                uncovered += 1;
+            }
+        }
+        // Backtrack to the next sequence
+        match sequence.pop() {
+            Some(e) => next = e + 1,
+            None => return Ok((rows, Some(uncovered)))
+        }
+    }
+}